@@ -4,12 +4,10 @@
 #![no_std]
 #![no_main]
 
-use core::panic::PanicInfo;
 use libfelix;
 
 #[no_mangle]
-#[link_section = ".start"]
-pub extern "C" fn _start() {
+fn main() -> i32 {
     let mut a: u32 = 0;
     let mut b: u8 = 0;
     loop {
@@ -25,10 +23,5 @@ pub extern "C" fn _start() {
         }
         a += 1;
     }
-    loop {}
-}
-
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+    0
 }
@@ -0,0 +1,16 @@
+//BUILD SCRIPT SHARED BY APPS
+//Each app points to it with the build key of its manifest
+
+use std::path::Path;
+
+fn main() {
+    let local_path = Path::new(env!("CARGO_MANIFEST_DIR"));
+    //tell rust to use linker script of libfelix
+    println!(
+        "cargo:rustc-link-arg-bins=--script={}",
+        local_path.join("../../lib/app.ld").display()
+    );
+
+    //user stack size, the loader reads it from the stack program header
+    println!("cargo:rustc-link-arg-bins=-zstack-size=0x4000");
+}
@@ -1,10 +0,0 @@
-use std::path::Path;
-
-fn main() {
-    let local_path = Path::new(env!("CARGO_MANIFEST_DIR"));
-    //tell rust to use custom linker script
-    println!(
-        "cargo:rustc-link-arg-bins=--script={}",
-        local_path.join("linker.ld").display()
-    );
-}
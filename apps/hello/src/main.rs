@@ -4,19 +4,61 @@
 #![no_std]
 #![no_main]
 
-use core::panic::PanicInfo;
 use libfelix;
+use libfelix::env;
+use libfelix::signal;
+use libfelix::signal::Handler;
+use libfelix::thread;
 
 #[no_mangle]
-#[link_section = ".start"]
-pub extern "C" fn _start() {
+fn main() -> i32 {
     let a = 0xFFFF;
     libfelix::println!("Hello world! {:X}", a);
 
-    loop {}
+    for (i, arg) in env::args().enumerate() {
+        libfelix::println!("Argument {}: {}", i, arg);
+    }
+
+    if let Some(home) = env::var("HOME") {
+        libfelix::println!("Home is {}", home);
+    }
+
+    //each thread has its own thread local storage
+    thread::set_local(1);
+
+    let handle = thread::spawn(move || {
+        thread::set_local(2);
+        libfelix::println!("Hello from thread {}!", thread::current_id());
+        a + thread::local()
+    });
+
+    match handle.and_then(|handle| handle.join()) {
+        Ok(value) => {
+            libfelix::println!("Thread returned {:X}", value);
+        }
+        Err(error) => {
+            libfelix::println!("Cannot run thread! {:?}", error);
+        }
+    }
+
+    libfelix::println!(
+        "Main thread {} local is {}",
+        thread::current_id(),
+        thread::local()
+    );
+
+    //a signal sent to itself is handled before kill returns
+    let pid = thread::current_id();
+    let sent = signal::set_handler(signal::TERM, Handler::Function(on_signal))
+        .and_then(|_| signal::kill(pid, signal::TERM));
+
+    if let Err(error) = sent {
+        libfelix::println!("Cannot send signal! {:?}", error);
+    }
+
+    0
 }
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+extern "C" fn on_signal(signal: u32) {
+    libfelix::println!("Got signal {}", signal::name(signal));
 }
@@ -0,0 +1,53 @@
+//CONSOLE DEVICE
+//Text terminal made of keyboard input and screen output
+
+use crate::drivers::device::{Device, DeviceKind, DEVICES};
+use crate::drivers::keyboard::KEYBOARD;
+use crate::filesystem::vfs::VfsError;
+use crate::syscalls::print::PRINTER;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut CONSOLE: Console = Console {};
+
+pub struct Console {}
+
+impl Device for Console {
+    //read typed chars
+    fn read(&mut self, _offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        unsafe { Ok(KEYBOARD.read_chars(buffer)) }
+    }
+
+    //print text on screen, invalid utf8 sequences are replaced by ?
+    //redirection is bypassed, otherwise redirecting to the console would write here again
+    fn write(&mut self, _offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        let mut rest = buffer;
+
+        while !rest.is_empty() {
+            unsafe {
+                match core::str::from_utf8(rest) {
+                    Ok(s) => {
+                        PRINTER.prints_screen(s);
+                        break;
+                    }
+                    Err(e) => {
+                        let (valid, invalid) = rest.split_at(e.valid_up_to());
+                        PRINTER.prints_screen(core::str::from_utf8_unchecked(valid));
+                        PRINTER.prints_screen("?");
+
+                        rest = &invalid[e.error_len().unwrap_or(invalid.len())..];
+                    }
+                }
+            }
+        }
+
+        Ok(buffer.len())
+    }
+}
+
+//register console device
+pub fn init() {
+    unsafe {
+        DEVICES.register("console", DeviceKind::Character, &mut CONSOLE);
+    }
+}
@@ -0,0 +1,111 @@
+//DEVICE REGISTRY
+//Drivers register their devices here with a name, devfs exposes them as files
+//Character devices are streams and ignore offsets, block devices are addressed by byte offset
+
+use crate::drivers::{console, disk, keyboard, mem};
+use crate::filesystem::vfs::VfsError;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut DEVICES: DeviceRegistry = DeviceRegistry {
+    devices: [NO_DEVICE; MAX_DEVICES],
+};
+
+const MAX_DEVICES: usize = 16;
+const DEVICE_NAME_SIZE: usize = 8;
+
+//init function of every driver, each one checks its hardware and registers its devices
+const DRIVERS: [fn(); 4] = [disk::init, keyboard::init, console::init, mem::init];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DeviceKind {
+    Character,
+    Block,
+}
+
+//interface implemented by drivers to expose a device
+pub trait Device {
+    //read from device starting at offset, returns how many bytes have been read
+    fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError>;
+
+    //write to device starting at offset, returns how many bytes have been written
+    fn write(&mut self, _offset: u32, _buffer: &[u8]) -> Result<usize, VfsError> {
+        Err(VfsError::ReadOnly)
+    }
+
+    //size in bytes, zero for character devices
+    fn size(&self) -> u32 {
+        0
+    }
+
+    //queue current task until device has data to read, returns false if reads never block
+    fn wait(&mut self) -> bool {
+        false
+    }
+}
+
+pub struct RegisteredDevice {
+    name: [u8; DEVICE_NAME_SIZE],
+    name_len: usize,
+    pub kind: DeviceKind,
+    pub device: &'static mut dyn Device,
+}
+
+impl RegisteredDevice {
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+}
+
+const NO_DEVICE: Option<RegisteredDevice> = None;
+
+pub struct DeviceRegistry {
+    devices: [Option<RegisteredDevice>; MAX_DEVICES],
+}
+
+impl DeviceRegistry {
+    //add a device with given name, names must be unique
+    pub fn register(&mut self, name: &str, kind: DeviceKind, device: &'static mut dyn Device) {
+        if name.is_empty() || name.len() > DEVICE_NAME_SIZE || self.find(name).is_some() {
+            libfelix::println!("[ERROR] Invalid device name {}!", name);
+            return;
+        }
+
+        let slot = match self.devices.iter().position(|d| d.is_none()) {
+            Some(slot) => slot,
+            None => {
+                libfelix::println!("[ERROR] Cannot register {}! Device table is full", name);
+                return;
+            }
+        };
+
+        let mut registered = RegisteredDevice {
+            name: [0; DEVICE_NAME_SIZE],
+            name_len: name.len(),
+            kind,
+            device,
+        };
+        registered.name[..name.len()].copy_from_slice(name.as_bytes());
+
+        self.devices[slot] = Some(registered);
+    }
+
+    //get device with given index, devices are never removed so indexes don't change
+    pub fn get(&mut self, index: usize) -> Option<&mut RegisteredDevice> {
+        self.devices.get_mut(index)?.as_mut()
+    }
+
+    //get index of device with given name
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|d| matches!(d, Some(device) if device.name() == name))
+    }
+}
+
+//init every driver
+pub fn init_drivers() {
+    for init in DRIVERS {
+        init();
+    }
+}
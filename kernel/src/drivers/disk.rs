@@ -1,11 +1,57 @@
 //DISK DRIVER
-//Driver for ATA disk supporting PIO MODE
+//Driver for ATA disks supporting PIO MODE read and write
+//Both master and slave drives of primary bus are supported, only master drive is behind block cache
 
+use crate::drivers::device::{Device, DeviceKind, DEVICES};
+use crate::filesystem::cache;
+use crate::filesystem::cache::CACHE;
+use crate::filesystem::vfs::VfsError;
 use core::arch::asm;
 
 //Warning! Mutable static here
 //TODO: Implement a mutex to get safe access to this
-pub static mut DISK: Disk = Disk { enabled: false };
+pub static mut DISK: Disk = Disk {
+    name: "hda",
+    drive: MASTER_DRIVE,
+    enabled: false,
+    cached: false,
+    status: 0,
+    sectors: 0,
+    model: [0; MODEL_SIZE],
+};
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut SECOND_DISK: Disk = Disk {
+    name: "hdb",
+    drive: SLAVE_DRIVE,
+    enabled: false,
+    cached: false,
+    status: 0,
+    sectors: 0,
+    model: [0; MODEL_SIZE],
+};
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut PARTITIONS: [Partition; MAX_PARTITIONS] = [NO_PARTITION; MAX_PARTITIONS];
+
+const SECTOR_SIZE: usize = 512;
+
+//primary partitions in master boot record
+pub const MAX_PARTITIONS: usize = 4;
+const PARTITION_TABLE_OFFSET: usize = 0x1be;
+const PARTITION_ENTRY_SIZE: usize = 16;
+const MBR_SIGNATURE: u16 = 0xaa55;
+
+//partition type used by linux filesystems
+pub const LINUX_PARTITION: u8 = 0x83;
+
+//partition type used by FAT16 volumes
+pub const FAT16_PARTITION: u8 = 0x06;
+
+//chs address written in partition entries, partitions are addressed only by lba
+const NO_CHS: [u8; 3] = [0xfe, 0xff, 0xff];
 
 //controller registers ports
 const DATA_REGISTER: u16 = 0x1f0;
@@ -18,22 +64,307 @@ const DRIVE_REGISTER: u16 = 0x1f6;
 //port used for both sending command and getting status
 const STATUS_COMMAND_REGISTER: u16 = 0x1f7;
 
+//drive register values selecting drive in lba mode
+const MASTER_DRIVE: u8 = 0xe0;
+const SLAVE_DRIVE: u8 = 0xf0;
+
+//lba mode bit of drive register, identify command is sent without it
+const LBA_MODE: u8 = 0b01000000;
+
 //read write command codes
 const READ_COMMAND: u8 = 0x20;
-//const WRITE_COMMAND: u8 = 0x30;
+const WRITE_COMMAND: u8 = 0x30;
+const FLUSH_COMMAND: u8 = 0xe7;
+const IDENTIFY_COMMAND: u8 = 0xec;
+
+//model string lenght in identify data
+const MODEL_SIZE: usize = 40;
 
 //status register bits
 const STATUS_BSY: u8 = 0b10000000;
 const STATUS_RDY: u8 = 0b01000000;
 //const STATUS_DFE: u8 = 0b00100000;
-//const STATUS_DRQ: u8 = 0b00001000;
+const STATUS_DRQ: u8 = 0b00001000;
 //const STATUS_ERR: u8 = 0b00000001;
 
 pub struct Disk {
+    pub name: &'static str,
+    drive: u8,
     pub enabled: bool,
+
+    //if true every access goes through block cache
+    cached: bool,
+
+    //status register read while checking the drive
+    pub status: u8,
+
+    //info returned by identify command
+    pub sectors: u32,
+    model: [u8; MODEL_SIZE],
+}
+
+//interface of devices that read and write fixed size blocks
+pub trait BlockDevice {
+    fn read_blocks(&mut self, target: *mut u8, lba: u64, count: u16);
+    fn write_blocks(&mut self, source: *const u8, lba: u64, count: u16);
+}
+
+impl BlockDevice for Disk {
+    fn read_blocks(&mut self, target: *mut u8, lba: u64, count: u16) {
+        Disk::read(self, target, lba, count);
+    }
+
+    fn write_blocks(&mut self, source: *const u8, lba: u64, count: u16) {
+        Disk::write(self, source, lba, count);
+    }
+}
+
+//partition of disk, lbas are absolute
+#[derive(Copy, Clone, Debug)]
+pub struct Partition {
+    pub start: u32,
+    pub sectors: u32,
+    pub kind: u8,
+}
+
+pub const NO_PARTITION: Partition = Partition {
+    start: 0,
+    sectors: 0,
+    kind: 0,
+};
+
+//whole disk as block device
+impl Device for Disk {
+    fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        read_bytes(self, 0, self.sectors, offset, buffer)
+    }
+
+    fn write(&mut self, offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        write_bytes(self, 0, self.sectors, offset, buffer)
+    }
+
+    fn size(&self) -> u32 {
+        self.sectors.saturating_mul(SECTOR_SIZE as u32)
+    }
+}
+
+//single partition as block device, offsets start from partition start
+//only partitions of first disk are registered as devices
+impl Device for Partition {
+    fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        unsafe { read_bytes(&DISK, self.start as u64, self.sectors, offset, buffer) }
+    }
+
+    fn write(&mut self, offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        unsafe { write_bytes(&DISK, self.start as u64, self.sectors, offset, buffer) }
+    }
+
+    fn size(&self) -> u32 {
+        self.sectors.saturating_mul(SECTOR_SIZE as u32)
+    }
+}
+
+//check how many bytes of an area of given sectors can be accessed from offset
+fn accessible(sectors: u32, offset: u32, len: usize) -> usize {
+    let size = sectors as u64 * SECTOR_SIZE as u64;
+
+    if offset as u64 >= size {
+        return 0;
+    }
+
+    len.min((size - offset as u64) as usize)
+}
+
+//read bytes of disk, offset is relative to first lba
+fn read_bytes(
+    disk: &Disk,
+    first_lba: u64,
+    sectors: u32,
+    offset: u32,
+    buffer: &mut [u8],
+) -> Result<usize, VfsError> {
+    let len = accessible(sectors, offset, buffer.len());
+    let mut sector = [0u8; SECTOR_SIZE];
+    let mut done = 0;
+
+    while done < len {
+        let position = offset as usize + done;
+        let in_sector = position % SECTOR_SIZE;
+        let count = (SECTOR_SIZE - in_sector).min(len - done);
+
+        disk.read_sectors(
+            sector.as_mut_ptr(),
+            first_lba + (position / SECTOR_SIZE) as u64,
+            1,
+        );
+        buffer[done..done + count].copy_from_slice(&sector[in_sector..in_sector + count]);
+
+        done += count;
+    }
+
+    Ok(len)
+}
+
+//write bytes to disk, partially written sectors are read first
+fn write_bytes(
+    disk: &Disk,
+    first_lba: u64,
+    sectors: u32,
+    offset: u32,
+    buffer: &[u8],
+) -> Result<usize, VfsError> {
+    let len = accessible(sectors, offset, buffer.len());
+    if len == 0 && !buffer.is_empty() {
+        return Err(VfsError::NoSpace);
+    }
+
+    let mut sector = [0u8; SECTOR_SIZE];
+    let mut done = 0;
+
+    while done < len {
+        let position = offset as usize + done;
+        let lba = first_lba + (position / SECTOR_SIZE) as u64;
+        let in_sector = position % SECTOR_SIZE;
+        let count = (SECTOR_SIZE - in_sector).min(len - done);
+
+        if count < SECTOR_SIZE {
+            disk.read_sectors(sector.as_mut_ptr(), lba, 1);
+        }
+
+        sector[in_sector..in_sector + count].copy_from_slice(&buffer[done..done + count]);
+        disk.write_sectors(sector.as_ptr(), lba, 1);
+
+        done += count;
+    }
+
+    Ok(len)
+}
+
+//check disks, then register them and partitions of first one
+pub fn init() {
+    unsafe {
+        DISK.check();
+
+        if DISK.enabled {
+            //every access to first disk goes through block cache
+            CACHE.acquire_mut().init(&mut DISK);
+            CACHE.free();
+            DISK.cached = true;
+
+            DEVICES.register(DISK.name, DeviceKind::Block, &mut DISK);
+
+            match DISK.read_partitions() {
+                Some(partitions) => PARTITIONS = partitions,
+                None => {
+                    libfelix::println!("[ERROR] Invalid partition table!");
+                }
+            }
+
+            for (i, partition) in PARTITIONS.iter_mut().enumerate() {
+                if partition.sectors == 0 {
+                    continue;
+                }
+
+                //partitions are numbered from one
+                let name = [b'h', b'd', b'a', b'1' + i as u8];
+                let name = core::str::from_utf8(&name).unwrap_or("hda?");
+
+                DEVICES.register(name, DeviceKind::Block, partition);
+            }
+        }
+
+        //second disk is optional and never cached
+        SECOND_DISK.check();
+
+        if SECOND_DISK.enabled {
+            DEVICES.register(SECOND_DISK.name, DeviceKind::Block, &mut SECOND_DISK);
+        }
+    }
 }
 
 impl Disk {
+    //read partition table from master boot record, none if disk has no valid one
+    pub fn read_partitions(&self) -> Option<[Partition; MAX_PARTITIONS]> {
+        let mut mbr = [0u8; SECTOR_SIZE];
+        self.read_sectors(mbr.as_mut_ptr(), 0, 1);
+
+        let signature = u16::from_le_bytes([mbr[510], mbr[511]]);
+        if signature != MBR_SIGNATURE {
+            return None;
+        }
+
+        let mut partitions = [NO_PARTITION; MAX_PARTITIONS];
+
+        for (i, partition) in partitions.iter_mut().enumerate() {
+            let entry = &mbr[PARTITION_TABLE_OFFSET + i * PARTITION_ENTRY_SIZE..];
+            let field = |offset: usize| {
+                u32::from_le_bytes([
+                    entry[offset],
+                    entry[offset + 1],
+                    entry[offset + 2],
+                    entry[offset + 3],
+                ])
+            };
+
+            *partition = Partition {
+                start: field(8),
+                sectors: field(12),
+                kind: entry[4],
+            };
+        }
+
+        Some(partitions)
+    }
+
+    //write partition table to master boot record, keeping boot code if disk has a valid one
+    pub fn write_partitions(&self, partitions: &[Partition; MAX_PARTITIONS]) {
+        let mut mbr = [0u8; SECTOR_SIZE];
+        self.read_sectors(mbr.as_mut_ptr(), 0, 1);
+
+        let signature = u16::from_le_bytes([mbr[510], mbr[511]]);
+        if signature != MBR_SIGNATURE {
+            mbr = [0; SECTOR_SIZE];
+        }
+
+        for (i, partition) in partitions.iter().enumerate() {
+            let offset = PARTITION_TABLE_OFFSET + i * PARTITION_ENTRY_SIZE;
+            let entry = &mut mbr[offset..offset + PARTITION_ENTRY_SIZE];
+            entry.fill(0);
+
+            if partition.sectors == 0 {
+                continue;
+            }
+
+            entry[1..4].copy_from_slice(&NO_CHS);
+            entry[4] = partition.kind;
+            entry[5..8].copy_from_slice(&NO_CHS);
+            entry[8..12].copy_from_slice(&partition.start.to_le_bytes());
+            entry[12..16].copy_from_slice(&partition.sectors.to_le_bytes());
+        }
+
+        mbr[510..512].copy_from_slice(&MBR_SIGNATURE.to_le_bytes());
+
+        self.write_sectors(mbr.as_ptr(), 0, 1);
+    }
+
+    //read sectors going through block cache if disk is behind it
+    pub fn read_sectors<T>(&self, target: *mut T, lba: u64, sectors: u16) {
+        if self.cached {
+            cache::read(target, lba, sectors);
+        } else {
+            self.read(target, lba, sectors);
+        }
+    }
+
+    //write sectors going through block cache if disk is behind it
+    pub fn write_sectors<T>(&self, source: *const T, lba: u64, sectors: u16) {
+        if self.cached {
+            cache::write(source, lba, sectors);
+        } else {
+            self.write(source, lba, sectors);
+        }
+    }
+
     //read multiple sectors from lba to specified target
     pub fn read<T>(&self, target: *mut T, lba: u64, sectors: u16) {
         if !self.enabled {
@@ -53,7 +384,7 @@ impl Disk {
             asm!("out dx, al", in("dx") LBA_LOW_REGISTER, in("al") lba as u8); //low 8 bits of lba
             asm!("out dx, al", in("dx") LBA_MID_REGISTER, in("al") (lba >> 8) as u8); //next 8 bits of lba
             asm!("out dx, al", in("dx") LBA_HIGH_REGISTER, in("al") (lba >> 16) as u8); //next 8 bits of lba
-            asm!("out dx, al", in("dx") DRIVE_REGISTER, in("al") (0xE0 | ((lba >> 24) & 0xF)) as u8); //0xe0 (master drive) ORed with highest 4 bits of lba
+            asm!("out dx, al", in("dx") DRIVE_REGISTER, in("al") (self.drive as u64 | ((lba >> 24) & 0xF)) as u8); //drive ORed with highest 4 bits of lba
 
             //send read command to port
             asm!("out dx, al", in("dx") STATUS_COMMAND_REGISTER, in("al") READ_COMMAND);
@@ -88,6 +419,63 @@ impl Disk {
         self.reset();
     }
 
+    //write multiple sectors from specified source to lba
+    pub fn write<T>(&self, source: *const T, lba: u64, sectors: u16) {
+        if !self.enabled {
+            libfelix::println!("[ERROR] Cannot write! Disk not enabled");
+            return;
+        }
+
+        //wait until not busy
+        while self.is_busy() {}
+
+        unsafe {
+            //disable ata interrupt
+            asm!("out dx, al", in("dx") 0x3f6, in("al") 0b00000010 as u8);
+
+            //setup registers
+            asm!("out dx, al", in("dx") SECTOR_COUNT_REGISTER, in("al") sectors as u8); //number of setcors to write
+            asm!("out dx, al", in("dx") LBA_LOW_REGISTER, in("al") lba as u8); //low 8 bits of lba
+            asm!("out dx, al", in("dx") LBA_MID_REGISTER, in("al") (lba >> 8) as u8); //next 8 bits of lba
+            asm!("out dx, al", in("dx") LBA_HIGH_REGISTER, in("al") (lba >> 16) as u8); //next 8 bits of lba
+            asm!("out dx, al", in("dx") DRIVE_REGISTER, in("al") (self.drive as u64 | ((lba >> 24) & 0xF)) as u8); //drive ORed with highest 4 bits of lba
+
+            //send write command to port
+            asm!("out dx, al", in("dx") STATUS_COMMAND_REGISTER, in("al") WRITE_COMMAND);
+        }
+
+        let mut sectors_left = sectors;
+        let mut source_pointer = source;
+        while sectors_left > 0 {
+            //wait until not busy
+            while self.is_busy() {}
+
+            //wait until drive asks for data
+            while !self.is_requesting() {}
+
+            //writes must be 16 bit wide, so loop for 512/2
+            for _i in 0..256 {
+                unsafe {
+                    let buffer = core::ptr::read_unaligned(source_pointer as *const u16);
+
+                    //write 16 bit to controller buffer
+                    asm!("out dx, ax", in("dx") DATA_REGISTER, in("ax") buffer);
+
+                    source_pointer = source_pointer.byte_add(2);
+                }
+            }
+            sectors_left -= 1;
+        }
+
+        //make sure data reaches the disk before returning
+        unsafe {
+            asm!("out dx, al", in("dx") STATUS_COMMAND_REGISTER, in("al") FLUSH_COMMAND);
+        }
+        while self.is_busy() {}
+
+        self.reset();
+    }
+
     //check if disk is busy
     pub fn is_busy(&self) -> bool {
         let status: u8;
@@ -110,25 +498,95 @@ impl Disk {
         (status & STATUS_RDY) != 0
     }
 
-    //check if ata drive is working
-    pub fn check(&mut self) {
+    //check if disk is waiting for data
+    pub fn is_requesting(&self) -> bool {
         let status: u8;
         unsafe {
             asm!("in al, dx", out("al") status, in("dx") STATUS_COMMAND_REGISTER);
         }
 
+        //if drq bit is not 0 return true
+        (status & STATUS_DRQ) != 0
+    }
+
+    //check if ata drive is working
+    pub fn check(&mut self) {
+        let mut status: u8 = 0;
+        unsafe {
+            //select drive, status is valid after reading it a few times
+            asm!("out dx, al", in("dx") DRIVE_REGISTER, in("al") self.drive);
+            for _i in 0..5 {
+                asm!("in al, dx", out("al") status, in("dx") STATUS_COMMAND_REGISTER);
+            }
+        }
+
+        self.status = status;
+
         if status != 0 && status != 0xff {
             self.enabled = true;
-            libfelix::println!("[!] ATA drive found! Status register: {:X}", status);
+            libfelix::println!(
+                "[!] ATA drive {} found! Status register: {:X}",
+                self.name,
+                status
+            );
+
+            self.identify();
         } else {
             self.enabled = false;
             libfelix::println!(
-                "[ERROR] ATA drive not working! Status register: {:X}",
+                "[ERROR] ATA drive {} not working! Status register: {:X}",
+                self.name,
                 status
             );
         }
     }
 
+    //ask drive its model and size
+    fn identify(&mut self) {
+        while self.is_busy() {}
+
+        unsafe {
+            asm!("out dx, al", in("dx") DRIVE_REGISTER, in("al") self.drive & !LBA_MODE); //selected drive
+            asm!("out dx, al", in("dx") SECTOR_COUNT_REGISTER, in("al") 0 as u8);
+            asm!("out dx, al", in("dx") LBA_LOW_REGISTER, in("al") 0 as u8);
+            asm!("out dx, al", in("dx") LBA_MID_REGISTER, in("al") 0 as u8);
+            asm!("out dx, al", in("dx") LBA_HIGH_REGISTER, in("al") 0 as u8);
+            asm!("out dx, al", in("dx") STATUS_COMMAND_REGISTER, in("al") IDENTIFY_COMMAND);
+        }
+
+        while self.is_busy() {}
+
+        //identify data is 256 words
+        let mut data = [0u16; 256];
+
+        if self.is_requesting() {
+            for word in data.iter_mut() {
+                unsafe {
+                    asm!("in ax, dx", out("ax") *word, in("dx") DATA_REGISTER);
+                }
+            }
+        }
+
+        //lba28 sector count is in words 60 and 61
+        self.sectors = data[60] as u32 | (data[61] as u32) << 16;
+
+        //model is in words 27 to 46, each word has its two chars swapped
+        for i in 0..MODEL_SIZE / 2 {
+            let word = data[27 + i];
+            self.model[i * 2] = (word >> 8) as u8;
+            self.model[i * 2 + 1] = word as u8;
+        }
+
+        self.reset();
+    }
+
+    //get model name reported by drive
+    pub fn model(&self) -> &str {
+        core::str::from_utf8(&self.model)
+            .unwrap_or("?")
+            .trim_end_matches(|c| c == ' ' || c == '\0')
+    }
+
     pub fn reset(&self) {
         unsafe {
             asm!("out dx, al", in("dx") 0x3f6, in("al") 0b00000110 as u8);
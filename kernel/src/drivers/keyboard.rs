@@ -1,20 +1,112 @@
 //KEYBOARD DRIVER
 //Interrupt handler for keyboard, reads scancode from keyboard controller then interprets it
 
+use crate::drivers::device::{Device, DeviceKind, DEVICES};
 use crate::drivers::pic::PICS;
+use crate::filesystem::vfs::VfsError;
+use crate::interrupts::idt;
+use crate::multitasking::wait_queue::WaitQueue;
 use crate::shell::shell::SHELL;
 use core::arch::asm;
 
 //Warning! Mutable static here
 //TODO: Implement a mutex to get safe access to this
-pub static mut KEYBOARD: Keyboard = Keyboard { lshift: false };
+pub static mut KEYBOARD: Keyboard = Keyboard {
+    lshift: false,
+    lctrl: false,
+    buffer: [0; KEY_BUFFER_SIZE],
+    head: 0,
+    len: 0,
+    readers: WaitQueue::new(),
+};
 
 pub const KEYBOARD_INT: u8 = 33;
 pub const KEYBAORD_CONTROLLER: u8 = 0x60;
 pub const CHAR_COUNT: usize = 36;
 
+//typed chars waiting to be read from device
+const KEY_BUFFER_SIZE: usize = 64;
+
 pub struct Keyboard {
     lshift: bool,
+    lctrl: bool,
+
+    //ring buffer of typed chars, oldest ones are lost when it's full
+    buffer: [u8; KEY_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+
+    //tasks waiting for chars to be typed
+    readers: WaitQueue,
+}
+
+impl Keyboard {
+    //add typed char to buffer, waking tasks waiting for it
+    fn push(&mut self, c: u8) {
+        let tail = (self.head + self.len) % KEY_BUFFER_SIZE;
+        self.buffer[tail] = c;
+
+        if self.len < KEY_BUFFER_SIZE {
+            self.len += 1;
+        } else {
+            self.head = (self.head + 1) % KEY_BUFFER_SIZE;
+        }
+
+        self.readers.wake_all();
+    }
+
+    //take oldest typed char from buffer
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let c = self.buffer[self.head];
+        self.head = (self.head + 1) % KEY_BUFFER_SIZE;
+        self.len -= 1;
+
+        Some(c)
+    }
+
+    //read typed chars to buffer, returns how many chars have been read
+    pub fn read_chars(&mut self, buffer: &mut [u8]) -> usize {
+        let mut read = 0;
+
+        while read < buffer.len() {
+            match self.pop() {
+                Some(c) => {
+                    buffer[read] = c;
+                    read += 1;
+                }
+                None => break,
+            }
+        }
+
+        read
+    }
+}
+
+//reading returns only chars already typed, zero if there are none
+//read syscall waits for chars instead, so tasks block until something is typed
+impl Device for Keyboard {
+    fn read(&mut self, _offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        Ok(self.read_chars(buffer))
+    }
+
+    fn wait(&mut self) -> bool {
+        if self.len == 0 {
+            self.readers.enqueue_current();
+        }
+
+        true
+    }
+}
+
+//register keyboard device
+pub fn init() {
+    unsafe {
+        DEVICES.register("kbd", DeviceKind::Character, &mut KEYBOARD);
+    }
 }
 
 //keyboard handler
@@ -51,6 +143,7 @@ pub extern "C" fn keyboard_handler(charset: [u8; CHAR_COUNT]) {
 
     //notify pics end of interrupt
     PICS.end_interrupt(KEYBOARD_INT);
+    idt::count_interrupt(KEYBOARD_INT);
 
     unsafe {
         match scancode {
@@ -66,14 +159,34 @@ pub extern "C" fn keyboard_handler(charset: [u8; CHAR_COUNT]) {
                 return;
             }
 
+            //press left control
+            0x1d => {
+                KEYBOARD.lctrl = true;
+                return;
+            }
+
+            //release left control
+            0x9d => {
+                KEYBOARD.lctrl = false;
+                return;
+            }
+
+            //ctrl+c
+            0x2e if KEYBOARD.lctrl => {
+                SHELL.interrupt();
+                return;
+            }
+
             //backspace
             0x0e => {
+                KEYBOARD.push(0x08);
                 SHELL.backspace();
                 return;
             }
 
             //enter
             0x1c => {
+                KEYBOARD.push(b'\n');
                 SHELL.enter();
                 return;
             }
@@ -87,6 +200,7 @@ pub extern "C" fn keyboard_handler(charset: [u8; CHAR_COUNT]) {
 
     if key != '\0' {
         unsafe {
+            KEYBOARD.push(key as u8);
             SHELL.add(key);
         }
     }
@@ -0,0 +1,99 @@
+//MEMORY DEVICES
+//Devices not backed by hardware: null discards writes, zero returns zeros and random returns pseudo random bytes
+
+use crate::drivers::device::{Device, DeviceKind, DEVICES};
+use crate::filesystem::vfs::VfsError;
+use crate::interrupts::timer::TICKS;
+use core::arch::asm;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut NULL: Null = Null {};
+pub static mut ZERO: Zero = Zero {};
+pub static mut RANDOM: Random = Random { state: 0 };
+
+pub struct Null {}
+
+pub struct Zero {}
+
+//xorshift generator, not suitable for cryptography
+pub struct Random {
+    state: u32,
+}
+
+impl Device for Null {
+    //always at end of file
+    fn read(&mut self, _offset: u32, _buffer: &mut [u8]) -> Result<usize, VfsError> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        Ok(buffer.len())
+    }
+}
+
+impl Device for Zero {
+    fn read(&mut self, _offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        buffer.fill(0);
+        Ok(buffer.len())
+    }
+
+    fn write(&mut self, _offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        Ok(buffer.len())
+    }
+}
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+
+        x
+    }
+}
+
+impl Device for Random {
+    fn read(&mut self, _offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        //mix in timer ticks, so reads at different times differ more
+        unsafe {
+            self.state ^= TICKS;
+        }
+
+        //state must never be zero
+        if self.state == 0 {
+            self.state = 0x2545_f491;
+        }
+
+        for chunk in buffer.chunks_mut(4) {
+            let bytes = self.next().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+
+        Ok(buffer.len())
+    }
+
+    //writing adds entropy
+    fn write(&mut self, _offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        for byte in buffer {
+            self.state = self.state.rotate_left(8) ^ *byte as u32;
+        }
+
+        Ok(buffer.len())
+    }
+}
+
+//seed generator with cpu timestamp counter and register devices
+pub fn init() {
+    unsafe {
+        let low: u32;
+        asm!("rdtsc", out("eax") low, out("edx") _);
+        RANDOM.state = low | 1;
+
+        DEVICES.register("null", DeviceKind::Character, &mut NULL);
+        DEVICES.register("zero", DeviceKind::Character, &mut ZERO);
+        DEVICES.register("random", DeviceKind::Character, &mut RANDOM);
+    }
+}
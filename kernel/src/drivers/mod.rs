@@ -1,3 +1,6 @@
+pub mod console;
+pub mod device;
 pub mod disk;
 pub mod keyboard;
+pub mod mem;
 pub mod pic;
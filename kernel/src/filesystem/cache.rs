@@ -0,0 +1,280 @@
+//BLOCK CACHE
+//Keeps recently used sectors in memory between filesystems and the block device
+//Writes only mark blocks as dirty, they reach the disk when evicted or when the cache is flushed
+
+use crate::drivers::disk::BlockDevice;
+use crate::interrupts::idt;
+use crate::multitasking::kthread;
+use libfelix::mutex::Mutex;
+
+pub static mut CACHE: Mutex<BlockCache> = Mutex::new(BlockCache {
+    device: None,
+    blocks: [EMPTY_BLOCK; CACHE_SIZE],
+    clock: 0,
+    hits: 0,
+    misses: 0,
+    read_ahead: [0; READ_AHEAD_SIZE * BLOCK_SIZE],
+});
+
+pub const BLOCK_SIZE: usize = 512;
+const CACHE_SIZE: usize = 64;
+
+//max sectors read with a single command when reading ahead
+const READ_AHEAD_SIZE: usize = 8;
+
+#[derive(Copy, Clone)]
+struct Block {
+    lba: u64,
+    data: [u8; BLOCK_SIZE],
+    valid: bool,
+    dirty: bool,
+    last_used: u32,
+}
+
+const EMPTY_BLOCK: Block = Block {
+    lba: 0,
+    data: [0; BLOCK_SIZE],
+    valid: false,
+    dirty: false,
+    last_used: 0,
+};
+
+pub struct BlockCache {
+    device: Option<&'static mut dyn BlockDevice>,
+    blocks: [Block; CACHE_SIZE],
+
+    //incremented on every access, used to find least recently used block
+    clock: u32,
+
+    pub hits: u32,
+    pub misses: u32,
+
+    read_ahead: [u8; READ_AHEAD_SIZE * BLOCK_SIZE],
+}
+
+impl BlockCache {
+    //set device behind cache, discarding every block
+    pub fn init(&mut self, device: &'static mut dyn BlockDevice) {
+        self.device = Some(device);
+        self.blocks = [EMPTY_BLOCK; CACHE_SIZE];
+    }
+
+    //read sectors to target
+    pub fn read<T>(&mut self, target: *mut T, lba: u64, sectors: u16) {
+        let target = target as *mut u8;
+
+        for i in 0..sectors as usize {
+            let index = self.get_block(lba + i as u64, true);
+
+            unsafe {
+                let source = self.blocks[index].data.as_ptr();
+                core::ptr::copy_nonoverlapping(source, target.add(i * BLOCK_SIZE), BLOCK_SIZE);
+            }
+        }
+    }
+
+    //write sectors from source, they are only marked as dirty
+    pub fn write<T>(&mut self, source: *const T, lba: u64, sectors: u16) {
+        let source = source as *const u8;
+
+        for i in 0..sectors as usize {
+            //no need to read a block that is going to be overwritten
+            let index = self.get_block(lba + i as u64, false);
+            let block = &mut self.blocks[index];
+
+            unsafe {
+                let source = source.add(i * BLOCK_SIZE);
+                core::ptr::copy_nonoverlapping(source, block.data.as_mut_ptr(), BLOCK_SIZE);
+            }
+
+            block.valid = true;
+            block.dirty = true;
+        }
+    }
+
+    //load sectors that are not cached yet, reading them with as few commands as possible
+    pub fn read_ahead(&mut self, lba: u64, sectors: u16) {
+        let mut current = lba;
+        let end = lba + sectors as u64;
+
+        while current < end {
+            if self.find(current).is_some() {
+                current += 1;
+                continue;
+            }
+
+            //count how many consecutive sectors are missing
+            let mut count = 1;
+            while count < READ_AHEAD_SIZE as u64
+                && current + count < end
+                && self.find(current + count).is_none()
+            {
+                count += 1;
+            }
+
+            if let Some(device) = self.device.as_mut() {
+                device.read_blocks(self.read_ahead.as_mut_ptr(), current, count as u16);
+            }
+
+            for i in 0..count as usize {
+                let index = self.get_block(current + i as u64, false);
+                let block = &mut self.blocks[index];
+
+                let data = &self.read_ahead[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE];
+                block.data.copy_from_slice(data);
+                block.valid = true;
+            }
+
+            current += count;
+        }
+    }
+
+    //write every dirty block to disk, returns how many blocks have been written
+    pub fn flush(&mut self) -> usize {
+        let mut count = 0;
+
+        for index in 0..CACHE_SIZE {
+            if self.blocks[index].valid && self.blocks[index].dirty {
+                self.write_back(index);
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    //get cached blocks count
+    pub fn used(&self) -> usize {
+        self.blocks.iter().filter(|b| b.valid).count()
+    }
+
+    //search block containing lba
+    fn find(&self, lba: u64) -> Option<usize> {
+        self.blocks.iter().position(|b| b.valid && b.lba == lba)
+    }
+
+    //get index of block containing lba, loading it if load is true and it's not cached
+    fn get_block(&mut self, lba: u64, load: bool) -> usize {
+        self.clock = self.clock.wrapping_add(1);
+
+        if let Some(index) = self.find(lba) {
+            self.hits += 1;
+            self.blocks[index].last_used = self.clock;
+            return index;
+        }
+
+        self.misses += 1;
+
+        let index = self.evict();
+        let block = &mut self.blocks[index];
+
+        block.lba = lba;
+        block.valid = load;
+        block.dirty = false;
+        block.last_used = self.clock;
+
+        if load {
+            if let Some(device) = self.device.as_mut() {
+                device.read_blocks(block.data.as_mut_ptr(), lba, 1);
+            }
+        }
+
+        index
+    }
+
+    //free least recently used block, writing it back if dirty
+    fn evict(&mut self) -> usize {
+        //prefer empty blocks
+        if let Some(index) = self.blocks.iter().position(|b| !b.valid) {
+            return index;
+        }
+
+        let mut index = 0;
+        for i in 1..CACHE_SIZE {
+            //distance from clock works even when clock wraps around
+            let age = self.clock.wrapping_sub(self.blocks[i].last_used);
+            if age > self.clock.wrapping_sub(self.blocks[index].last_used) {
+                index = i;
+            }
+        }
+
+        if self.blocks[index].dirty {
+            self.write_back(index);
+        }
+
+        self.blocks[index].valid = false;
+
+        index
+    }
+
+    //write block to disk and mark it clean
+    fn write_back(&mut self, index: usize) {
+        let block = &mut self.blocks[index];
+
+        if let Some(device) = self.device.as_mut() {
+            device.write_blocks(block.data.as_ptr(), block.lba, 1);
+        }
+
+        block.dirty = false;
+    }
+}
+
+//read sectors through the cache
+pub fn read<T>(target: *mut T, lba: u64, sectors: u16) {
+    unsafe {
+        CACHE.acquire_mut().read(target, lba, sectors);
+        CACHE.free();
+    }
+}
+
+//write sectors through the cache
+pub fn write<T>(source: *const T, lba: u64, sectors: u16) {
+    unsafe {
+        CACHE.acquire_mut().write(source, lba, sectors);
+        CACHE.free();
+    }
+}
+
+//load sectors in cache before they are needed
+pub fn read_ahead(lba: u64, sectors: u16) {
+    unsafe {
+        CACHE.acquire_mut().read_ahead(lba, sectors);
+        CACHE.free();
+    }
+}
+
+//write dirty blocks to disk, returns how many blocks have been written
+pub fn flush() -> usize {
+    unsafe {
+        let count = CACHE.acquire_mut().flush();
+        CACHE.free();
+
+        count
+    }
+}
+
+//pit default frequency is about 18.2 Hz, so dirty blocks are flushed every 5 seconds
+const FLUSH_INTERVAL: u32 = 91;
+
+//flush only if nobody is using the cache
+pub fn try_flush() {
+    unsafe {
+        if let Some(cache) = CACHE.try_acquire_mut() {
+            cache.flush();
+            CACHE.free();
+        }
+    }
+}
+
+//start kernel thread that periodically writes back dirty cached blocks
+pub fn start_flusher() {
+    //the handle is dropped, so the thread is detached
+    let _ = kthread::spawn("flusher", || loop {
+        kthread::sleep(FLUSH_INTERVAL);
+
+        //the shell may use the cache from the keyboard interrupt
+        let enabled = idt::disable_interrupts();
+        try_flush();
+        idt::restore_interrupts(enabled);
+    });
+}
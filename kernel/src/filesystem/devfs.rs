@@ -0,0 +1,117 @@
+//DEVFS
+//Exposes every device of the registry as a file, reads and writes are forwarded to drivers
+
+use crate::drivers::device::{DeviceKind, RegisteredDevice, DEVICES};
+use crate::filesystem::vfs::{DirEntry, FileSystem, Inode, InodeKind, VfsError};
+use libfelix::mutex::Mutex;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut DEVFS: Mutex<DevFs> = Mutex::new(DevFs {});
+
+//device inode id is its registry index plus one
+const ROOT_ID: u32 = 0;
+
+pub struct DevFs {}
+
+impl DevFs {
+    fn inode(&self, index: usize) -> Result<Inode, VfsError> {
+        let device = unsafe { DEVICES.get(index).ok_or(VfsError::NotFound)? };
+
+        Ok(Inode {
+            id: index as u32 + 1,
+            kind: InodeKind::File,
+            size: device.device.size(),
+        })
+    }
+
+    //character devices are streams, so file offsets are not forwarded to them
+    fn offset(device: &RegisteredDevice, offset: u32) -> u32 {
+        match device.kind {
+            DeviceKind::Character => 0,
+            DeviceKind::Block => offset,
+        }
+    }
+
+    fn index(inode: &Inode) -> Result<usize, VfsError> {
+        match inode.id {
+            ROOT_ID => Err(VfsError::IsDirectory),
+            id => Ok(id as usize - 1),
+        }
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&mut self) -> Inode {
+        Inode {
+            id: ROOT_ID,
+            kind: InodeKind::Directory,
+            size: 0,
+        }
+    }
+
+    fn lookup(&mut self, dir: &Inode, name: &str) -> Result<Inode, VfsError> {
+        if dir.id != ROOT_ID {
+            return Err(VfsError::NotDirectory);
+        }
+
+        let index = unsafe { DEVICES.find(name).ok_or(VfsError::NotFound)? };
+        self.inode(index)
+    }
+
+    fn read_dir(&mut self, dir: &Inode, index: usize) -> Result<Option<DirEntry>, VfsError> {
+        if dir.id != ROOT_ID {
+            return Err(VfsError::NotDirectory);
+        }
+
+        unsafe {
+            match DEVICES.get(index) {
+                Some(device) => Ok(Some(DirEntry::new(device.name(), self.inode(index)?))),
+                None => Ok(None),
+            }
+        }
+    }
+
+    fn read(&mut self, inode: &Inode, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let index = DevFs::index(inode)?;
+
+        unsafe {
+            let device = DEVICES.get(index).ok_or(VfsError::NotFound)?;
+            device.device.read(DevFs::offset(device, offset), buffer)
+        }
+    }
+
+    fn write(&mut self, inode: &Inode, offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        let index = DevFs::index(inode)?;
+
+        unsafe {
+            let device = DEVICES.get(index).ok_or(VfsError::NotFound)?;
+            device.device.write(DevFs::offset(device, offset), buffer)
+        }
+    }
+
+    //opening a device for writing truncates it, but devices can't change size
+    fn truncate(&mut self, _inode: &Inode) -> Result<(), VfsError> {
+        Ok(())
+    }
+
+    fn wait(&mut self, inode: &Inode) -> Result<bool, VfsError> {
+        let index = DevFs::index(inode)?;
+
+        unsafe {
+            let device = DEVICES.get(index).ok_or(VfsError::NotFound)?;
+            Ok(device.device.wait())
+        }
+    }
+
+    fn stat(&mut self, inode: &Inode) -> Result<Inode, VfsError> {
+        match inode.id {
+            ROOT_ID => Ok(self.root()),
+            _ => self.inode(DevFs::index(inode)?),
+        }
+    }
+}
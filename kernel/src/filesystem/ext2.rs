@@ -0,0 +1,603 @@
+//EXT2 FILESYSTEM IMPLEMENTATION
+//Read-only driver for ext2 volumes created by mke2fs
+//Inodes are found through block group descriptors, file blocks through direct and indirect pointers
+//Symbolic links are followed while looking up names, so the vfs only sees files and directories
+
+use crate::filesystem::cache;
+use crate::filesystem::vfs::{DirEntry, FileSystem, Inode, InodeKind, VfsError};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+use libfelix::mutex::Mutex;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut EXT2: Mutex<Ext2Driver> = Mutex::new(Ext2Driver {
+    start: 0,
+    superblock: NULL_SUPERBLOCK,
+    block_size: 0,
+    groups: 0,
+    inode_size: 0,
+    block: Vec::new(),
+    block_number: 0,
+});
+
+const SECTOR_SIZE: usize = 512;
+
+//superblock is always 1024 bytes after volume start
+const SUPERBLOCK_OFFSET: u32 = 1024;
+const EXT2_MAGIC: u16 = 0xef53;
+
+//block size is 1024 shifted left by this at most
+const MAX_LOG_BLOCK_SIZE: u32 = 6;
+
+const ROOT_INODE: u32 = 2;
+
+//block pointers in inode
+const DIRECT_BLOCKS: u32 = 12;
+const SINGLY_INDIRECT: usize = 12;
+const DOUBLY_INDIRECT: usize = 13;
+const TRIPLY_INDIRECT: usize = 14;
+
+//inode mode type bits
+const MODE_TYPE_MASK: u16 = 0xf000;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_SYMLINK: u16 = 0xa000;
+
+//symlinks shorter than this are stored inside block pointers
+const FAST_SYMLINK_SIZE: u32 = 60;
+
+//max symlinks followed while resolving a name
+const MAX_LINKS: usize = 8;
+
+//incompatible features that can be read by this driver
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE;
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct Superblock {
+    inodes_count: u32,
+    blocks_count: u32,
+    reserved_blocks_count: u32,
+    free_blocks_count: u32,
+    free_inodes_count: u32,
+    first_data_block: u32,
+    log_block_size: u32,
+    log_fragment_size: u32,
+    blocks_per_group: u32,
+    fragments_per_group: u32,
+    inodes_per_group: u32,
+    mount_time: u32,
+    write_time: u32,
+    mount_count: u16,
+    max_mount_count: u16,
+    magic: u16,
+    state: u16,
+    errors: u16,
+    minor_revision: u16,
+    last_check: u32,
+    check_interval: u32,
+    creator_os: u32,
+    revision: u32,
+    default_uid: u16,
+    default_gid: u16,
+
+    //only valid on revision 1 or later
+    first_inode: u32,
+    inode_size: u16,
+    block_group: u16,
+    feature_compat: u32,
+    feature_incompat: u32,
+    feature_ro_compat: u32,
+    uuid: [u8; 16],
+    volume_name: [u8; 16],
+
+    rest: [u8; 888],
+}
+
+const NULL_SUPERBLOCK: Superblock = Superblock {
+    inodes_count: 0,
+    blocks_count: 0,
+    reserved_blocks_count: 0,
+    free_blocks_count: 0,
+    free_inodes_count: 0,
+    first_data_block: 0,
+    log_block_size: 0,
+    log_fragment_size: 0,
+    blocks_per_group: 0,
+    fragments_per_group: 0,
+    inodes_per_group: 0,
+    mount_time: 0,
+    write_time: 0,
+    mount_count: 0,
+    max_mount_count: 0,
+    magic: 0,
+    state: 0,
+    errors: 0,
+    minor_revision: 0,
+    last_check: 0,
+    check_interval: 0,
+    creator_os: 0,
+    revision: 0,
+    default_uid: 0,
+    default_gid: 0,
+    first_inode: 0,
+    inode_size: 0,
+    block_group: 0,
+    feature_compat: 0,
+    feature_incompat: 0,
+    feature_ro_compat: 0,
+    uuid: [0; 16],
+    volume_name: [0; 16],
+    rest: [0; 888],
+};
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct GroupDescriptor {
+    block_bitmap: u32,
+    inode_bitmap: u32,
+    inode_table: u32,
+    free_blocks_count: u16,
+    free_inodes_count: u16,
+    used_dirs_count: u16,
+    pad: u16,
+    reserved: [u8; 12],
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct Ext2Inode {
+    mode: u16,
+    uid: u16,
+    size: u32,
+    access_time: u32,
+    creation_time: u32,
+    modification_time: u32,
+    deletion_time: u32,
+    gid: u16,
+    links_count: u16,
+    sectors: u32,
+    flags: u32,
+    os_specific: u32,
+    block: [u32; 15],
+    generation: u32,
+    file_acl: u32,
+    size_high: u32,
+    fragment_address: u32,
+    os_specific2: [u8; 12],
+}
+
+impl Ext2Inode {
+    fn kind(&self) -> u16 {
+        self.mode & MODE_TYPE_MASK
+    }
+
+    fn to_inode(&self, id: u32) -> Inode {
+        let kind = if self.kind() == MODE_DIRECTORY {
+            InodeKind::Directory
+        } else {
+            InodeKind::File
+        };
+
+        Inode {
+            id,
+            kind,
+            size: self.size,
+        }
+    }
+}
+
+//header of a directory entry, name follows it
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct DirEntryHeader {
+    inode: u32,
+    record_len: u16,
+    name_len: u8,
+    file_type: u8,
+}
+
+pub struct Ext2Driver {
+    //first sector of volume
+    start: u32,
+
+    superblock: Superblock,
+    block_size: u32,
+    groups: u32,
+    inode_size: u32,
+
+    //last read block and its number
+    block: Vec<u8>,
+    block_number: u32,
+}
+
+impl Ext2Driver {
+    //read superblock of volume starting at given sector and check it can be mounted
+    pub fn load(&mut self, start: u32) -> Result<(), VfsError> {
+        self.start = start;
+
+        let lba = start as u64 + (SUPERBLOCK_OFFSET as usize / SECTOR_SIZE) as u64;
+        let sectors = (mem::size_of::<Superblock>() / SECTOR_SIZE) as u16;
+        cache::read(&mut self.superblock as *mut Superblock, lba, sectors);
+
+        let superblock = self.superblock;
+
+        if superblock.magic != EXT2_MAGIC {
+            libfelix::println!("[ERROR] No ext2 filesystem found!");
+            return Err(VfsError::NotMounted);
+        }
+
+        let incompat = if superblock.revision >= 1 {
+            superblock.feature_incompat
+        } else {
+            0
+        };
+
+        if incompat & !SUPPORTED_INCOMPAT != 0 {
+            libfelix::println!("[ERROR] Unsupported ext2 features: {:X}", incompat);
+            return Err(VfsError::NotMounted);
+        }
+
+        let inode_size = if superblock.revision >= 1 {
+            superblock.inode_size as u32
+        } else {
+            128
+        };
+
+        //a malformed superblock would make later layout math divide by zero or overflow
+        //inodes must not cross sector boundaries to be read
+        if superblock.log_block_size > MAX_LOG_BLOCK_SIZE
+            || superblock.blocks_per_group == 0
+            || superblock.inodes_per_group == 0
+            || superblock.first_data_block >= superblock.blocks_count
+            || inode_size < 128
+            || !inode_size.is_power_of_two()
+            || inode_size as usize > SECTOR_SIZE
+        {
+            libfelix::println!("[ERROR] Invalid ext2 superblock!");
+            return Err(VfsError::NotMounted);
+        }
+
+        self.block_size = 1024 << superblock.log_block_size;
+        self.inode_size = inode_size;
+        self.groups = (superblock.blocks_count - superblock.first_data_block - 1)
+            / superblock.blocks_per_group
+            + 1;
+
+        self.block = vec![0; self.block_size as usize];
+        self.block_number = 0;
+
+        let blocks = superblock.blocks_count;
+        let inodes = superblock.inodes_count;
+
+        libfelix::print!(
+            "[!] ext2 filesystem found! Blocks: {}, block size: {}, inodes: {}, label: ",
+            blocks,
+            self.block_size,
+            inodes
+        );
+        for c in superblock.volume_name.iter().take_while(|c| **c != 0) {
+            libfelix::print!("{}", *c as char);
+        }
+        libfelix::println!();
+
+        Ok(())
+    }
+
+    //lba of first sector of a block
+    fn block_lba(&self, block: u32) -> u64 {
+        self.start as u64 + block as u64 * (self.block_size as u64 / SECTOR_SIZE as u64)
+    }
+
+    //read a whole block to block buffer, if not already there
+    fn load_block(&mut self, block: u32) {
+        if self.block_number == block {
+            return;
+        }
+
+        let sectors = (self.block_size as usize / SECTOR_SIZE) as u16;
+        cache::read(self.block.as_mut_ptr(), self.block_lba(block), sectors);
+
+        self.block_number = block;
+    }
+
+    //read a structure at given byte offset of a block, reading only the sector containing it
+    //structures must not cross sector boundaries
+    fn read_struct<T>(&self, block: u32, offset: u32) -> T {
+        let mut sector = [0u8; SECTOR_SIZE];
+
+        let lba = self.block_lba(block) + (offset as usize / SECTOR_SIZE) as u64;
+        cache::read(sector.as_mut_ptr(), lba, 1);
+
+        let in_sector = offset as usize % SECTOR_SIZE;
+        unsafe { core::ptr::read_unaligned(sector.as_ptr().add(in_sector) as *const T) }
+    }
+
+    fn group_descriptor(&self, group: u32) -> GroupDescriptor {
+        //descriptor table starts in the block after the superblock
+        let table = self.superblock.first_data_block + 1;
+        let offset = group * mem::size_of::<GroupDescriptor>() as u32;
+
+        self.read_struct(table + offset / self.block_size, offset % self.block_size)
+    }
+
+    //read inode with given number, numbers start from one
+    fn read_inode(&self, number: u32) -> Result<Ext2Inode, VfsError> {
+        if number == 0 || number > self.superblock.inodes_count {
+            return Err(VfsError::NotFound);
+        }
+
+        let group = (number - 1) / self.superblock.inodes_per_group;
+        if group >= self.groups {
+            return Err(VfsError::NotFound);
+        }
+
+        let index = (number - 1) % self.superblock.inodes_per_group;
+
+        let table = self.group_descriptor(group).inode_table;
+        let offset = index * self.inode_size;
+
+        Ok(self.read_struct(table + offset / self.block_size, offset % self.block_size))
+    }
+
+    //get pointer number index of an indirect block
+    fn indirect(&self, block: u32, index: u32) -> u32 {
+        if block == 0 {
+            return 0;
+        }
+
+        self.read_struct(block, index * 4)
+    }
+
+    //convert block index inside a file to block number on volume, zero for holes
+    fn file_block(&self, inode: &Ext2Inode, index: u32) -> u32 {
+        let pointers = self.block_size / 4;
+        let block = inode.block;
+
+        if index < DIRECT_BLOCKS {
+            return block[index as usize];
+        }
+
+        let index = index - DIRECT_BLOCKS;
+        if index < pointers {
+            return self.indirect(block[SINGLY_INDIRECT], index);
+        }
+
+        let index = index - pointers;
+        if index < pointers * pointers {
+            let singly = self.indirect(block[DOUBLY_INDIRECT], index / pointers);
+            return self.indirect(singly, index % pointers);
+        }
+
+        let index = index - pointers * pointers;
+        let doubly = self.indirect(block[TRIPLY_INDIRECT], index / (pointers * pointers));
+        let singly = self.indirect(doubly, (index / pointers) % pointers);
+        self.indirect(singly, index % pointers)
+    }
+
+    //read file content from offset to buffer
+    fn read_data(&mut self, inode: &Ext2Inode, offset: u32, buffer: &mut [u8]) -> usize {
+        if offset >= inode.size {
+            return 0;
+        }
+
+        let len = buffer.len().min((inode.size - offset) as usize);
+        let mut read = 0;
+
+        while read < len {
+            let position = offset + read as u32;
+            let in_block = (position % self.block_size) as usize;
+            let count = (self.block_size as usize - in_block).min(len - read);
+
+            let block = self.file_block(inode, position / self.block_size);
+
+            //holes read as zeros
+            if block == 0 {
+                buffer[read..read + count].fill(0);
+            } else {
+                self.load_block(block);
+                buffer[read..read + count].copy_from_slice(&self.block[in_block..in_block + count]);
+            }
+
+            read += count;
+        }
+
+        read
+    }
+
+    //iterate entries of a directory calling f on each one until it returns true
+    //returns the inode number of the entry that stopped the iteration
+    fn find_entry(&mut self, dir: &Ext2Inode, mut f: impl FnMut(&str, u32) -> bool) -> Option<u32> {
+        let blocks = (dir.size + self.block_size - 1) / self.block_size;
+
+        for index in 0..blocks {
+            let block = self.file_block(dir, index);
+            if block == 0 {
+                continue;
+            }
+
+            self.load_block(block);
+
+            let mut offset = 0;
+            while offset + mem::size_of::<DirEntryHeader>() <= self.block_size as usize {
+                let header = unsafe {
+                    core::ptr::read_unaligned(
+                        self.block.as_ptr().add(offset) as *const DirEntryHeader
+                    )
+                };
+
+                if header.record_len == 0 {
+                    break;
+                }
+
+                let name_start = offset + mem::size_of::<DirEntryHeader>();
+                let name_end = (name_start + header.name_len as usize).min(self.block.len());
+                offset += header.record_len as usize;
+
+                //unused entries have inode zero
+                if header.inode == 0 {
+                    continue;
+                }
+
+                let name = core::str::from_utf8(&self.block[name_start..name_end]).unwrap_or("");
+
+                if f(name, header.inode) {
+                    return Some(header.inode);
+                }
+            }
+        }
+
+        None
+    }
+
+    //read target path of a symbolic link
+    fn link_target(&mut self, link: &Ext2Inode, target: &mut [u8; 256]) -> usize {
+        let len = (link.size as usize).min(target.len());
+
+        //short targets are stored in place of block pointers
+        if link.size < FAST_SYMLINK_SIZE && link.sectors == 0 {
+            let blocks = link.block;
+            for (i, pointer) in blocks.iter().enumerate() {
+                for (j, byte) in pointer.to_le_bytes().iter().enumerate() {
+                    if i * 4 + j < len {
+                        target[i * 4 + j] = *byte;
+                    }
+                }
+            }
+            return len;
+        }
+
+        self.read_data(link, 0, &mut target[..len])
+    }
+
+    //search name in directory, following symbolic links
+    fn resolve(&mut self, dir: u32, name: &str, depth: usize) -> Result<u32, VfsError> {
+        let dir_inode = self.read_inode(dir)?;
+        if dir_inode.kind() != MODE_DIRECTORY {
+            return Err(VfsError::NotDirectory);
+        }
+
+        let number = self
+            .find_entry(&dir_inode, |n, _| n == name)
+            .ok_or(VfsError::NotFound)?;
+
+        let inode = self.read_inode(number)?;
+        if inode.kind() != MODE_SYMLINK {
+            return Ok(number);
+        }
+
+        if depth >= MAX_LINKS {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let mut target = [0u8; 256];
+        let len = self.link_target(&inode, &mut target);
+        let target = core::str::from_utf8(&target[..len]).map_err(|_| VfsError::InvalidPath)?;
+
+        //absolute targets start from root of this volume
+        let mut current = if target.starts_with('/') {
+            ROOT_INODE
+        } else {
+            dir
+        };
+
+        //dot dot entries are real entries on ext2, so they can be looked up too
+        for component in target.split('/') {
+            if component.is_empty() || component == "." {
+                continue;
+            }
+            current = self.resolve(current, component, depth + 1)?;
+        }
+
+        Ok(current)
+    }
+}
+
+impl FileSystem for Ext2Driver {
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+
+    fn root(&mut self) -> Inode {
+        Inode {
+            id: ROOT_INODE,
+            kind: InodeKind::Directory,
+            size: 0,
+        }
+    }
+
+    fn lookup(&mut self, dir: &Inode, name: &str) -> Result<Inode, VfsError> {
+        let number = self.resolve(dir.id, name, 0)?;
+        Ok(self.read_inode(number)?.to_inode(number))
+    }
+
+    fn read_dir(&mut self, dir: &Inode, index: usize) -> Result<Option<DirEntry>, VfsError> {
+        let dir_inode = self.read_inode(dir.id)?;
+        if dir_inode.kind() != MODE_DIRECTORY {
+            return Err(VfsError::NotDirectory);
+        }
+
+        let mut count = 0;
+        let mut name = [0u8; 255];
+        let mut name_len = 0;
+
+        //skip dot entries, the vfs resolves them
+        let number = self.find_entry(&dir_inode, |n, _| {
+            if n == "." || n == ".." {
+                return false;
+            }
+
+            if count == index {
+                name_len = n.len().min(name.len());
+                name[..name_len].copy_from_slice(&n.as_bytes()[..name_len]);
+                return true;
+            }
+
+            count += 1;
+            false
+        });
+
+        let number = match number {
+            Some(number) => number,
+            None => return Ok(None),
+        };
+
+        let name = core::str::from_utf8(&name[..name_len]).unwrap_or("?");
+
+        //show what links point to, broken links are shown as they are
+        let inode = match self.lookup(dir, name) {
+            Ok(inode) => inode,
+            Err(_) => self.read_inode(number)?.to_inode(number),
+        };
+
+        Ok(Some(DirEntry::new(name, inode)))
+    }
+
+    fn read(&mut self, inode: &Inode, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let ext2_inode = self.read_inode(inode.id)?;
+
+        match ext2_inode.kind() {
+            MODE_DIRECTORY => Err(VfsError::IsDirectory),
+
+            //broken links are seen as files containing their target
+            MODE_SYMLINK => {
+                let mut target = [0u8; 256];
+                let len = self.link_target(&ext2_inode, &mut target);
+
+                let offset = (offset as usize).min(len);
+                let count = buffer.len().min(len - offset);
+                buffer[..count].copy_from_slice(&target[offset..offset + count]);
+
+                Ok(count)
+            }
+
+            _ => Ok(self.read_data(&ext2_inode, offset, buffer)),
+        }
+    }
+
+    fn stat(&mut self, inode: &Inode) -> Result<Inode, VfsError> {
+        Ok(self.read_inode(inode.id)?.to_inode(inode.id))
+    }
+}
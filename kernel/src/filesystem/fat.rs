@@ -1,22 +1,106 @@
-//FAT16 FILESYSTEM IMPLEMENTATION
+//FAT FILESYSTEM IMPLEMENTATION
+//Supports FAT12, FAT16 and FAT32, the type is detected from the cluster count of the volume
 
-use crate::drivers::disk::DISK;
+use crate::filesystem::cache;
+use crate::filesystem::vfs::{DirEntry, FileSystem, Inode, InodeKind, VfsError};
 use core::mem;
+use core::ptr;
 use libfelix::mutex::Mutex;
 
+pub mod fsck;
+pub mod mkfs;
+
 pub static mut FAT: Mutex<FatDriver> = Mutex::new(FatDriver {
     header: NULL_HEADER,
-    entries: [NULL_ENTRY; ENTRY_COUNT],
-    table: [0; FAT_SIZE],
-    buffer: [0; 2048],
+    info: NULL_INFO,
+    fat_type: FatType::Fat16,
+    fat_start: 0,
+    fat_size: 0,
+    root_start: 0,
+    root_sectors: 0,
+    data_start: 0,
+    cluster_count: 0,
+    table: [0; TABLE_SIZE],
+    table_sector: u32::MAX,
+    next_free: 2,
+    sector: [0; SECTOR_SIZE],
+    sector_lba: u64::MAX,
 });
 
-const ENTRY_COUNT: usize = 512;
-const FAT_START: u16 = 36864;
+const FAT_START: u32 = 36864;
+
+//the table is not loaded entirely, only a window of two sectors is kept in memory
+//two sectors are needed because a FAT12 entry can be split between two sectors
+const SECTOR_SIZE: usize = 512;
+const TABLE_SIZE: usize = SECTOR_SIZE * 2;
+
+//cluster count limits used to detect fat type
+const FAT12_MAX_CLUSTERS: u32 = 4085;
+const FAT16_MAX_CLUSTERS: u32 = 65525;
+
+//entry attributes
+const ATTRIBUTE_VOLUME_ID: u8 = 0x08;
+const ATTRIBUTE_ARCHIVE: u8 = 0x20;
+const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+const ATTRIBUTE_LONG_NAME: u8 = 0x0f;
+
+//first name byte of a deleted entry
+const DELETED_ENTRY: u8 = 0xe5;
+
+//chars not allowed in short names
+const INVALID_NAME_CHARS: &[u8] = b" \"*+,./:;<=>?[\\]|";
 
-const FAT_SIZE: usize = 256;
+//root directory has no entry, so it uses an id that no entry can have
+const ROOT_ID: u32 = 0;
+
+//how many entries fit in a sector
+const ENTRIES_PER_SECTOR: u32 = (SECTOR_SIZE / mem::size_of::<Entry>()) as u32;
+
+//FAT32 cluster numbers are 28 bits, the highest 4 bits are reserved
+const FAT32_CLUSTER_MASK: u32 = 0x0fff_ffff;
+
+//fs info sector signatures
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_TRAIL_SIGNATURE: u32 = 0xaa55_0000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    //first cluster value that marks the end of a chain
+    pub fn end_of_chain(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xff8,
+            FatType::Fat16 => 0xfff8,
+            FatType::Fat32 => 0x0fff_fff8,
+        }
+    }
+
+    //value written in the table to mark the end of a chain
+    pub fn end_marker(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xfff,
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fff_ffff,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FatType::Fat12 => "FAT12",
+            FatType::Fat16 => "FAT16",
+            FatType::Fat32 => "FAT32",
+        }
+    }
+}
 
-//FAT16 header
+//FAT header (boot sector), the bios parameter block is common to all fat types
+//the extended boot record that follows it has a different layout on FAT32
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
 pub struct Header {
@@ -37,14 +121,8 @@ pub struct Header {
     hidden_sectors: u32,
     large_sector_count: u32,
 
-    //extended boot record
-    drive_number: u8,
-    reserved: u8,
-    signature: u8,
-    volume_id: u32,
-    volume_label: [u8; 11],
-    system_id: [u8; 8],
-    zero: [u8; 460], //needed to make struct 512 bytes big
+    //extended boot record, read it with ebr() or ebr32()
+    extended: [u8; 476], //needed to make struct 512 bytes big
 }
 
 static NULL_HEADER: Header = Header {
@@ -64,15 +142,123 @@ static NULL_HEADER: Header = Header {
     hidden_sectors: 0,
     large_sector_count: 0,
 
-    drive_number: 0,
-    reserved: 0,
-    signature: 0,
-    volume_id: 0,
-    volume_label: [0; 11],
-    system_id: [0; 8],
-    zero: [0; 460],
+    extended: [0; 476],
+};
+
+//FAT12 and FAT16 extended boot record
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct ExtendedBootRecord {
+    pub drive_number: u8,
+    reserved: u8,
+    pub signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub system_id: [u8; 8],
+}
+
+//FAT32 extended boot record
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct ExtendedBootRecord32 {
+    pub sectors_per_fat: u32,
+    pub flags: u16,
+    pub version: u16,
+    pub root_cluster: u32,
+    pub fs_info_sector: u16,
+    pub backup_boot_sector: u16,
+    reserved: [u8; 12],
+    pub drive_number: u8,
+    reserved_nt: u8,
+    pub signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub system_id: [u8; 8],
+}
+
+impl Header {
+    pub fn bytes_per_sector(&self) -> u32 {
+        self.bytes_per_sector as u32
+    }
+
+    pub fn sectors_per_cluster(&self) -> u32 {
+        self.sectors_per_cluster as u32
+    }
+
+    pub fn reserved_sectors(&self) -> u32 {
+        self.reserved_sectors as u32
+    }
+
+    pub fn fat_count(&self) -> u32 {
+        self.fat_count as u32
+    }
+
+    pub fn dir_entries_count(&self) -> u32 {
+        self.dir_entries_count as u32
+    }
+
+    //small sector count is zero if the volume needs more than 16 bits
+    pub fn total_sectors(&self) -> u32 {
+        if self.total_sectors != 0 {
+            self.total_sectors as u32
+        } else {
+            self.large_sector_count
+        }
+    }
+
+    //sectors per fat in the bpb is zero on FAT32, the real value is in the extended boot record
+    pub fn sectors_per_fat(&self) -> u32 {
+        if self.sectors_per_fat != 0 {
+            self.sectors_per_fat as u32
+        } else {
+            self.ebr32().sectors_per_fat
+        }
+    }
+
+    pub fn ebr(&self) -> ExtendedBootRecord {
+        unsafe { ptr::read_unaligned(self.extended.as_ptr() as *const ExtendedBootRecord) }
+    }
+
+    pub fn ebr32(&self) -> ExtendedBootRecord32 {
+        unsafe { ptr::read_unaligned(self.extended.as_ptr() as *const ExtendedBootRecord32) }
+    }
+}
+
+//FAT32 fs info sector, contains hints about free clusters
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct FsInfo {
+    lead_signature: u32,
+    reserved: [u8; 480],
+    struct_signature: u32,
+    pub free_count: u32,
+    pub next_free: u32,
+    reserved_end: [u8; 12],
+    trail_signature: u32,
+}
+
+static NULL_INFO: FsInfo = FsInfo {
+    lead_signature: 0,
+    reserved: [0; 480],
+    struct_signature: 0,
+    free_count: 0,
+    next_free: 0,
+    reserved_end: [0; 12],
+    trail_signature: 0,
 };
 
+impl FsInfo {
+    pub fn is_valid(&self) -> bool {
+        let lead = self.lead_signature;
+        let structure = self.struct_signature;
+        let trail = self.trail_signature;
+
+        lead == FS_INFO_LEAD_SIGNATURE
+            && structure == FS_INFO_STRUCT_SIGNATURE
+            && trail == FS_INFO_TRAIL_SIGNATURE
+    }
+}
+
 //FAT file entry struct
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -91,182 +277,921 @@ pub struct Entry {
     size: u32,
 }
 
-static NULL_ENTRY: Entry = Entry {
-    name: [0; 11],
-    attributes: 0,
-    reserved: 0,
-    created_time_tenths: 0,
-    created_time: 0,
-    created_date: 0,
-    accessed_date: 0,
-    first_cluster_high: 0,
-    modified_time: 0,
-    modified_date: 0,
-    first_cluster_low: 0,
-    size: 0,
-};
+impl Entry {
+    //create an empty entry with given short name
+    fn new(name: [u8; 11], attributes: u8) -> Self {
+        Entry {
+            name,
+            attributes,
+            reserved: 0,
+            created_time_tenths: 0,
+            created_time: 0,
+            created_date: 0,
+            accessed_date: 0,
+            first_cluster_high: 0,
+            modified_time: 0,
+            modified_date: 0,
+            first_cluster_low: 0,
+            size: 0,
+        }
+    }
+
+    //convert a dotted name to 8.3 format, uppercasing it
+    fn short_name(name: &str) -> Result<[u8; 11], VfsError> {
+        let (base, extension) = match name.rfind('.') {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => (name, ""),
+        };
+
+        if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let mut short = [b' '; 11];
+
+        for (i, c) in base.bytes().enumerate() {
+            short[i] = c;
+        }
+        for (i, c) in extension.bytes().enumerate() {
+            short[8 + i] = c;
+        }
+
+        for c in short.iter_mut() {
+            if *c < 0x20 || *c > 0x7e || (*c != b' ' && INVALID_NAME_CHARS.contains(c)) {
+                return Err(VfsError::InvalidPath);
+            }
+            *c = c.to_ascii_uppercase();
+        }
+
+        Ok(short)
+    }
+
+    //high part of cluster is zero on FAT12 and FAT16, since their clusters fit in 16 bits
+    fn set_first_cluster(&mut self, cluster: u32) {
+        self.first_cluster_low = cluster as u16;
+        self.first_cluster_high = (cluster >> 16) as u16;
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attributes & ATTRIBUTE_DIRECTORY != 0
+    }
+
+    //free, deleted, long name and volume label entries are not files
+    pub fn is_valid(&self) -> bool {
+        self.name[0] != 0
+            && self.name[0] != DELETED_ENTRY
+            && self.attributes & ATTRIBUTE_LONG_NAME != ATTRIBUTE_LONG_NAME
+            && self.attributes & ATTRIBUTE_VOLUME_ID == 0
+    }
+
+    //convert 8.3 name to a dotted name, returns name lenght
+    pub fn file_name(&self, target: &mut [u8; 12]) -> usize {
+        let mut len = 0;
+
+        for i in 0..8 {
+            if self.name[i] != b' ' {
+                len = i + 1;
+            }
+            target[i] = self.name[i];
+        }
+
+        //0x05 is used in place of 0xe5 as first char, since 0xe5 marks deleted entries
+        if target[0] == 0x05 {
+            target[0] = DELETED_ENTRY;
+        }
+
+        if self.name[8] != b' ' {
+            target[len] = b'.';
+            len += 1;
+
+            for i in 8..11 {
+                if self.name[i] != b' ' {
+                    target[len] = self.name[i];
+                    len += 1;
+                }
+            }
+        }
+
+        len
+    }
+
+    //inode of an entry is identified by entry position
+    fn to_inode(&self, position: u32) -> Inode {
+        Inode {
+            id: position,
+            kind: if self.is_directory() {
+                InodeKind::Directory
+            } else {
+                InodeKind::File
+            },
+            size: self.size,
+        }
+    }
+}
 
 #[derive(Copy, Clone)]
 pub struct FatDriver {
     pub header: Header,
-    pub entries: [Entry; ENTRY_COUNT],
-    //the root directory is an array of file entries
-    pub table: [u16; FAT_SIZE],
-    pub buffer: [u8; 2048],
+    pub info: FsInfo,
+    pub fat_type: FatType,
+
+    //volume layout calculated from header, lbas are absolute
+    fat_start: u32,
+    fat_size: u32,
+    root_start: u32,
+    root_sectors: u32,
+    data_start: u32,
+    cluster_count: u32,
+
+    //window of the file allocation table and its first sector relative to fat start
+    table: [u8; TABLE_SIZE],
+    table_sector: u32,
+
+    //where to start searching for a free cluster
+    next_free: u32,
+
+    //last read sector of directories or file data
+    sector: [u8; SECTOR_SIZE],
+    sector_lba: u64,
 }
 
 impl FatDriver {
     //get header address and overwrite that mem location with data from boot sector
+    //then calculate volume layout and detect fat type
     pub fn load_header(&mut self) {
         let target = &mut self.header as *mut Header;
 
         let lba: u64 = FAT_START as u64;
         let sectors: u16 = 1;
 
-        unsafe {
-            DISK.read(target, lba, sectors);
+        cache::read(target, lba, sectors);
+
+        self.load_layout();
+
+        if self.fat_type == FatType::Fat32 {
+            self.load_info();
+        }
+
+        libfelix::print!(
+            "[!] {} filesystem found! Clusters: {}",
+            self.fat_type.name(),
+            self.cluster_count
+        );
+
+        if let Some(free) = self.free_clusters() {
+            libfelix::print!(", free: {}", free);
         }
+
+        libfelix::print!(", label: ");
+        for c in self.volume_label() {
+            libfelix::print!("{}", c as char);
+        }
+        libfelix::println!();
     }
 
-    //get entries array address and overwrite that mem location with data from root directory
-    //calculate size and position of root direcotry based on data from header
-    pub fn load_entries(&mut self) {
-        libfelix::print!(" loading entries");
-        let target = &mut self.entries as *mut Entry;
+    //calculate where fats, root directory and data region start
+    fn load_layout(&mut self) {
+        let header = &self.header;
 
-        let entry_size = mem::size_of::<Entry>() as u16;
+        let bytes_per_sector = header.bytes_per_sector();
+        let sectors_per_cluster = header.sectors_per_cluster();
 
-        let lba: u64 = FAT_START as u64
-            + (self.header.reserved_sectors
-                + self.header.sectors_per_fat * self.header.fat_count as u16) as u64;
+        if bytes_per_sector == 0
+            || sectors_per_cluster == 0
+            || header.fat_count() == 0
+            || header.sectors_per_fat() == 0
+        {
+            libfelix::println!("[ERROR] Invalid FAT header!");
+            return;
+        }
 
-        let size: u16 = entry_size * self.header.dir_entries_count;
-        let sectors: u16 = size / self.header.bytes_per_sector;
+        //root directory size is always zero on FAT32
+        let root_sectors = ((header.dir_entries_count() * mem::size_of::<Entry>() as u32)
+            + (bytes_per_sector - 1))
+            / bytes_per_sector;
 
-        unsafe {
-            DISK.read(target, lba, sectors);
+        let fat_size = header.sectors_per_fat();
+        let fat_start = FAT_START + header.reserved_sectors();
+
+        //a truncated or garbage header can describe regions past the end of the volume
+        let root_start = fat_size
+            .checked_mul(header.fat_count())
+            .and_then(|size| fat_start.checked_add(size));
+        let data_start = root_start.and_then(|start| start.checked_add(root_sectors));
+        let data_sectors =
+            data_start.and_then(|start| header.total_sectors().checked_sub(start - FAT_START));
+
+        let (root_start, data_start, data_sectors) = match (root_start, data_start, data_sectors) {
+            (Some(root_start), Some(data_start), Some(data_sectors)) => {
+                (root_start, data_start, data_sectors)
+            }
+            _ => {
+                libfelix::println!("[ERROR] Invalid FAT header!");
+                return;
+            }
+        };
+
+        let cluster_count = data_sectors / sectors_per_cluster;
+
+        self.fat_type = if cluster_count < FAT12_MAX_CLUSTERS {
+            FatType::Fat12
+        } else if cluster_count < FAT16_MAX_CLUSTERS {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        self.fat_start = fat_start;
+        self.fat_size = fat_size;
+        self.root_start = root_start;
+        self.root_sectors = root_sectors;
+        self.data_start = data_start;
+        self.cluster_count = cluster_count;
+    }
+
+    //load FAT32 fs info sector
+    fn load_info(&mut self) {
+        let target = &mut self.info as *mut FsInfo;
+
+        let lba: u64 = FAT_START as u64 + self.header.ebr32().fs_info_sector as u64;
+
+        cache::read(target, lba, 1);
+
+        if !self.info.is_valid() {
+            libfelix::println!("[ERROR] Invalid FAT32 fs info sector!");
+            self.info = NULL_INFO;
         }
     }
 
-    //list each entry in root direcotry
-    //TODO: add other info like creation_date ecc
-    pub fn list_entries(&self) {
-        libfelix::println!("Listing root directory entries:");
+    //free cluster count from fs info sector, if known
+    pub fn free_clusters(&self) -> Option<u32> {
+        let free = self.info.free_count;
 
-        libfelix::println!("Name          Size          Cluster number");
+        if self.info.is_valid() && free != 0xffff_ffff {
+            Some(free)
+        } else {
+            None
+        }
+    }
+
+    pub fn cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
+
+    //volume label is in a different position on FAT32
+    pub fn volume_label(&self) -> [u8; 11] {
+        match self.fat_type {
+            FatType::Fat32 => self.header.ebr32().volume_label,
+            _ => self.header.ebr().volume_label,
+        }
+    }
+
+    //load first window of file allocation table
+    pub fn load_table(&mut self) {
+        self.load_table_window(0);
+    }
+
+    //load two sectors of the file allocation table starting from given sector
+    fn load_table_window(&mut self, sector: u32) {
+        if self.table_sector == sector {
+            return;
+        }
+
+        let target = &mut self.table as *mut u8;
+
+        let lba: u64 = self.fat_start as u64 + sector as u64;
+        let sectors: u16 = (TABLE_SIZE / SECTOR_SIZE) as u16;
+
+        cache::read(target, lba, sectors);
+
+        self.table_sector = sector;
+    }
+
+    //get next cluster of a chain reading the file allocation table
+    pub fn next_cluster(&mut self, cluster: u32) -> u32 {
+        //byte offset of entry inside the table
+        let offset = match self.fat_type {
+            FatType::Fat12 => cluster + (cluster / 2),
+            FatType::Fat16 => cluster * 2,
+            FatType::Fat32 => cluster * 4,
+        };
+
+        let sector = offset / SECTOR_SIZE as u32;
+        let index = (offset % SECTOR_SIZE as u32) as usize;
+
+        self.load_table_window(sector);
+
+        match self.fat_type {
+            //FAT12 entries are 12 bits packed in pairs in three bytes
+            FatType::Fat12 => {
+                let value = u16::from_le_bytes([self.table[index], self.table[index + 1]]);
 
-        for i in 0..ENTRY_COUNT {
-            if self.entries[i].name[0] != 0 {
-                //print name
-                for c in self.entries[i].name {
-                    libfelix::print!("{}", c as char);
+                if cluster & 1 == 1 {
+                    (value >> 4) as u32
+                } else {
+                    (value & 0xfff) as u32
                 }
-                //print size
-                let size = self.entries[i].size;
-                libfelix::print!("   {} bytes", size);
-
-                //print cluster
-                let cluster = self.entries[i].first_cluster_low;
-                libfelix::print!("     {}", cluster);
-                libfelix::println!();
+            }
+            FatType::Fat16 => u16::from_le_bytes([self.table[index], self.table[index + 1]]) as u32,
+            FatType::Fat32 => {
+                let value = u32::from_le_bytes([
+                    self.table[index],
+                    self.table[index + 1],
+                    self.table[index + 2],
+                    self.table[index + 3],
+                ]);
+
+                value & FAT32_CLUSTER_MASK
             }
         }
     }
 
-    //load file allocation table
-    pub fn load_table(&mut self) {
-        let target = &mut self.table as *mut u16;
+    //check if cluster marks the end of a chain, free and reserved clusters also end it
+    pub fn is_end_of_chain(&self, cluster: u32) -> bool {
+        cluster < 2 || cluster >= self.fat_type.end_of_chain()
+    }
 
-        let lba: u64 = FAT_START as u64 + self.header.reserved_sectors as u64;
+    //get first cluster of an entry, the high part is used only by FAT32
+    pub fn first_cluster(&self, entry: &Entry) -> u32 {
+        let low = entry.first_cluster_low as u32;
+        let high = entry.first_cluster_high as u32;
 
-        //let sectors: u16 = self.header.sectors_per_fat;
-        let sectors: u16 = 1;
+        match self.fat_type {
+            FatType::Fat32 => ((high << 16) | low) & FAT32_CLUSTER_MASK,
+            _ => low,
+        }
+    }
+
+    //get lba of first sector of a cluster
+    pub fn cluster_lba(&self, cluster: u32) -> u64 {
+        self.data_start as u64 + ((cluster - 2) * self.header.sectors_per_cluster()) as u64
+    }
+
+    //cluster size in bytes
+    pub fn cluster_size(&self) -> usize {
+        (self.header.sectors_per_cluster() * self.header.bytes_per_sector()) as usize
+    }
+
+    //set value of a cluster in every copy of the file allocation table
+    fn set_cluster(&mut self, cluster: u32, value: u32) {
+        let offset = match self.fat_type {
+            FatType::Fat12 => cluster + (cluster / 2),
+            FatType::Fat16 => cluster * 2,
+            FatType::Fat32 => cluster * 4,
+        };
+
+        let sector = offset / SECTOR_SIZE as u32;
+        let index = (offset % SECTOR_SIZE as u32) as usize;
+
+        self.load_table_window(sector);
+
+        let size = match self.fat_type {
+            FatType::Fat12 => {
+                let old = u16::from_le_bytes([self.table[index], self.table[index + 1]]);
+
+                //keep the half byte that belongs to the other entry
+                let new = if cluster & 1 == 1 {
+                    (old & 0x000f) | ((value as u16) << 4)
+                } else {
+                    (old & 0xf000) | (value as u16 & 0x0fff)
+                };
+
+                self.table[index..index + 2].copy_from_slice(&new.to_le_bytes());
+                2
+            }
+            FatType::Fat16 => {
+                self.table[index..index + 2].copy_from_slice(&(value as u16).to_le_bytes());
+                2
+            }
+            FatType::Fat32 => {
+                let old = u32::from_le_bytes([
+                    self.table[index],
+                    self.table[index + 1],
+                    self.table[index + 2],
+                    self.table[index + 3],
+                ]);
+
+                //highest 4 bits are reserved and must be preserved
+                let new = (old & !FAT32_CLUSTER_MASK) | (value & FAT32_CLUSTER_MASK);
+
+                self.table[index..index + 4].copy_from_slice(&new.to_le_bytes());
+                4
+            }
+        };
+
+        //a FAT12 entry may be split between two sectors
+        let sectors: u16 = if index + size > SECTOR_SIZE { 2 } else { 1 };
+
+        for copy in 0..self.header.fat_count() {
+            let lba = (self.fat_start + copy * self.fat_size + sector) as u64;
+
+            cache::write(&self.table as *const u8, lba, sectors);
+        }
+    }
+
+    //find a free cluster and mark it as end of chain, linking it after previous if not zero
+    fn allocate_cluster(&mut self, previous: u32) -> Result<u32, VfsError> {
+        let start = self.next_free.max(2) - 2;
+
+        for i in 0..self.cluster_count {
+            let cluster = 2 + (start + i) % self.cluster_count;
+
+            if self.next_cluster(cluster) == 0 {
+                self.set_cluster(cluster, self.fat_type.end_marker());
+
+                if previous != 0 {
+                    self.set_cluster(previous, cluster);
+                }
+
+                self.next_free = cluster + 1;
+                self.update_info(-1, cluster);
+
+                return Ok(cluster);
+            }
+        }
+
+        Err(VfsError::NoSpace)
+    }
+
+    //free every cluster of a chain
+    fn free_chain(&mut self, first_cluster: u32) {
+        let mut cluster = first_cluster;
+
+        while !self.is_end_of_chain(cluster) {
+            let next = self.next_cluster(cluster);
+
+            self.set_cluster(cluster, 0);
+            self.update_info(1, self.next_free);
+
+            cluster = next;
+        }
+    }
+
+    //keep FAT32 fs info sector updated after allocations
+    fn update_info(&mut self, free_change: i32, next_free: u32) {
+        if self.fat_type != FatType::Fat32 || self.free_clusters().is_none() {
+            return;
+        }
+
+        self.info.free_count = (self.info.free_count as i32 + free_change) as u32;
+        self.info.next_free = next_free;
+
+        let lba: u64 = FAT_START as u64 + self.header.ebr32().fs_info_sector as u64;
+
+        cache::write(&self.info as *const FsInfo, lba, 1);
+    }
+
+    //fill a cluster with zeros
+    fn zero_cluster(&mut self, cluster: u32) {
+        let lba = self.cluster_lba(cluster);
+
+        self.sector = [0; SECTOR_SIZE];
+        for i in 0..self.header.sectors_per_cluster() as u64 {
+            self.sector_lba = lba + i;
+            self.store_sector();
+        }
+    }
+
+    //load a cluster and the next one of the chain in cache
+    //files are mostly read sequentially, so they will be needed soon
+    fn read_ahead(&mut self, cluster: u32) {
+        let sectors = self.header.sectors_per_cluster() as u16;
+        cache::read_ahead(self.cluster_lba(cluster), sectors);
+
+        let next = self.next_cluster(cluster);
+        if !self.is_end_of_chain(next) {
+            cache::read_ahead(self.cluster_lba(next), sectors);
+        }
+    }
+
+    //read a sector to the sector buffer, if not already there
+    fn load_sector(&mut self, lba: u64) {
+        if self.sector_lba == lba {
+            return;
+        }
+
+        let target = &mut self.sector as *mut u8;
+
+        cache::read(target, lba, 1);
+
+        self.sector_lba = lba;
+    }
+
+    //directory entries are identified by their position on disk
+    //position is the lba of their sector multiplied by entries per sector plus their index
+    fn entry_at(&mut self, position: u32) -> Entry {
+        let lba = (position / ENTRIES_PER_SECTOR) as u64;
+        let index = (position % ENTRIES_PER_SECTOR) as usize;
+
+        self.load_sector(lba);
 
         unsafe {
-            DISK.read(target, lba, sectors);
+            let entries = self.sector.as_ptr() as *const Entry;
+            ptr::read_unaligned(entries.add(index))
         }
     }
 
-    //read first cluster of file to buffer
-    pub fn read_file_to_buffer(&self, entry: &Entry) {
-        let target = self.buffer.as_ptr() as *mut u8;
+    //write sector buffer back to disk
+    fn store_sector(&mut self) {
+        cache::write(&self.sector as *const u8, self.sector_lba, 1);
+    }
 
-        let data_lba: u64 = FAT_START as u64
-            + (self.header.reserved_sectors
-                + self.header.sectors_per_fat * self.header.fat_count as u16
-                + 32) as u64;
-        let lba: u64 = data_lba
-            + ((entry.first_cluster_low - 2) * self.header.sectors_per_cluster as u16) as u64;
+    //overwrite entry at given position
+    fn write_entry(&mut self, position: u32, entry: &Entry) {
+        let lba = (position / ENTRIES_PER_SECTOR) as u64;
+        let index = (position % ENTRIES_PER_SECTOR) as usize;
 
-        let sectors: u16 = self.header.sectors_per_cluster as u16;
+        self.load_sector(lba);
 
         unsafe {
-            DISK.read(target, lba, sectors);
+            let entries = self.sector.as_mut_ptr() as *mut Entry;
+            ptr::write_unaligned(entries.add(index), *entry);
         }
+
+        self.store_sector();
     }
 
-    //read file reading one cluster at time
-    pub fn read_file_to_target(&self, entry: &Entry, target: *mut u32) {
-        let mut next_cluster = entry.first_cluster_low;
-        let mut current_target = target;
+    //get position of a free slot in a directory, extending it if full
+    fn free_slot(&mut self, dir: &Inode) -> Result<u32, VfsError> {
+        let mut index = 0;
+        let mut last_cluster = 0;
 
-        //loop cluster read, until it reaches 0xffff in fat
-        loop {
-            let data_lba: u64 = FAT_START as u64
-                + (self.header.reserved_sectors
-                    + self.header.sectors_per_fat * self.header.fat_count as u16
-                    + 32) as u64;
+        while let Some(position) = self.slot_position(dir, index) {
+            let entry = self.entry_at(position);
 
-            let lba: u64 =
-                data_lba + ((next_cluster - 2) * self.header.sectors_per_cluster as u16) as u64;
+            if entry.name[0] == 0 || entry.name[0] == DELETED_ENTRY {
+                return Ok(position);
+            }
+
+            //remember last cluster of the chain, it's needed to extend it
+            last_cluster = self.position_cluster(position);
+            index += 1;
+        }
+
+        //fixed root directory can't grow
+        if last_cluster == 0 {
+            return Err(VfsError::NoSpace);
+        }
 
-            let sectors: u16 = self.header.sectors_per_cluster as u16;
+        let cluster = self.allocate_cluster(last_cluster)?;
+        self.zero_cluster(cluster);
 
-            unsafe {
-                DISK.read(current_target, lba, sectors);
+        Ok(self.cluster_lba(cluster) as u32 * ENTRIES_PER_SECTOR)
+    }
+
+    //get cluster containing an entry position, zero if outside data region
+    fn position_cluster(&self, position: u32) -> u32 {
+        let lba = position / ENTRIES_PER_SECTOR;
+
+        if lba < self.data_start {
+            return 0;
+        }
+
+        (lba - self.data_start) / self.header.sectors_per_cluster() + 2
+    }
+
+    //get first cluster of a directory, zero for fixed root directory of FAT12 and FAT16
+    fn dir_cluster(&mut self, dir: &Inode) -> u32 {
+        if dir.id == ROOT_ID {
+            match self.fat_type {
+                FatType::Fat32 => self.header.ebr32().root_cluster & FAT32_CLUSTER_MASK,
+                _ => 0,
             }
+        } else {
+            let entry = self.entry_at(dir.id);
+            self.first_cluster(&entry)
+        }
+    }
 
-            next_cluster = self.table[next_cluster as usize];
+    //get position of the nth slot of a directory, none if directory is over
+    fn slot_position(&mut self, dir: &Inode, index: u32) -> Option<u32> {
+        let first_cluster = self.dir_cluster(dir);
 
-            //after reading a cluster, increment target by cluster size
-            unsafe {
-                //let cluster_size = 2048;
-                let cluster_size =
-                    self.header.sectors_per_cluster as u16 * self.header.bytes_per_sector;
-                current_target = current_target.byte_add(cluster_size as usize);
+        //fixed root directory
+        if first_cluster == 0 {
+            if index >= self.header.dir_entries_count() {
+                return None;
             }
 
-            if next_cluster == 0xffff {
+            let lba = self.root_start + index / ENTRIES_PER_SECTOR;
+            return Some(lba * ENTRIES_PER_SECTOR + index % ENTRIES_PER_SECTOR);
+        }
+
+        //directory stored in a cluster chain
+        let entries_per_cluster = ENTRIES_PER_SECTOR * self.header.sectors_per_cluster();
+
+        let mut cluster = first_cluster;
+        for _ in 0..(index / entries_per_cluster) {
+            cluster = self.next_cluster(cluster);
+
+            if self.is_end_of_chain(cluster) {
+                return None;
+            }
+        }
+
+        let index = index % entries_per_cluster;
+        let lba = self.cluster_lba(cluster) as u32 + index / ENTRIES_PER_SECTOR;
+
+        Some(lba * ENTRIES_PER_SECTOR + index % ENTRIES_PER_SECTOR)
+    }
+
+    //iterate valid entries of a directory calling f on each one until it returns true
+    //returns the entry that stopped the iteration and its position
+    fn find_entry(
+        &mut self,
+        dir: &Inode,
+        mut f: impl FnMut(&Entry, &str, u32) -> bool,
+    ) -> Option<(Entry, u32)> {
+        let mut index = 0;
+
+        while let Some(position) = self.slot_position(dir, index) {
+            let entry = self.entry_at(position);
+            index += 1;
+
+            //no more entries after a zero one
+            if entry.name[0] == 0 {
                 break;
             }
+
+            if !entry.is_valid() {
+                continue;
+            }
+
+            let mut name = [0; 12];
+            let len = entry.file_name(&mut name);
+            let name = core::str::from_utf8(&name[..len]).unwrap_or("");
+
+            //skip dot entries, the vfs resolves them
+            if name == "." || name == ".." {
+                continue;
+            }
+
+            if f(&entry, name, position) {
+                return Some((entry, position));
+            }
+        }
+
+        None
+    }
+}
+
+impl FileSystem for FatDriver {
+    fn name(&self) -> &'static str {
+        self.fat_type.name()
+    }
+
+    fn root(&mut self) -> Inode {
+        Inode {
+            id: ROOT_ID,
+            kind: InodeKind::Directory,
+            size: 0,
         }
     }
 
-    //search by filename, returns found root entry
-    pub fn search_file(&self, name: &[char]) -> &Entry {
-        for entry in self.entries.iter() {
-            let mut found = true;
-            let mut i = 0;
+    fn lookup(&mut self, dir: &Inode, name: &str) -> Result<Inode, VfsError> {
+        match self.find_entry(dir, |_, n, _| n.eq_ignore_ascii_case(name)) {
+            Some((entry, position)) => Ok(entry.to_inode(position)),
+            None => Err(VfsError::NotFound),
+        }
+    }
+
+    fn read_dir(&mut self, dir: &Inode, index: usize) -> Result<Option<DirEntry>, VfsError> {
+        let mut count = 0;
+        let mut found = None;
+
+        self.find_entry(dir, |entry, name, position| {
+            if count == index {
+                found = Some(DirEntry::new(name, entry.to_inode(position)));
+                return true;
+            }
+            count += 1;
+            false
+        });
+
+        Ok(found)
+    }
+
+    fn read(&mut self, inode: &Inode, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let entry = self.entry_at(inode.id);
+
+        if offset >= entry.size {
+            return Ok(0);
+        }
+
+        let len = buffer.len().min((entry.size - offset) as usize);
+        let cluster_size = self.cluster_size() as u32;
+
+        //skip clusters before offset
+        let mut cluster = self.first_cluster(&entry);
+        for _ in 0..(offset / cluster_size) {
+            //chain is shorter than entry size, nothing to read past its end
+            if self.is_end_of_chain(cluster) {
+                return Ok(0);
+            }
+
+            cluster = self.next_cluster(cluster);
+        }
+
+        let mut position = offset;
+        let mut read = 0;
 
-            for n in name {
-                let mut c = n.clone();
+        if !self.is_end_of_chain(cluster) {
+            self.read_ahead(cluster);
+        }
+
+        while read < len {
+            if self.is_end_of_chain(cluster) {
+                break;
+            }
+
+            //copy part of the sector containing position
+            let in_cluster = position % cluster_size;
+            let lba = self.cluster_lba(cluster) + (in_cluster / SECTOR_SIZE as u32) as u64;
+            self.load_sector(lba);
+
+            let in_sector = (position % SECTOR_SIZE as u32) as usize;
+            let count = (SECTOR_SIZE - in_sector).min(len - read);
 
-                if c.is_ascii_lowercase() {
-                    c = c.to_ascii_uppercase();
+            buffer[read..read + count].copy_from_slice(&self.sector[in_sector..in_sector + count]);
+
+            read += count;
+            position += count as u32;
+
+            //go to next cluster when this one is over
+            if position % cluster_size == 0 {
+                cluster = self.next_cluster(cluster);
+
+                if read < len && !self.is_end_of_chain(cluster) {
+                    self.read_ahead(cluster);
                 }
+            }
+        }
+
+        Ok(read)
+    }
+
+    fn write(&mut self, inode: &Inode, offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        let mut entry = self.entry_at(inode.id);
+
+        //fill the gap with zeros if writing after the end of the file
+        while entry.size < offset {
+            let zeros = [0; SECTOR_SIZE];
+            let count = (offset - entry.size).min(SECTOR_SIZE as u32) as usize;
+
+            if self.write(inode, entry.size, &zeros[..count])? == 0 {
+                return Err(VfsError::NoSpace);
+            }
+            entry = self.entry_at(inode.id);
+        }
+
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let cluster_size = self.cluster_size() as u32;
 
-                if (c != entry.name[i] as char) && (name[i] != '\0') {
-                    found = false;
+        //empty files have no cluster
+        let mut cluster = self.first_cluster(&entry);
+        if cluster == 0 {
+            cluster = self.allocate_cluster(0)?;
+            entry.set_first_cluster(cluster);
+            self.write_entry(inode.id, &entry);
+        }
+
+        //skip clusters before offset, growing the chain if offset is at its end
+        for _ in 0..(offset / cluster_size) {
+            let next = self.next_cluster(cluster);
+
+            cluster = if self.is_end_of_chain(next) {
+                self.allocate_cluster(cluster)?
+            } else {
+                next
+            };
+        }
+
+        let mut position = offset;
+        let mut written = 0;
+
+        while written < buffer.len() {
+            //read modify and write back the sector containing position
+            let in_cluster = position % cluster_size;
+            let lba = self.cluster_lba(cluster) + (in_cluster / SECTOR_SIZE as u32) as u64;
+            self.load_sector(lba);
+
+            let in_sector = (position % SECTOR_SIZE as u32) as usize;
+            let count = (SECTOR_SIZE - in_sector).min(buffer.len() - written);
+
+            self.sector[in_sector..in_sector + count]
+                .copy_from_slice(&buffer[written..written + count]);
+            self.store_sector();
+
+            written += count;
+            position += count as u32;
+
+            //go to next cluster when this one is over, allocating it if needed
+            if position % cluster_size == 0 && written < buffer.len() {
+                let next = self.next_cluster(cluster);
+
+                cluster = if self.is_end_of_chain(next) {
+                    match self.allocate_cluster(cluster) {
+                        Ok(next) => next,
+                        Err(_) => break,
+                    }
+                } else {
+                    next
+                };
+            }
+        }
+
+        if position > entry.size {
+            entry.size = position;
+            self.write_entry(inode.id, &entry);
+        }
+
+        Ok(written)
+    }
+
+    fn create(&mut self, dir: &Inode, name: &str, kind: InodeKind) -> Result<Inode, VfsError> {
+        let short_name = Entry::short_name(name)?;
+
+        if self.lookup(dir, name).is_ok() {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let position = self.free_slot(dir)?;
+
+        let mut entry = match kind {
+            InodeKind::File => Entry::new(short_name, ATTRIBUTE_ARCHIVE),
+            InodeKind::Directory => {
+                let mut entry = Entry::new(short_name, ATTRIBUTE_DIRECTORY);
+
+                //directories always have a cluster containing dot entries
+                let cluster = self.allocate_cluster(0)?;
+                self.zero_cluster(cluster);
+                entry.set_first_cluster(cluster);
+
+                let mut dot = Entry::new(*b".          ", ATTRIBUTE_DIRECTORY);
+                dot.set_first_cluster(cluster);
+
+                //dot dot entry of directories inside root points to cluster zero
+                let mut dot_dot = Entry::new(*b"..         ", ATTRIBUTE_DIRECTORY);
+                if dir.id != ROOT_ID {
+                    dot_dot.set_first_cluster(self.dir_cluster(dir));
                 }
 
-                i += 1;
+                let first_position = self.cluster_lba(cluster) as u32 * ENTRIES_PER_SECTOR;
+                self.write_entry(first_position, &dot);
+                self.write_entry(first_position + 1, &dot_dot);
+
+                entry
             }
+        };
+
+        entry.size = 0;
+        self.write_entry(position, &entry);
+
+        Ok(entry.to_inode(position))
+    }
+
+    fn truncate(&mut self, inode: &Inode) -> Result<(), VfsError> {
+        let mut entry = self.entry_at(inode.id);
+
+        self.free_chain(self.first_cluster(&entry));
 
-            if found {
-                return entry;
+        entry.set_first_cluster(0);
+        entry.size = 0;
+        self.write_entry(inode.id, &entry);
+
+        Ok(())
+    }
+
+    fn remove(&mut self, dir: &Inode, name: &str) -> Result<(), VfsError> {
+        let (mut entry, position) = self
+            .find_entry(dir, |_, n, _| n.eq_ignore_ascii_case(name))
+            .ok_or(VfsError::NotFound)?;
+
+        //only empty directories can be removed, dot entries are skipped by find_entry
+        if entry.is_directory() {
+            let inode = entry.to_inode(position);
+            if self.find_entry(&inode, |_, _, _| true).is_some() {
+                return Err(VfsError::NotEmpty);
             }
         }
 
-        &NULL_ENTRY
+        self.free_chain(self.first_cluster(&entry));
+
+        entry.name[0] = DELETED_ENTRY;
+        self.write_entry(position, &entry);
+
+        Ok(())
+    }
+
+    fn stat(&mut self, inode: &Inode) -> Result<Inode, VfsError> {
+        if inode.id == ROOT_ID {
+            return Ok(self.root());
+        }
+
+        let entry = self.entry_at(inode.id);
+        Ok(entry.to_inode(inode.id))
     }
 }
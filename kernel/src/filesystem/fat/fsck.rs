@@ -0,0 +1,427 @@
+//FAT FILESYSTEM CHECKER
+//Walks directory tree and cluster chains looking for inconsistencies, optionally repairing them
+//Checks FAT copies, invalid entries, broken and cross-linked chains, wrong file sizes and lost clusters
+
+use super::*;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+//max directory depth walked
+const MAX_DEPTH: usize = 32;
+
+//result of a check, messages are kept so they can be printed after the filesystem is released
+pub struct FsckReport {
+    pub messages: Vec<String>,
+    pub errors: u32,
+    pub files: u32,
+    pub directories: u32,
+}
+
+impl FsckReport {
+    fn error(&mut self, message: String) {
+        self.messages.push(message);
+        self.errors += 1;
+    }
+}
+
+//why a chain stopped before its end
+enum ChainError {
+    Invalid(u32),
+    CrossLinked(u32),
+}
+
+//clusters of a chain up to its end or to the first problem found
+struct Chain {
+    count: u32,
+    last: u32,
+    error: Option<ChainError>,
+}
+
+impl FatDriver {
+    //check whole volume, repairing problems if repair is true
+    pub fn check(&mut self, repair: bool) -> FsckReport {
+        let mut report = FsckReport {
+            messages: Vec::new(),
+            errors: 0,
+            files: 0,
+            directories: 0,
+        };
+
+        //clusters referenced by some chain
+        let mut used = vec![false; self.cluster_count as usize + 2];
+
+        self.check_copies(repair, &mut report);
+        self.check_tree(repair, &mut used, &mut report);
+        self.check_lost(repair, &used, &mut report);
+        self.check_info(repair, &mut report);
+
+        report
+    }
+
+    fn valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+
+    //compare every fat copy with the first one, repair copies the first one over the others
+    fn check_copies(&mut self, repair: bool, report: &mut FsckReport) {
+        let mut first = [0u8; SECTOR_SIZE];
+        let mut copy = [0u8; SECTOR_SIZE];
+
+        for index in 1..self.header.fat_count() {
+            let mut different = 0;
+
+            for sector in 0..self.fat_size {
+                let lba = (self.fat_start + sector) as u64;
+                let copy_lba = (self.fat_start + index * self.fat_size + sector) as u64;
+
+                cache::read(first.as_mut_ptr(), lba, 1);
+                cache::read(copy.as_mut_ptr(), copy_lba, 1);
+
+                if first != copy {
+                    different += 1;
+
+                    if repair {
+                        cache::write(first.as_ptr(), copy_lba, 1);
+                    }
+                }
+            }
+
+            if different > 0 {
+                report.error(format!(
+                    "FAT copy {} differs from first copy in {} sectors",
+                    index + 1,
+                    different
+                ));
+            }
+        }
+    }
+
+    //follow a chain marking its clusters as used
+    fn walk_chain(&mut self, first: u32, used: &mut [bool]) -> Chain {
+        let mut chain = Chain {
+            count: 0,
+            last: 0,
+            error: None,
+        };
+
+        let mut cluster = first;
+        loop {
+            if !self.valid_cluster(cluster) {
+                chain.error = Some(ChainError::Invalid(cluster));
+                return chain;
+            }
+
+            if used[cluster as usize] {
+                chain.error = Some(ChainError::CrossLinked(cluster));
+                return chain;
+            }
+
+            used[cluster as usize] = true;
+            chain.count += 1;
+            chain.last = cluster;
+
+            let next = self.next_cluster(cluster);
+            if next >= self.fat_type.end_of_chain() {
+                return chain;
+            }
+
+            cluster = next;
+        }
+    }
+
+    //check that entry name contains only valid chars
+    fn valid_name(entry: &Entry) -> bool {
+        //first byte 0x05 stands for a real 0xe5
+        if entry.name[0] == b' ' {
+            return false;
+        }
+
+        entry.name.iter().enumerate().all(|(i, c)| {
+            (i == 0 && *c == 0x05)
+                || (*c >= 0x20 && (*c == b' ' || !INVALID_NAME_CHARS.contains(c)))
+        })
+    }
+
+    //walk every directory starting from root
+    fn check_tree(&mut self, repair: bool, used: &mut [bool], report: &mut FsckReport) {
+        let root = self.root();
+        let entries_per_cluster = ENTRIES_PER_SECTOR * self.header.sectors_per_cluster();
+
+        //slots walked in a directory are limited to its valid clusters, so broken chains are not followed
+        let mut root_slots = u32::MAX;
+
+        //FAT32 root directory is a cluster chain too
+        let root_cluster = self.dir_cluster(&root);
+        if root_cluster != 0 {
+            let chain = self.walk_chain(root_cluster, used);
+            root_slots = chain.count * entries_per_cluster;
+
+            if chain.error.is_some() {
+                report.error(String::from("Root directory chain is broken"));
+
+                if repair && chain.last != 0 {
+                    self.set_cluster(chain.last, self.fat_type.end_marker());
+                }
+            }
+        }
+
+        let mut stack: Vec<(Inode, String, usize, u32)> =
+            vec![(root, String::new(), 0, root_slots)];
+
+        while let Some((dir, path, depth, slots)) = stack.pop() {
+            report.directories += 1;
+
+            let mut index = 0;
+            while index < slots {
+                let position = match self.slot_position(&dir, index) {
+                    Some(position) => position,
+                    None => break,
+                };
+                index += 1;
+
+                let mut entry = self.entry_at(position);
+
+                //no more entries after a zero one
+                if entry.name[0] == 0 {
+                    break;
+                }
+
+                if entry.name[0] == DELETED_ENTRY
+                    || entry.attributes & ATTRIBUTE_LONG_NAME == ATTRIBUTE_LONG_NAME
+                    || entry.attributes & ATTRIBUTE_VOLUME_ID != 0
+                    || entry.name[0] == b'.'
+                {
+                    continue;
+                }
+
+                let mut name = [0; 12];
+                let len = entry.file_name(&mut name);
+                let name = core::str::from_utf8(&name[..len]).unwrap_or("?");
+                let entry_path = format!("{}/{}", path, name);
+
+                let first_cluster = self.first_cluster(&entry);
+
+                //invalid entries are deleted, their clusters become lost and are freed later
+                if !FatDriver::valid_name(&entry)
+                    || (entry.is_directory() && first_cluster == 0)
+                    || (first_cluster != 0 && !self.valid_cluster(first_cluster))
+                {
+                    report.error(format!("{}: invalid entry", entry_path));
+
+                    if repair {
+                        entry.name[0] = DELETED_ENTRY;
+                        self.write_entry(position, &entry);
+                    }
+                    continue;
+                }
+
+                if entry.is_directory() {
+                    let count = self.check_directory(
+                        repair,
+                        position,
+                        &mut entry,
+                        &entry_path,
+                        used,
+                        report,
+                    );
+
+                    if entry.name[0] != DELETED_ENTRY {
+                        if depth + 1 < MAX_DEPTH {
+                            let inode = entry.to_inode(position);
+                            let slots = count * entries_per_cluster;
+                            stack.push((inode, entry_path, depth + 1, slots));
+                        } else {
+                            report.error(format!("{}: directory too deep", entry_path));
+                        }
+                    }
+                } else {
+                    report.files += 1;
+                    self.check_file(repair, position, &mut entry, &entry_path, used, report);
+                }
+            }
+        }
+    }
+
+    //check chain of a directory, directories with a broken first cluster are deleted
+    //returns how many valid clusters the directory has
+    fn check_directory(
+        &mut self,
+        repair: bool,
+        position: u32,
+        entry: &mut Entry,
+        path: &str,
+        used: &mut [bool],
+        report: &mut FsckReport,
+    ) -> u32 {
+        let chain = self.walk_chain(self.first_cluster(entry), used);
+
+        let error = match chain.error {
+            Some(error) => error,
+            None => return chain.count,
+        };
+
+        match error {
+            ChainError::Invalid(cluster) => report.error(format!(
+                "{}: chain points to invalid cluster {}",
+                path, cluster
+            )),
+            ChainError::CrossLinked(cluster) => report.error(format!(
+                "{}: chain is cross-linked on cluster {}",
+                path, cluster
+            )),
+        }
+
+        if repair {
+            if chain.last != 0 {
+                self.set_cluster(chain.last, self.fat_type.end_marker());
+            } else {
+                entry.name[0] = DELETED_ENTRY;
+                self.write_entry(position, entry);
+            }
+        }
+
+        chain.count
+    }
+
+    //check chain of a file and compare its lenght with file size
+    fn check_file(
+        &mut self,
+        repair: bool,
+        position: u32,
+        entry: &mut Entry,
+        path: &str,
+        used: &mut [bool],
+        report: &mut FsckReport,
+    ) {
+        let first_cluster = self.first_cluster(entry);
+        let cluster_size = self.cluster_size() as u32;
+        let mut changed = false;
+
+        let mut count = 0;
+        if first_cluster != 0 {
+            let chain = self.walk_chain(first_cluster, used);
+            count = chain.count;
+
+            if let Some(error) = chain.error {
+                match error {
+                    ChainError::Invalid(cluster) => report.error(format!(
+                        "{}: chain points to invalid cluster {}",
+                        path, cluster
+                    )),
+                    ChainError::CrossLinked(cluster) => report.error(format!(
+                        "{}: chain is cross-linked on cluster {}",
+                        path, cluster
+                    )),
+                }
+
+                //truncate chain before problem, keeping clusters of other chains untouched
+                if repair {
+                    if chain.last != 0 {
+                        self.set_cluster(chain.last, self.fat_type.end_marker());
+                    } else {
+                        entry.set_first_cluster(0);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let size = entry.size;
+        let expected = (size + cluster_size - 1) / cluster_size;
+
+        if count < expected {
+            //size larger than chain, file is cut to the data it really has
+            report.error(format!(
+                "{}: size {} needs {} clusters, chain has {}",
+                path, size, expected, count
+            ));
+
+            if repair {
+                entry.size = count * cluster_size;
+                changed = true;
+            }
+        } else if count > expected {
+            //chain longer than size, extra clusters are freed
+            report.error(format!(
+                "{}: size {} needs {} clusters, chain has {}",
+                path, size, expected, count
+            ));
+
+            if repair {
+                if expected == 0 {
+                    self.free_chain(first_cluster);
+                    entry.set_first_cluster(0);
+                    changed = true;
+                } else {
+                    let mut last = first_cluster;
+                    for _ in 1..expected {
+                        last = self.next_cluster(last);
+                    }
+
+                    let extra = self.next_cluster(last);
+                    self.set_cluster(last, self.fat_type.end_marker());
+                    self.free_chain(extra);
+                }
+            }
+        }
+
+        if changed {
+            self.write_entry(position, entry);
+        }
+    }
+
+    //allocated clusters not referenced by any chain are lost, repair frees them
+    fn check_lost(&mut self, repair: bool, used: &[bool], report: &mut FsckReport) {
+        let bad = self.fat_type.end_of_chain() - 1;
+        let mut lost = 0;
+
+        for cluster in 2..self.cluster_count + 2 {
+            if used[cluster as usize] {
+                continue;
+            }
+
+            let value = self.next_cluster(cluster);
+            if value == 0 || value == bad {
+                continue;
+            }
+
+            lost += 1;
+
+            if repair {
+                self.set_cluster(cluster, 0);
+            }
+        }
+
+        if lost > 0 {
+            report.error(format!("{} lost clusters", lost));
+        }
+    }
+
+    //compare FAT32 free cluster count with the real one
+    fn check_info(&mut self, repair: bool, report: &mut FsckReport) {
+        let stored = match self.free_clusters() {
+            Some(free) => free,
+            None => return,
+        };
+
+        let mut free = 0;
+        for cluster in 2..self.cluster_count + 2 {
+            if self.next_cluster(cluster) == 0 {
+                free += 1;
+            }
+        }
+
+        if free != stored {
+            report.error(format!(
+                "Free cluster count is {}, fs info says {}",
+                free, stored
+            ));
+
+            if repair {
+                self.update_info(free as i32 - stored as i32, self.next_free);
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//FAT16 FORMATTER
+//Writes a new FAT16 volume on a partition: boot sector header, zeroed fats and an empty root directory
+//Layout is calculated like mkfs.fat does, choosing the smallest cluster size that keeps the volume FAT16
+
+use super::*;
+use crate::drivers::disk::Disk;
+use crate::interrupts::timer::TICKS;
+use alloc::vec;
+
+//FAT16 volumes have one reserved sector (the boot sector), two fats and 512 root entries
+const RESERVED_SECTORS: u32 = 1;
+const FAT_COUNT: u32 = 2;
+const ROOT_ENTRIES: u32 = 512;
+
+//biggest cluster allowed is 32KiB
+const MAX_SECTORS_PER_CLUSTER: u32 = 64;
+
+//fixed disk media
+const MEDIA_DESCRIPTOR: u8 = 0xf8;
+
+//sectors zeroed with a single write
+const ZERO_SECTORS: usize = 8;
+
+//extended boot record signature and boot sector signature
+const EBR_SIGNATURE: u8 = 0x29;
+const BOOT_SIGNATURE: u16 = 0xaa55;
+
+//layout of a formatted volume
+pub struct Format {
+    pub sectors_per_cluster: u32,
+    pub sectors_per_fat: u32,
+    pub clusters: u32,
+}
+
+//calculate volume layout for a partition of given size
+fn layout(sectors: u32) -> Result<Format, &'static str> {
+    let root_sectors = ROOT_ENTRIES * mem::size_of::<Entry>() as u32 / SECTOR_SIZE as u32;
+
+    if sectors <= RESERVED_SECTORS + root_sectors {
+        return Err("Partition too small for FAT16");
+    }
+
+    let available = sectors - RESERVED_SECTORS - root_sectors;
+
+    let mut sectors_per_cluster = 1;
+    loop {
+        //every fat sector holds 256 entries, formula from fat specification
+        let entries_per_fat = 256 * sectors_per_cluster + FAT_COUNT;
+        let sectors_per_fat = (available + entries_per_fat - 1) / entries_per_fat;
+
+        let data_sectors = available.saturating_sub(FAT_COUNT * sectors_per_fat);
+        let clusters = data_sectors / sectors_per_cluster;
+
+        if clusters < FAT12_MAX_CLUSTERS {
+            return Err("Partition too small for FAT16");
+        }
+
+        if clusters < FAT16_MAX_CLUSTERS {
+            return Ok(Format {
+                sectors_per_cluster,
+                sectors_per_fat,
+                clusters,
+            });
+        }
+
+        if sectors_per_cluster == MAX_SECTORS_PER_CLUSTER {
+            return Err("Partition too big for FAT16");
+        }
+
+        sectors_per_cluster *= 2;
+    }
+}
+
+//format partition of disk starting at start as FAT16, label is padded with spaces
+pub fn format(disk: &Disk, start: u32, sectors: u32, label: &str) -> Result<Format, &'static str> {
+    let format = layout(sectors)?;
+
+    //volumes without label use the same name as mkfs.fat
+    let mut volume_label = *b"NO NAME    ";
+    if !label.is_empty() {
+        volume_label = [b' '; 11];
+        for (i, c) in label.bytes().take(volume_label.len()).enumerate() {
+            volume_label[i] = c.to_ascii_uppercase();
+        }
+    }
+
+    //write zeroed fats and root directory first, so a failed format never looks valid
+    let root_sectors = ROOT_ENTRIES * mem::size_of::<Entry>() as u32 / SECTOR_SIZE as u32;
+    let fat_start = start + RESERVED_SECTORS;
+    let zero_count = FAT_COUNT * format.sectors_per_fat + root_sectors;
+
+    //shell runs on a small stack, so the buffer is allocated on heap
+    let zero = vec![0u8; SECTOR_SIZE * ZERO_SECTORS];
+    let mut done = 0;
+    while done < zero_count {
+        let count = (zero_count - done).min(ZERO_SECTORS as u32);
+        disk.write_sectors(zero.as_ptr(), (fat_start + done) as u64, count as u16);
+        done += count;
+    }
+
+    //first two fat entries hold media descriptor and end of chain marker
+    let mut sector = [0u8; SECTOR_SIZE];
+    sector[..4].copy_from_slice(&[MEDIA_DESCRIPTOR, 0xff, 0xff, 0xff]);
+
+    for i in 0..FAT_COUNT {
+        let lba = fat_start + i * format.sectors_per_fat;
+        disk.write_sectors(sector.as_ptr(), lba as u64, 1);
+    }
+
+    //root directory starts with volume label entry, if any
+    if !label.is_empty() {
+        let root_start = fat_start + FAT_COUNT * format.sectors_per_fat;
+        let entry = Entry::new(volume_label, ATTRIBUTE_VOLUME_ID);
+
+        sector = [0; SECTOR_SIZE];
+        unsafe {
+            ptr::write_unaligned(sector.as_mut_ptr() as *mut Entry, entry);
+        }
+        disk.write_sectors(sector.as_ptr(), root_start as u64, 1);
+    }
+
+    //boot sector is written last
+    let mut header = Header {
+        boot_jump_instructions: [0xeb, 0x3c, 0x90],
+
+        oem_identifier: *b"FELIX   ",
+        bytes_per_sector: SECTOR_SIZE as u16,
+        sectors_per_cluster: format.sectors_per_cluster as u8,
+        reserved_sectors: RESERVED_SECTORS as u16,
+        fat_count: FAT_COUNT as u8,
+        dir_entries_count: ROOT_ENTRIES as u16,
+        total_sectors: 0,
+        media_descriptor_type: MEDIA_DESCRIPTOR,
+        sectors_per_fat: format.sectors_per_fat as u16,
+        sectors_per_track: 63,
+        heads: 255,
+        hidden_sectors: start,
+        large_sector_count: 0,
+
+        extended: [0; 476],
+    };
+
+    //small sector count is used only if it fits in 16 bits
+    if sectors <= u16::MAX as u32 {
+        header.total_sectors = sectors as u16;
+    } else {
+        header.large_sector_count = sectors;
+    }
+
+    let ebr = ExtendedBootRecord {
+        drive_number: 0x80,
+        reserved: 0,
+        signature: EBR_SIGNATURE,
+        volume_id: unsafe { TICKS } ^ start,
+        volume_label,
+        system_id: *b"FAT16   ",
+    };
+
+    unsafe {
+        ptr::write_unaligned(header.extended.as_mut_ptr() as *mut ExtendedBootRecord, ebr);
+    }
+
+    //boot signature is in the last two bytes of the sector
+    let signature_offset = header.extended.len() - 2;
+    header.extended[signature_offset..].copy_from_slice(&BOOT_SIGNATURE.to_le_bytes());
+
+    disk.write_sectors(&header as *const Header, start as u64, 1);
+
+    Ok(format)
+}
@@ -0,0 +1,47 @@
+//OPEN FILES
+//Each task has a table of open files indexed by file descriptor
+
+use crate::filesystem::vfs::Vnode;
+
+const MAX_FILES: usize = 16;
+
+//open file, keeps track of the current offset inside it
+#[derive(Copy, Clone, Debug)]
+pub struct OpenFile {
+    pub vnode: Vnode,
+    pub offset: u32,
+    pub flags: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FileTable {
+    files: [Option<OpenFile>; MAX_FILES],
+}
+
+pub const EMPTY_FILE_TABLE: FileTable = FileTable {
+    files: [None; MAX_FILES],
+};
+
+impl FileTable {
+    //add file to the first free descriptor, returns none if table is full
+    pub fn insert(&mut self, file: OpenFile) -> Option<usize> {
+        let fd = self.files.iter().position(|f| f.is_none())?;
+
+        self.files[fd] = Some(file);
+
+        Some(fd)
+    }
+
+    pub fn get_mut(&mut self, fd: usize) -> Option<&mut OpenFile> {
+        self.files.get_mut(fd)?.as_mut()
+    }
+
+    //get how many files are open
+    pub fn count(&self) -> usize {
+        self.files.iter().filter(|f| f.is_some()).count()
+    }
+
+    pub fn remove(&mut self, fd: usize) -> Option<OpenFile> {
+        self.files.get_mut(fd)?.take()
+    }
+}
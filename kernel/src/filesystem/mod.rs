@@ -1 +1,9 @@
+pub mod cache;
+pub mod devfs;
+pub mod ext2;
 pub mod fat;
+pub mod file;
+pub mod procfs;
+pub mod reader;
+pub mod tmpfs;
+pub mod vfs;
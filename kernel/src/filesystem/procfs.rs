@@ -0,0 +1,344 @@
+//PROCFS
+//Synthetic filesystem exposing kernel and task state as text files
+//Files have no stored content, it is generated every time they are read
+
+use crate::drivers::disk::{DISK, SECOND_DISK};
+use crate::drivers::keyboard::KEYBOARD_INT;
+use crate::filesystem::cache::CACHE;
+use crate::filesystem::fat::FAT;
+use crate::filesystem::vfs::{DirEntry, FileSystem, Inode, InodeKind, VfsError, VFS};
+use crate::interrupts::idt::INTERRUPT_COUNTS;
+use crate::interrupts::timer::{TICKS, TIMER_INT};
+use crate::memory::allocator::{HEAP, HEAP_SIZE};
+use crate::multitasking::scheduler;
+use crate::multitasking::task::TASK_MANAGER;
+use crate::syscalls::handler::SYSCALL_INT;
+use alloc::string::String;
+use core::fmt::Write;
+use libfelix::mutex::Mutex;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut PROCFS: Mutex<ProcFs> = Mutex::new(ProcFs {});
+
+const ROOT_ID: u32 = 0;
+
+//files in root directory, their inode id is their index plus one
+const FILES: [&str; 7] = [
+    "tasks",
+    "meminfo",
+    "interrupts",
+    "mounts",
+    "disks",
+    "fat",
+    "sched",
+];
+
+//each task has a directory named after its pid containing its files, inode ids use its pid
+//pid is resolved again on every access, so open files never switch to a task reusing the slot
+const TASK_DIR: u32 = 0x1000_0000;
+const TASK_STATUS: u32 = 0x2000_0000;
+
+pub struct ProcFs {}
+
+impl ProcFs {
+    fn file_inode(&self, id: u32) -> Result<Inode, VfsError> {
+        let mut content = String::new();
+        self.generate(id, &mut content)?;
+
+        Ok(Inode {
+            id,
+            kind: InodeKind::File,
+            size: content.len() as u32,
+        })
+    }
+
+    fn task_inode(&self, pid: u32) -> Result<Inode, VfsError> {
+        if pid >= TASK_STATUS - TASK_DIR {
+            return Err(VfsError::NotFound);
+        }
+
+        unsafe {
+            TASK_MANAGER.find_task(pid).ok_or(VfsError::NotFound)?;
+        }
+
+        Ok(Inode {
+            id: TASK_DIR + pid,
+            kind: InodeKind::Directory,
+            size: 0,
+        })
+    }
+
+    //write content of file with given id
+    fn generate(&self, id: u32, out: &mut String) -> Result<(), VfsError> {
+        let result = match id {
+            1 => tasks(out),
+            2 => meminfo(out),
+            3 => interrupts(out),
+            4 => mounts(out),
+            5 => disks(out),
+            6 => fat(out),
+            7 => sched(out),
+            _ if id >= TASK_STATUS => task_status(id - TASK_STATUS, out)?,
+            _ => return Err(VfsError::NotFound),
+        };
+
+        //writing to a string fails only if formatting fails
+        result.map_err(|_| VfsError::NoSpace)
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&mut self) -> Inode {
+        Inode {
+            id: ROOT_ID,
+            kind: InodeKind::Directory,
+            size: 0,
+        }
+    }
+
+    fn lookup(&mut self, dir: &Inode, name: &str) -> Result<Inode, VfsError> {
+        match dir.id {
+            ROOT_ID => {
+                if let Some(index) = FILES.iter().position(|f| *f == name) {
+                    return self.file_inode(index as u32 + 1);
+                }
+
+                //task directories are named after task pid
+                let pid = name.parse::<u32>().map_err(|_| VfsError::NotFound)?;
+                self.task_inode(pid)
+            }
+
+            _ if dir.kind == InodeKind::Directory => match name {
+                "status" => self.file_inode(TASK_STATUS + dir.id - TASK_DIR),
+                _ => Err(VfsError::NotFound),
+            },
+
+            _ => Err(VfsError::NotDirectory),
+        }
+    }
+
+    fn read_dir(&mut self, dir: &Inode, index: usize) -> Result<Option<DirEntry>, VfsError> {
+        match dir.id {
+            //fixed files first, then a directory for each running task
+            ROOT_ID => {
+                if index < FILES.len() {
+                    let inode = self.file_inode(index as u32 + 1)?;
+                    return Ok(Some(DirEntry::new(FILES[index], inode)));
+                }
+
+                let pid = (0..unsafe { TASK_MANAGER.slots() })
+                    .filter_map(|id| unsafe { TASK_MANAGER.get_task(id).map(|t| t.pid) })
+                    .nth(index - FILES.len());
+
+                match pid {
+                    Some(pid) => {
+                        let mut name = String::new();
+                        let _ = write!(name, "{}", pid);
+
+                        Ok(Some(DirEntry::new(&name, self.task_inode(pid)?)))
+                    }
+                    None => Ok(None),
+                }
+            }
+
+            _ if dir.kind == InodeKind::Directory => match index {
+                0 => {
+                    let inode = self.file_inode(TASK_STATUS + dir.id - TASK_DIR)?;
+                    Ok(Some(DirEntry::new("status", inode)))
+                }
+                _ => Ok(None),
+            },
+
+            _ => Err(VfsError::NotDirectory),
+        }
+    }
+
+    fn read(&mut self, inode: &Inode, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let mut content = String::new();
+        self.generate(inode.id, &mut content)?;
+
+        let content = content.as_bytes();
+        let offset = offset as usize;
+
+        if offset >= content.len() {
+            return Ok(0);
+        }
+
+        let len = buffer.len().min(content.len() - offset);
+        buffer[..len].copy_from_slice(&content[offset..offset + len]);
+
+        Ok(len)
+    }
+
+    //size changes every time content is generated
+    fn stat(&mut self, inode: &Inode) -> Result<Inode, VfsError> {
+        match inode.kind {
+            InodeKind::File => self.file_inode(inode.id),
+            InodeKind::Directory => Ok(*inode),
+        }
+    }
+}
+
+//list tasks, same table shown by ps
+fn tasks(out: &mut String) -> core::fmt::Result {
+    unsafe { TASK_MANAGER.write_tasks(out) }
+}
+
+//status of a single task
+fn task_status(pid: u32, out: &mut String) -> Result<core::fmt::Result, VfsError> {
+    unsafe {
+        let id = TASK_MANAGER.find_task(pid).ok_or(VfsError::NotFound)?;
+        let task = TASK_MANAGER.get_task(id).ok_or(VfsError::NotFound)?;
+
+        Ok(writeln!(
+            out,
+            "Pid: {}\nName: {}\nParent: {}\nProcess: {}\nState: {}\nMode: {}\nNice: {}\nLevel: {}\nStart: {}\nTicks: {}\nExit code: {}\nOpen files: {}",
+            task.pid,
+            task.name(),
+            task.parent,
+            task.process,
+            task.state.name(),
+            if task.user { "user" } else { "kernel" },
+            task.nice,
+            task.level,
+            task.created,
+            task.ticks,
+            task.exit_code,
+            task.files.count()
+        ))
+    }
+}
+
+//scheduling policy selected at build time and its settings
+fn sched(out: &mut String) -> core::fmt::Result {
+    writeln!(out, "Policy: {}", scheduler::POLICY)?;
+    writeln!(out, "TimeSlices: {:?}", scheduler::TIME_SLICES)?;
+    writeln!(out, "BoostInterval: {}", scheduler::BOOST_INTERVAL)
+}
+
+//kernel heap and block cache usage
+fn meminfo(out: &mut String) -> core::fmt::Result {
+    //values are copied before writing, since writing to the string allocates on the heap
+    let (heap_used, cache_used, hits, misses) = unsafe {
+        let used = HEAP.acquire().used;
+        HEAP.free();
+
+        let cache = CACHE.acquire();
+        let cache_info = (cache.used(), cache.hits, cache.misses);
+        CACHE.free();
+
+        (used, cache_info.0, cache_info.1, cache_info.2)
+    };
+
+    writeln!(out, "HeapTotal: {} KiB", HEAP_SIZE / 1024)?;
+    writeln!(out, "HeapUsed: {} KiB", heap_used / 1024)?;
+    writeln!(out, "HeapFree: {} KiB", (HEAP_SIZE - heap_used) / 1024)?;
+    writeln!(out, "CacheBlocks: {}", cache_used)?;
+    writeln!(out, "CacheHits: {}", hits)?;
+    writeln!(out, "CacheMisses: {}", misses)
+}
+
+//how many times each interrupt has been handled
+fn interrupts(out: &mut String) -> core::fmt::Result {
+    unsafe {
+        for (int, count) in INTERRUPT_COUNTS.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+
+            let name = match int as u8 {
+                0x00 => "division error",
+                0x06 => "invalid opcode",
+                0x08 => "double fault",
+                0x0d => "general protection fault",
+                0x0e => "page fault",
+                TIMER_INT => "timer",
+                KEYBOARD_INT => "keyboard",
+                SYSCALL_INT => "syscall",
+                _ => "other",
+            };
+
+            writeln!(out, "{:>3}: {:>10} {}", int, count, name)?;
+        }
+
+        writeln!(out, "Ticks: {}", TICKS)
+    }
+}
+
+//mounted filesystems
+fn mounts(out: &mut String) -> core::fmt::Result {
+    unsafe {
+        for (path, name) in VFS.mounts() {
+            writeln!(out, "{} {}", name, path)?;
+        }
+    }
+
+    Ok(())
+}
+
+//detected ata disks
+fn disks(out: &mut String) -> core::fmt::Result {
+    unsafe {
+        for disk in [&DISK, &SECOND_DISK] {
+            if disk.enabled {
+                writeln!(
+                    out,
+                    "{}: {}, {} sectors ({} KiB), status {:X}",
+                    disk.name,
+                    disk.model(),
+                    disk.sectors,
+                    disk.sectors / 2,
+                    disk.status
+                )?;
+            } else {
+                writeln!(out, "{}: not present, status {:X}", disk.name, disk.status)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//parameters of fat volume
+fn fat(out: &mut String) -> core::fmt::Result {
+    unsafe {
+        if !DISK.enabled {
+            return Ok(());
+        }
+
+        let fat = FAT.acquire();
+
+        let header = fat.header;
+        let fat_type = fat.fat_type.name();
+        let clusters = fat.cluster_count();
+        let free = fat.free_clusters();
+        let label = fat.volume_label();
+
+        FAT.free();
+
+        writeln!(out, "Type: {}", fat_type)?;
+        writeln!(out, "BytesPerSector: {}", header.bytes_per_sector())?;
+        writeln!(out, "SectorsPerCluster: {}", header.sectors_per_cluster())?;
+        writeln!(out, "ReservedSectors: {}", header.reserved_sectors())?;
+        writeln!(out, "Fats: {}", header.fat_count())?;
+        writeln!(out, "SectorsPerFat: {}", header.sectors_per_fat())?;
+        writeln!(out, "RootEntries: {}", header.dir_entries_count())?;
+        writeln!(out, "TotalSectors: {}", header.total_sectors())?;
+        writeln!(out, "Clusters: {}", clusters)?;
+
+        if let Some(free) = free {
+            writeln!(out, "FreeClusters: {}", free)?;
+        }
+
+        write!(out, "Label: ")?;
+        for c in label {
+            out.push(c as char);
+        }
+        writeln!(out)
+    }
+}
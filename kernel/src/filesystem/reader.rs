@@ -0,0 +1,89 @@
+//FILE READER
+//Streams a file through a caller provided buffer, reading one chunk at time from the vfs
+
+use crate::filesystem::vfs::{VfsError, Vnode, VFS};
+
+pub struct FileReader<'a> {
+    vnode: Vnode,
+    offset: u32,
+    buffer: &'a mut [u8],
+    position: usize,
+    len: usize,
+}
+
+impl<'a> FileReader<'a> {
+    //open file at path, directories can't be streamed
+    pub fn open(path: &str, buffer: &'a mut [u8]) -> Result<Self, VfsError> {
+        let vnode = unsafe { VFS.lookup(path)? };
+
+        if vnode.is_directory() {
+            return Err(VfsError::IsDirectory);
+        }
+
+        Ok(FileReader {
+            vnode,
+            offset: 0,
+            buffer,
+            position: 0,
+            len: 0,
+        })
+    }
+
+    //get next chunk of file, none at end of file
+    pub fn chunk(&mut self) -> Result<Option<&[u8]>, VfsError> {
+        //return what is left in buffer before reading again
+        if self.position < self.len {
+            let chunk = &self.buffer[self.position..self.len];
+            self.position = self.len;
+            return Ok(Some(chunk));
+        }
+
+        self.fill()?;
+
+        if self.len == 0 {
+            return Ok(None);
+        }
+
+        self.position = self.len;
+        Ok(Some(&self.buffer[..self.len]))
+    }
+
+    //get next byte of file, none at end of file
+    pub fn next_byte(&mut self) -> Result<Option<u8>, VfsError> {
+        if self.position >= self.len {
+            self.fill()?;
+
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+
+        let byte = self.buffer[self.position];
+        self.position += 1;
+
+        Ok(Some(byte))
+    }
+
+    //go back to the start of the file
+    pub fn rewind(&mut self) {
+        self.offset = 0;
+        self.position = 0;
+        self.len = 0;
+    }
+
+    //offset in file of next byte returned by next_byte
+    pub fn offset(&self) -> u32 {
+        self.offset - (self.len - self.position) as u32
+    }
+
+    //read next part of file to buffer
+    fn fill(&mut self) -> Result<(), VfsError> {
+        let read = unsafe { VFS.read(&self.vnode, self.offset, self.buffer)? };
+
+        self.offset += read as u32;
+        self.position = 0;
+        self.len = read;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//TMPFS
+//Filesystem that lives in memory, its content is lost on reboot
+//Nodes and file data are allocated on the kernel heap, total data size and node count are limited
+
+use crate::filesystem::vfs::{DirEntry, FileSystem, Inode, InodeKind, VfsError, NAME_SIZE};
+use alloc::string::String;
+use alloc::vec::Vec;
+use libfelix::mutex::Mutex;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut TMPFS: Mutex<TmpFs> = Mutex::new(TmpFs {
+    nodes: Vec::new(),
+    size: 0,
+});
+
+//max bytes of file data
+const MAX_SIZE: usize = 0x0010_0000;
+
+//max files and directories, root included
+const MAX_NODES: usize = 256;
+
+const ROOT_ID: u32 = 0;
+
+struct Node {
+    name: String,
+    parent: u32,
+    kind: InodeKind,
+    data: Vec<u8>,
+}
+
+pub struct TmpFs {
+    //inode id is the index of the node, removed nodes leave a free slot
+    nodes: Vec<Option<Node>>,
+
+    //bytes used by file data
+    size: usize,
+}
+
+impl TmpFs {
+    //get node with given id
+    fn node(&mut self, id: u32) -> Result<&mut Node, VfsError> {
+        match self.nodes.get_mut(id as usize) {
+            Some(Some(node)) => Ok(node),
+            _ => Err(VfsError::NotFound),
+        }
+    }
+
+    fn inode(&mut self, id: u32) -> Result<Inode, VfsError> {
+        let node = self.node(id)?;
+
+        Ok(Inode {
+            id,
+            kind: node.kind,
+            size: node.data.len() as u32,
+        })
+    }
+
+    //iterate ids of nodes inside a directory
+    fn children(&self, dir: u32) -> impl Iterator<Item = (u32, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(id, node)| node.as_ref().map(|node| (id as u32, node)))
+            .filter(move |(id, node)| *id != ROOT_ID && node.parent == dir)
+    }
+
+    fn find(&self, dir: u32, name: &str) -> Option<u32> {
+        self.children(dir)
+            .find(|(_, node)| node.name == name)
+            .map(|(id, _)| id)
+    }
+
+    //check that inode is a directory of this filesystem
+    fn check_dir(&mut self, dir: &Inode) -> Result<(), VfsError> {
+        match self.node(dir.id)?.kind {
+            InodeKind::Directory => Ok(()),
+            InodeKind::File => Err(VfsError::NotDirectory),
+        }
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    //root node is created when filesystem is mounted
+    fn root(&mut self) -> Inode {
+        if self.nodes.is_empty() {
+            self.nodes.push(Some(Node {
+                name: String::new(),
+                parent: ROOT_ID,
+                kind: InodeKind::Directory,
+                data: Vec::new(),
+            }));
+        }
+
+        Inode {
+            id: ROOT_ID,
+            kind: InodeKind::Directory,
+            size: 0,
+        }
+    }
+
+    fn lookup(&mut self, dir: &Inode, name: &str) -> Result<Inode, VfsError> {
+        self.check_dir(dir)?;
+
+        let id = self.find(dir.id, name).ok_or(VfsError::NotFound)?;
+        self.inode(id)
+    }
+
+    fn read_dir(&mut self, dir: &Inode, index: usize) -> Result<Option<DirEntry>, VfsError> {
+        self.check_dir(dir)?;
+
+        let id = match self.children(dir.id).nth(index) {
+            Some((id, _)) => id,
+            None => return Ok(None),
+        };
+
+        let inode = self.inode(id)?;
+        let node = self.node(id)?;
+
+        Ok(Some(DirEntry::new(&node.name, inode)))
+    }
+
+    fn read(&mut self, inode: &Inode, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError> {
+        let node = self.node(inode.id)?;
+
+        let offset = offset as usize;
+        if offset >= node.data.len() {
+            return Ok(0);
+        }
+
+        let len = buffer.len().min(node.data.len() - offset);
+        buffer[..len].copy_from_slice(&node.data[offset..offset + len]);
+
+        Ok(len)
+    }
+
+    fn write(&mut self, inode: &Inode, offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        let available = MAX_SIZE - self.size;
+        let node = self.node(inode.id)?;
+
+        if node.kind == InodeKind::Directory {
+            return Err(VfsError::IsDirectory);
+        }
+
+        let offset = offset as usize;
+        let old_size = node.data.len();
+
+        //write only what fits in size limit, gap after end of file is filled with zeros
+        let max_end = old_size + available;
+        if offset >= max_end && !buffer.is_empty() {
+            return Err(VfsError::NoSpace);
+        }
+
+        let len = buffer.len().min(max_end.saturating_sub(offset));
+        let end = offset + len;
+
+        if end > old_size {
+            node.data
+                .try_reserve(end - old_size)
+                .map_err(|_| VfsError::NoSpace)?;
+            node.data.resize(end, 0);
+        }
+
+        node.data[offset..end].copy_from_slice(&buffer[..len]);
+
+        let new_size = node.data.len();
+        self.size += new_size - old_size;
+
+        Ok(len)
+    }
+
+    fn create(&mut self, dir: &Inode, name: &str, kind: InodeKind) -> Result<Inode, VfsError> {
+        self.check_dir(dir)?;
+
+        if name.is_empty() || name.len() > NAME_SIZE || name.contains('/') {
+            return Err(VfsError::InvalidPath);
+        }
+
+        if self.find(dir.id, name).is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let node = Node {
+            name: String::from(name),
+            parent: dir.id,
+            kind,
+            data: Vec::new(),
+        };
+
+        //reuse slots of removed nodes
+        let id = match self.nodes.iter().position(|n| n.is_none()) {
+            Some(id) => {
+                self.nodes[id] = Some(node);
+                id
+            }
+            None => {
+                if self.nodes.len() >= MAX_NODES {
+                    return Err(VfsError::NoSpace);
+                }
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+
+        self.inode(id as u32)
+    }
+
+    fn truncate(&mut self, inode: &Inode) -> Result<(), VfsError> {
+        let node = self.node(inode.id)?;
+
+        let freed = node.data.len();
+        node.data = Vec::new();
+
+        self.size -= freed;
+
+        Ok(())
+    }
+
+    fn remove(&mut self, dir: &Inode, name: &str) -> Result<(), VfsError> {
+        self.check_dir(dir)?;
+
+        let id = self.find(dir.id, name).ok_or(VfsError::NotFound)?;
+
+        if self.children(id).next().is_some() {
+            return Err(VfsError::NotEmpty);
+        }
+
+        if let Some(node) = self.nodes[id as usize].take() {
+            self.size -= node.data.len();
+        }
+
+        Ok(())
+    }
+
+    fn stat(&mut self, inode: &Inode) -> Result<Inode, VfsError> {
+        self.inode(inode.id)
+    }
+}
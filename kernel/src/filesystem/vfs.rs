@@ -0,0 +1,503 @@
+//VIRTUAL FILESYSTEM
+//Keeps a table of mounted filesystems and resolves paths crossing mount points
+//Each filesystem identifies its nodes with inodes, the vfs binds them to a mount creating vnodes
+
+use libfelix::mutex::Mutex;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut VFS: Vfs = Vfs {
+    mounts: [NO_MOUNT; MAX_MOUNTS],
+};
+
+const MAX_MOUNTS: usize = 8;
+
+//max directory depth while resolving a path
+const MAX_DEPTH: usize = 16;
+
+pub const NAME_SIZE: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InodeKind {
+    File,
+    Directory,
+}
+
+//node of a filesystem, id is chosen by the filesystem and must be unique inside it
+#[derive(Copy, Clone, Debug)]
+pub struct Inode {
+    pub id: u32,
+    pub kind: InodeKind,
+    pub size: u32,
+}
+
+//inode bound to the mount it belongs to
+#[derive(Copy, Clone, Debug)]
+pub struct Vnode {
+    pub mount: usize,
+    pub inode: Inode,
+}
+
+impl Vnode {
+    pub fn is_directory(&self) -> bool {
+        self.inode.kind == InodeKind::Directory
+    }
+
+    //check if two vnodes refer to the same node
+    pub fn same(&self, other: &Vnode) -> bool {
+        self.mount == other.mount && self.inode.id == other.inode.id
+    }
+}
+
+//directory entry returned when listing a directory
+#[derive(Copy, Clone)]
+pub struct DirEntry {
+    name: [u8; NAME_SIZE],
+    name_len: usize,
+    pub inode: Inode,
+}
+
+impl DirEntry {
+    pub fn new(name: &str, inode: Inode) -> Self {
+        let mut entry = DirEntry {
+            name: [0; NAME_SIZE],
+            name_len: 0,
+            inode,
+        };
+
+        for b in name.bytes().take(NAME_SIZE) {
+            entry.name[entry.name_len] = b;
+            entry.name_len += 1;
+        }
+
+        entry
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VfsError {
+    NotFound,
+    NotDirectory,
+    IsDirectory,
+    InvalidPath,
+    NotMounted,
+    MountTableFull,
+    AlreadyExists,
+    ReadOnly,
+    NoSpace,
+    NotEmpty,
+    Busy,
+}
+
+impl VfsError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            VfsError::NotFound => "No such file or directory",
+            VfsError::NotDirectory => "Not a directory",
+            VfsError::IsDirectory => "Is a directory",
+            VfsError::InvalidPath => "Invalid path",
+            VfsError::NotMounted => "No filesystem mounted",
+            VfsError::MountTableFull => "Mount table is full",
+            VfsError::AlreadyExists => "File exists",
+            VfsError::ReadOnly => "Read-only filesystem",
+            VfsError::NoSpace => "No space left on device",
+            VfsError::NotEmpty => "Directory not empty",
+            VfsError::Busy => "Device or resource busy",
+        }
+    }
+}
+
+//interface every filesystem has to implement to be mounted
+//write operations are optional, read-only filesystems can rely on the default ones
+pub trait FileSystem {
+    fn name(&self) -> &'static str;
+
+    //root directory of the filesystem
+    fn root(&mut self) -> Inode;
+
+    //search a name inside a directory
+    fn lookup(&mut self, dir: &Inode, name: &str) -> Result<Inode, VfsError>;
+
+    //get the nth entry of a directory, none if there are no more entries
+    fn read_dir(&mut self, dir: &Inode, index: usize) -> Result<Option<DirEntry>, VfsError>;
+
+    //read file content starting from offset, returns how many bytes have been read
+    fn read(&mut self, inode: &Inode, offset: u32, buffer: &mut [u8]) -> Result<usize, VfsError>;
+
+    //write file content starting from offset, returns how many bytes have been written
+    fn write(&mut self, _inode: &Inode, _offset: u32, _buffer: &[u8]) -> Result<usize, VfsError> {
+        Err(VfsError::ReadOnly)
+    }
+
+    //create a new file or directory inside a directory
+    fn create(&mut self, _dir: &Inode, _name: &str, _kind: InodeKind) -> Result<Inode, VfsError> {
+        Err(VfsError::ReadOnly)
+    }
+
+    //set file size to zero
+    fn truncate(&mut self, _inode: &Inode) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnly)
+    }
+
+    //delete a file or an empty directory from a directory
+    fn remove(&mut self, _dir: &Inode, _name: &str) -> Result<(), VfsError> {
+        Err(VfsError::ReadOnly)
+    }
+
+    //get updated inode, needed since size may change after a write
+    fn stat(&mut self, inode: &Inode) -> Result<Inode, VfsError> {
+        Ok(*inode)
+    }
+
+    //block current task until file has data to read, returns false if reads never block
+    fn wait(&mut self, _inode: &Inode) -> Result<bool, VfsError> {
+        Ok(false)
+    }
+}
+
+//mounted filesystem
+struct Mount {
+    path: [u8; NAME_SIZE],
+    path_len: usize,
+    fs: &'static mut Mutex<dyn FileSystem>,
+    root: Inode,
+
+    //filesystem name, saved so it can be shown without locking the filesystem
+    fs_name: &'static str,
+
+    //directory containing the mount point, none for root mount
+    parent: Option<Vnode>,
+}
+
+const NO_MOUNT: Option<Mount> = None;
+
+impl Mount {
+    fn path(&self) -> &str {
+        if self.path_len == 0 {
+            return "/";
+        }
+
+        core::str::from_utf8(&self.path[..self.path_len]).unwrap_or("?")
+    }
+
+    //last component of mount path
+    fn name(&self) -> &str {
+        let path = self.path();
+        match path.rfind('/') {
+            Some(i) => &path[i + 1..],
+            None => path,
+        }
+    }
+}
+
+pub struct Vfs {
+    mounts: [Option<Mount>; MAX_MOUNTS],
+}
+
+impl Vfs {
+    //mount a filesystem on given path, root must be mounted first
+    pub fn mount(
+        &mut self,
+        path: &str,
+        fs: &'static mut Mutex<dyn FileSystem>,
+    ) -> Result<(), VfsError> {
+        if !path.starts_with('/') || path.len() > NAME_SIZE {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let path = path.trim_end_matches('/');
+
+        //mount point must be inside an existing directory
+        let parent = if path.is_empty() {
+            if self.mounts[0].is_some() {
+                return Err(VfsError::AlreadyExists);
+            }
+            None
+        } else {
+            if self.lookup(path).is_ok() {
+                return Err(VfsError::AlreadyExists);
+            }
+
+            let parent_path = &path[..path.rfind('/').unwrap_or(0)];
+            let parent = self.lookup(parent_path)?;
+            if !parent.is_directory() {
+                return Err(VfsError::NotDirectory);
+            }
+            Some(parent)
+        };
+
+        let slot = self
+            .mounts
+            .iter()
+            .position(|m| m.is_none())
+            .ok_or(VfsError::MountTableFull)?;
+
+        let filesystem = fs.acquire_mut();
+        let root = filesystem.root();
+        let fs_name = filesystem.name();
+        fs.free();
+
+        let mut mount = Mount {
+            path: [0; NAME_SIZE],
+            path_len: path.len(),
+            fs,
+            root,
+            fs_name,
+            parent,
+        };
+        mount.path[..path.len()].copy_from_slice(path.as_bytes());
+
+        self.mounts[slot] = Some(mount);
+
+        Ok(())
+    }
+
+    //iterate mount table, returning mount path and filesystem name
+    pub fn mounts(&self) -> impl Iterator<Item = (&str, &'static str)> {
+        self.mounts
+            .iter()
+            .flatten()
+            .map(|mount| (mount.path(), mount.fs_name))
+    }
+
+    //print mount table
+    pub fn list_mounts(&self) {
+        for (path, name) in self.mounts() {
+            libfelix::println!("{} on {}", name, path);
+        }
+    }
+
+    //run given function on the filesystem of a mount, holding its lock
+    fn with_fs<R>(
+        &mut self,
+        mount: usize,
+        f: impl FnOnce(&mut dyn FileSystem) -> Result<R, VfsError>,
+    ) -> Result<R, VfsError> {
+        let mount = self.mounts[mount].as_mut().ok_or(VfsError::NotMounted)?;
+
+        let result = f(mount.fs.acquire_mut());
+        mount.fs.free();
+
+        result
+    }
+
+    //get mount point root if a filesystem is mounted on name inside directory
+    fn mounted_on(&self, dir: &Vnode, name: &str) -> Option<Vnode> {
+        for (i, mount) in self.mounts.iter().enumerate() {
+            if let Some(mount) = mount {
+                if let Some(parent) = mount.parent {
+                    if parent.same(dir) && mount.name() == name {
+                        return Some(Vnode {
+                            mount: i,
+                            inode: mount.root,
+                        });
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    //resolve a path to a vnode, relative paths start from root directory
+    pub fn lookup(&mut self, path: &str) -> Result<Vnode, VfsError> {
+        let root = match &self.mounts[0] {
+            Some(mount) => Vnode {
+                mount: 0,
+                inode: mount.root,
+            },
+            None => return Err(VfsError::NotMounted),
+        };
+
+        //keep track of visited directories to go back with ..
+        let mut stack = [root; MAX_DEPTH];
+        let mut depth = 0;
+
+        for component in path.split('/') {
+            match component {
+                "" | "." => continue,
+                ".." => {
+                    if depth > 0 {
+                        depth -= 1;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let current = stack[depth];
+
+            if !current.is_directory() {
+                return Err(VfsError::NotDirectory);
+            }
+
+            if depth + 1 >= MAX_DEPTH {
+                return Err(VfsError::InvalidPath);
+            }
+
+            //mount points hide what is below them
+            let next = match self.mounted_on(&current, component) {
+                Some(vnode) => vnode,
+                None => {
+                    let inode =
+                        self.with_fs(current.mount, |fs| fs.lookup(&current.inode, component))?;
+
+                    Vnode {
+                        mount: current.mount,
+                        inode,
+                    }
+                }
+            };
+
+            depth += 1;
+            stack[depth] = next;
+        }
+
+        Ok(stack[depth])
+    }
+
+    //get nth entry of a directory, mount points inside it are listed after its own entries
+    pub fn read_dir(&mut self, dir: &Vnode, index: usize) -> Result<Option<DirEntry>, VfsError> {
+        if !dir.is_directory() {
+            return Err(VfsError::NotDirectory);
+        }
+
+        if let Some(entry) = self.with_fs(dir.mount, |fs| fs.read_dir(&dir.inode, index))? {
+            return Ok(Some(entry));
+        }
+
+        //count entries of the directory itself, mount points come after them
+        let mut count = 0;
+        while count < index
+            && self
+                .with_fs(dir.mount, |fs| fs.read_dir(&dir.inode, count))?
+                .is_some()
+        {
+            count += 1;
+        }
+
+        let mut mount_index = count;
+        for mount in self.mounts.iter().flatten() {
+            if let Some(parent) = mount.parent {
+                if parent.same(dir) {
+                    if mount_index == index {
+                        return Ok(Some(DirEntry::new(mount.name(), mount.root)));
+                    }
+                    mount_index += 1;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    //read file from offset to buffer
+    pub fn read(
+        &mut self,
+        file: &Vnode,
+        offset: u32,
+        buffer: &mut [u8],
+    ) -> Result<usize, VfsError> {
+        if file.is_directory() {
+            return Err(VfsError::IsDirectory);
+        }
+
+        self.with_fs(file.mount, |fs| fs.read(&file.inode, offset, buffer))
+    }
+
+    //write buffer to file from offset
+    pub fn write(&mut self, file: &Vnode, offset: u32, buffer: &[u8]) -> Result<usize, VfsError> {
+        if file.is_directory() {
+            return Err(VfsError::IsDirectory);
+        }
+
+        self.with_fs(file.mount, |fs| fs.write(&file.inode, offset, buffer))
+    }
+
+    //set file size to zero
+    pub fn truncate(&mut self, file: &Vnode) -> Result<(), VfsError> {
+        if file.is_directory() {
+            return Err(VfsError::IsDirectory);
+        }
+
+        self.with_fs(file.mount, |fs| fs.truncate(&file.inode))
+    }
+
+    //block current task until file has data to read, returns false if reads never block
+    //task is only queued, it leaves the cpu at next task switch after the filesystem lock is released
+    pub fn wait(&mut self, file: &Vnode) -> Result<bool, VfsError> {
+        self.with_fs(file.mount, |fs| fs.wait(&file.inode))
+    }
+
+    //get updated vnode
+    pub fn stat(&mut self, node: &Vnode) -> Result<Vnode, VfsError> {
+        let inode = self.with_fs(node.mount, |fs| fs.stat(&node.inode))?;
+
+        Ok(Vnode {
+            mount: node.mount,
+            inode,
+        })
+    }
+
+    //create a file or directory at path
+    pub fn create(&mut self, path: &str, kind: InodeKind) -> Result<Vnode, VfsError> {
+        let (dir, name) = self.split(path)?;
+
+        if self.mounted_on(&dir, name).is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let inode = self.with_fs(dir.mount, |fs| fs.create(&dir.inode, name, kind))?;
+
+        Ok(Vnode {
+            mount: dir.mount,
+            inode,
+        })
+    }
+
+    //delete file or empty directory at path, mount points can't be removed
+    pub fn remove(&mut self, path: &str) -> Result<(), VfsError> {
+        let (dir, name) = self.split(path)?;
+
+        if self.mounted_on(&dir, name).is_some() {
+            return Err(VfsError::Busy);
+        }
+
+        //directories with mount points inside are busy too
+        let node = self.lookup(path)?;
+        for mount in self.mounts.iter().flatten() {
+            if let Some(parent) = mount.parent {
+                if parent.same(&node) {
+                    return Err(VfsError::Busy);
+                }
+            }
+        }
+
+        self.with_fs(dir.mount, |fs| fs.remove(&dir.inode, name))
+    }
+
+    //split path in parent directory vnode and last component name
+    fn split<'a>(&mut self, path: &'a str) -> Result<(Vnode, &'a str), VfsError> {
+        let path = path.trim_end_matches('/');
+
+        let (parent, name) = match path.rfind('/') {
+            Some(i) => (&path[..i], &path[i + 1..]),
+            None => ("", path),
+        };
+
+        if name.is_empty() || name == "." || name == ".." || name.len() > NAME_SIZE {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let dir = self.lookup(parent)?;
+        if !dir.is_directory() {
+            return Err(VfsError::NotDirectory);
+        }
+
+        Ok((dir, name))
+    }
+}
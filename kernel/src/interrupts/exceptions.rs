@@ -1,10 +1,24 @@
+use crate::interrupts::idt;
+use crate::interrupts::timer;
+use crate::memory::gdt::TSS;
+use crate::multitasking::task;
+use crate::multitasking::task::TASK_MANAGER;
 use core::arch::asm;
 
 //CPU EXCEPTIONS HANDLERS
+//Some exceptions push an error code, the others push a zero in its place so the handler always gets the same frame
+
+//exit code of tasks killed by a double fault, exceptions in user mode end tasks as a SEGV signal would
+const EXCEPTION_EXIT_CODE: i32 = -1;
+
+//page fault error code bits of a write to a present page
+const PAGE_FAULT_WRITE_PRESENT: u32 = 0b011;
 
 //handle excpetion based on interrupt number
 #[no_mangle]
-pub extern "C" fn exception_handler(int: u32, eip: u32, cs: u32, eflags: u32) {
+pub extern "C" fn exception_handler(int: u32, error: u32, eip: u32, cs: u32, eflags: u32) {
+    idt::count_interrupt(int as u8);
+
     match int {
         0x00 => {
             libfelix::println!("DIVISION ERROR!");
@@ -12,9 +26,6 @@ pub extern "C" fn exception_handler(int: u32, eip: u32, cs: u32, eflags: u32) {
         0x06 => {
             libfelix::println!("INVALID OPCODE!");
         }
-        0x08 => {
-            libfelix::println!("DOUBLE FAULT!");
-        }
         0x0D => {
             libfelix::println!("GENERAL PROTECTION FAULT!");
         }
@@ -28,7 +39,24 @@ pub extern "C" fn exception_handler(int: u32, eip: u32, cs: u32, eflags: u32) {
             libfelix::println!("EXCEPTION!");
         }
     }
-    libfelix::println!("EIP: {:X}, CS: {:X}, EFLAGS: {:b}", eip, cs, eflags);
+    libfelix::println!(
+        "EIP: {:X}, CS: {:X}, EFLAGS: {:b}, ERROR: {:X}",
+        eip,
+        cs,
+        eflags,
+        error
+    );
+
+    //exception raised in user mode only kills the task that caused it
+    if cs & 0b11 == 3 {
+        unsafe {
+            if let Some(task) = TASK_MANAGER.get_current_task() {
+                libfelix::println!("Task {} ({}) terminated!", task.pid, task.name());
+            }
+        }
+
+        task::exit(libfelix::signal::exit_code(libfelix::signal::SEGV));
+    }
 
     loop {}
 }
@@ -37,9 +65,10 @@ pub extern "C" fn exception_handler(int: u32, eip: u32, cs: u32, eflags: u32) {
 pub extern "C" fn div_error() {
     unsafe {
         asm!(
+            "push 0",
             "push 0x00",
             "call exception_handler",
-            "add esp, 4",
+            "add esp, 8",
             "iretd",
             options(noreturn)
         );
@@ -50,47 +79,96 @@ pub extern "C" fn div_error() {
 pub extern "C" fn invalid_opcode() {
     unsafe {
         asm!(
+            "push 0",
             "push 0x06",
             "call exception_handler",
-            "add esp, 4",
+            "add esp, 8",
             "iretd",
             options(noreturn)
         );
     }
 }
 
+//double fault runs as a separate hardware task, returning from it switches back to the interrupted task
+//next double fault resumes this task after iretd, so the handler is called in a loop
 #[naked]
 pub extern "C" fn double_fault() {
     unsafe {
         asm!(
-            "push 0x08",
-            "call exception_handler",
+            "2:",
+            "call double_fault_handler",
+            //drop error code pushed by cpu
             "add esp, 4",
             "iretd",
+            "jmp 2b",
             options(noreturn)
         );
     }
 }
 
+//a task hitting the guard page below its kernel stack can't handle the page fault on that stack
+//so it ends up here, the task is killed and the interrupted task is replaced by the next one
+#[no_mangle]
+pub extern "C" fn double_fault_handler() {
+    idt::count_interrupt(0x08);
+
+    let address: u32;
+    unsafe {
+        asm!("mov {0:e}, cr2", out(reg) address);
+
+        if let Some(task) = TASK_MANAGER.get_current_task() {
+            if task.is_stack_guard(address) {
+                libfelix::println!("Task {} ({}) stack overflow!", task.pid, task.name());
+
+                TASK_MANAGER.exit_current_task(EXCEPTION_EXIT_CODE);
+                task::EXITED.wake_all();
+
+                //cpu loads interrupted task state from main tss when this task returns
+                let esp = timer::switch_task(0);
+                TSS.set_state(timer::resume_task as u32, esp);
+                return;
+            }
+        }
+    }
+
+    libfelix::println!("DOUBLE FAULT!");
+    libfelix::println!("ADDRESS: {:X}", address);
+
+    loop {}
+}
+
 #[naked]
 pub extern "C" fn general_protection_fault() {
     unsafe {
         asm!(
             "push 0x0d",
             "call exception_handler",
-            "add esp, 4",
+            "add esp, 8",
             "iretd",
             options(noreturn)
         );
     }
 }
 
+//page faults may be handled and the faulting instruction run again, so registers are saved
+//error code, eip, cs and eflags are copied as arguments of the handler
 #[naked]
 pub extern "C" fn page_fault() {
     unsafe {
         asm!(
-            "push 0x0e",
-            "call exception_handler",
+            "push eax",
+            "push ecx",
+            "push edx",
+            "push dword ptr [esp + 24]",
+            "push dword ptr [esp + 24]",
+            "push dword ptr [esp + 24]",
+            "push dword ptr [esp + 24]",
+            "call page_fault_handler",
+            "add esp, 16",
+            "pop edx",
+            "pop ecx",
+            "pop eax",
+            //drop error code pushed by cpu
             "add esp, 4",
             "iretd",
             options(noreturn)
@@ -98,13 +176,34 @@ pub extern "C" fn page_fault() {
     }
 }
 
+//writes to copy on write pages of current task give it its own copy of the page, then the write is retried
+//any other page fault is a real exception
+#[no_mangle]
+pub extern "C" fn page_fault_handler(error: u32, eip: u32, cs: u32, eflags: u32) {
+    let address: u32;
+    unsafe {
+        asm!("mov {0:e}, cr2", out(reg) address);
+
+        if error & PAGE_FAULT_WRITE_PRESENT == PAGE_FAULT_WRITE_PRESENT
+            && TASK_MANAGER.copy_on_write(address)
+        {
+            idt::count_interrupt(0x0e);
+            return;
+        }
+    }
+
+    libfelix::println!("ADDRESS: {:X}", address);
+    exception_handler(0x0e, error, eip, cs, eflags);
+}
+
 #[naked]
 pub extern "C" fn generic_handler() {
     unsafe {
         asm!(
+            "push 0",
             "push 0xff",
             "call exception_handler",
-            "add esp, 4",
+            "add esp, 8",
             "iretd",
             options(noreturn)
         );
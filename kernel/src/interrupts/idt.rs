@@ -1,6 +1,7 @@
 //INTERRUPT DESCRIPTOR TABLE
 
 use crate::interrupts::exceptions;
+use crate::memory::gdt::DOUBLE_FAULT_TSS_SELECTOR;
 use core::arch::asm;
 use core::mem::size_of;
 
@@ -12,6 +13,37 @@ pub static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable {
 
 const IDT_ENTRIES: usize = 256;
 
+//how many times each interrupt has been handled
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut INTERRUPT_COUNTS: [u32; IDT_ENTRIES] = [0; IDT_ENTRIES];
+
+//increment counter of given interrupt, called by handlers
+pub fn count_interrupt(int: u8) {
+    unsafe {
+        INTERRUPT_COUNTS[int as usize] = INTERRUPT_COUNTS[int as usize].wrapping_add(1);
+    }
+}
+
+//disable interrupts, returns true if they were enabled
+pub fn disable_interrupts() -> bool {
+    let flags: u32;
+    unsafe {
+        asm!("pushfd", "pop {0:e}", "cli", out(reg) flags);
+    }
+
+    flags & 0x200 != 0
+}
+
+//enable interrupts again if they were enabled before disable_interrupts
+pub fn restore_interrupts(enabled: bool) {
+    if enabled {
+        unsafe {
+            asm!("sti");
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
 pub struct IdtEntry {
@@ -44,6 +76,19 @@ impl InterruptDescriptorTable {
         self.entries[int].set(handler);
     }
 
+    //add interrupt that can be raised from user mode too, like system calls
+    pub fn add_user(&mut self, int: usize, handler: u32) {
+        self.entries[int].set(handler);
+        self.entries[int].flags |= 3 << 5;
+    }
+
+    //add interrupt handled by switching to another hardware task, described by the tss with given selector
+    pub fn add_task_gate(&mut self, int: usize, selector: u16) {
+        self.entries[int].set(0);
+        self.entries[int].segment_selector = selector;
+        self.entries[int].flags = 0x85; //present, ring 0, task gate
+    }
+
     //load idt using lidt instruction
     pub fn load(&self) {
         let descriptor = IdtDescriptor {
@@ -60,7 +105,7 @@ impl InterruptDescriptorTable {
     pub fn add_exceptions(&mut self) {
         self.add(0x0, exceptions::div_error as u32);
         self.add(0x6, exceptions::invalid_opcode as u32);
-        self.add(0x8, exceptions::double_fault as u32);
+        self.add_task_gate(0x8, DOUBLE_FAULT_TSS_SELECTOR);
         self.add(0xd, exceptions::general_protection_fault as u32);
         self.add(0xe, exceptions::page_fault as u32);
     }
@@ -1,18 +1,24 @@
 //TIMER INTERRUPT HANDLER
 //Used to trigger the cpu scheduler and to context switch
+//The kernel can also switch task right away raising the yield interrupt, which works the same way
 
 use crate::drivers::pic::PICS;
+use crate::interrupts::idt;
+use crate::multitasking::signal;
 use crate::multitasking::task::CPUState;
 use crate::multitasking::task::TASK_MANAGER;
 use core::arch::asm;
 
+use crate::memory::gdt::{GDT, TSS};
 use crate::memory::paging::PAGING;
-use crate::memory::paging::TABLES;
 
 pub const TIMER_INT: u8 = 32;
+pub const YIELD_INT: u8 = 0x81;
 
-const APP_TARGET: u32 = 0x00a0_0000;
-const APP_SIZE: u32 = 0x0001_0000;
+//timer ticks since boot
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut TICKS: u32 = 0;
 
 //TIMER IRQ
 #[naked]
@@ -55,17 +61,112 @@ pub extern "C" fn timer() {
 pub extern "C" fn timer_handler(esp: u32) -> u32 {
     //trigger scheduler and return the esp returned by scheduler
     unsafe {
-        let new_esp: u32 = TASK_MANAGER.schedule(esp as *mut CPUState) as u32;
-
-        let slot = TASK_MANAGER.get_current_slot();
-        let target = APP_TARGET + (slot as u32 * APP_SIZE);
+        TICKS = TICKS.wrapping_add(1);
+        idt::count_interrupt(TIMER_INT);
 
-        //map table 8 (0x02000000) to the address where the executable is loaded
-        TABLES[8].set(target);
-        PAGING.set_table(8, &TABLES[8]);
+        //the interrupted task used the whole tick, it keeps running until its time slice ends
+        //a task that keeps running still handles signals sent to it meanwhile
+        let new_esp = match TASK_MANAGER.tick() {
+            true => switch_task(esp),
+            false => {
+                signal::deliver(&mut *(esp as *mut CPUState), false);
+                esp
+            }
+        };
 
         PICS.end_interrupt(TIMER_INT);
 
         return new_esp;
     }
 }
+
+//YIELD INTERRUPT, same as timer irq but raised by software
+#[naked]
+pub extern "C" fn yield_interrupt() {
+    unsafe {
+        asm!(
+            //disable interrupts
+            "cli",
+            //save registers
+            "push ebp",
+            "push edi",
+            "push esi",
+            "push edx",
+            "push ecx",
+            "push ebx",
+            "push eax",
+            //call c function with esp as argument
+            "push esp",
+            "call yield_handler",
+            //set esp to return value of c func
+            "mov esp, eax",
+            //restore registers
+            "pop eax",
+            "pop ebx",
+            "pop ecx",
+            "pop edx",
+            "pop esi",
+            "pop edi",
+            "pop ebp",
+            //re-enable interrupts
+            "sti",
+            //return irq
+            "iretd",
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn yield_handler(esp: u32) -> u32 {
+    idt::count_interrupt(YIELD_INT);
+
+    switch_task(esp)
+}
+
+//save state of current task and switch to next one, returns its esp
+pub fn switch_task(esp: u32) -> u32 {
+    unsafe {
+        let new_esp: u32 = TASK_MANAGER.schedule(esp as *mut CPUState) as u32;
+
+        if let Some(task) = TASK_MANAGER.get_current_task() {
+            //map table 8 (0x02000000) to the memory where the executable is loaded
+            match &task.memory {
+                Some(memory) => PAGING.map_app(&memory.borrow()),
+                None => PAGING.unmap_app(),
+            }
+
+            //gs of user tasks points to their thread local storage
+            if task.user {
+                GDT.set_tls(task.tls);
+            }
+
+            //cpu switches to kernel stack of task when it's interrupted in user mode
+            TSS.esp0 = task.stack_top();
+
+            //a task interrupted in user mode handles its pending signals before resuming
+            signal::deliver(&mut *(new_esp as *mut CPUState), false);
+        }
+
+        new_esp
+    }
+}
+
+//restore registers saved on a task stack and return to it
+//used to resume a task when there is no interrupt handler to return from
+#[naked]
+pub extern "C" fn resume_task() {
+    unsafe {
+        asm!(
+            "pop eax",
+            "pop ebx",
+            "pop ecx",
+            "pop edx",
+            "pop esi",
+            "pop edi",
+            "pop ebp",
+            "iretd",
+            options(noreturn)
+        );
+    }
+}
@@ -16,13 +16,22 @@ mod syscalls;
 use core::arch::asm;
 use core::panic::PanicInfo;
 use drivers::disk::DISK;
+use drivers::disk::LINUX_PARTITION;
+use drivers::disk::PARTITIONS;
 use drivers::pic::PICS;
+use filesystem::devfs::DEVFS;
+use filesystem::ext2::EXT2;
+use filesystem::fat::FAT;
+use filesystem::procfs::PROCFS;
+use filesystem::tmpfs::TMPFS;
+use filesystem::vfs::VFS;
 use interrupts::idt::IDT;
 use memory::allocator::Allocator;
+use memory::allocator::HEAP;
+use memory::gdt::GDT;
 use memory::paging::PAGING;
 use shell::shell::SHELL;
 use syscalls::print::PRINTER;
-use filesystem::fat::FAT;
 
 use multitasking::task::TASK_MANAGER;
 
@@ -52,39 +61,87 @@ pub extern "C" fn _start() -> ! {
         PAGING.identity();
         PAGING.enable();
 
+        //setup kernel heap
+        HEAP.acquire_mut()
+            .init(memory::allocator::HEAP_START, memory::allocator::HEAP_SIZE);
+        HEAP.free();
+
         //bochs magic breakpoint
         asm!("xchg bx, bx");
 
+        //setup gdt with user segments and tss
+        GDT.init();
+        GDT.load();
+
         //setup idt
-        IDT.init(); //init idt  
-        IDT.add_exceptions(); //add CPU exceptions to idt 
+        IDT.init(); //init idt
+        IDT.add_exceptions(); //add CPU exceptions to idt
         IDT.add(
             interrupts::timer::TIMER_INT as usize,
             interrupts::timer::timer as u32,
-        ); //add timer interrupt to idt     
+        ); //add timer interrupt to idt
         IDT.add(
+            interrupts::timer::YIELD_INT as usize,
+            interrupts::timer::yield_interrupt as u32,
+        ); //add yield interrupt to idt, used by the kernel to switch task right away
+        IDT.add_user(
             syscalls::handler::SYSCALL_INT as usize,
             syscalls::handler::syscall as u32,
-        ); //add system call handler interrupt     
+        ); //add system call handler interrupt, callable from user mode
         IDT.add(
             drivers::keyboard::KEYBOARD_INT as usize,
             drivers::keyboard::keyboard as u32,
-        ); //add keyboard interrupt to idt   
+        ); //add keyboard interrupt to idt
         IDT.load(); //load idt
 
         //init programmable interrupt controllers
         PICS.init();
 
-        //enable ata disk if present
-        DISK.check();
+        //init drivers, they register their devices
+        drivers::device::init_drivers();
 
         //init filesystem
         if DISK.enabled {
             let fat = FAT.acquire_mut();
             fat.load_header();
             fat.load_table();
-            fat.load_entries();
             FAT.free();
+
+            //mount fat volume as root
+            if let Err(e) = VFS.mount("/", &mut FAT) {
+                libfelix::println!("[ERROR] Cannot mount root filesystem! {}", e.message());
+            }
+
+            //mount memory filesystem for temporary files
+            if let Err(e) = VFS.mount("/tmp", &mut TMPFS) {
+                libfelix::println!("[ERROR] Cannot mount /tmp! {}", e.message());
+            }
+
+            //mount ext2 volume of first linux partition
+            let linux = PARTITIONS
+                .iter()
+                .find(|p| p.kind == LINUX_PARTITION && p.sectors > 0);
+
+            if let Some(partition) = linux {
+                let result = EXT2.acquire_mut().load(partition.start);
+                EXT2.free();
+
+                if result.is_ok() {
+                    if let Err(e) = VFS.mount("/mnt", &mut EXT2) {
+                        libfelix::println!("[ERROR] Cannot mount /mnt! {}", e.message());
+                    }
+                }
+            }
+
+            //mount device filesystem
+            if let Err(e) = VFS.mount("/dev", &mut DEVFS) {
+                libfelix::println!("[ERROR] Cannot mount /dev! {}", e.message());
+            }
+
+            //mount kernel info filesystem
+            if let Err(e) = VFS.mount("/proc", &mut PROCFS) {
+                libfelix::println!("[ERROR] Cannot mount /proc! {}", e.message());
+            }
         }
 
         //print name, version and copyright
@@ -96,6 +153,9 @@ pub extern "C" fn _start() -> ! {
         //init multitasking
         TASK_MANAGER.init();
 
+        //start kernel threads
+        filesystem::cache::start_flusher();
+
         //bochs magic breakpoint
         asm!("xchg bx, bx");
 
@@ -1,13 +1,232 @@
+//KERNEL HEAP ALLOCATOR
+//Linked list allocator, free regions are kept in a list sorted by address
+//Adjacent free regions are merged back together when memory is freed
+
+use crate::interrupts::idt;
+use crate::memory::paging::PAGE_SIZE;
 use alloc::alloc::{GlobalAlloc, Layout};
+use core::mem;
+use core::ptr;
+use core::slice;
+use libfelix::mutex::Mutex;
+
+//heap lives in identity mapped memory after apps
+pub const HEAP_START: usize = 0x0100_0000;
+pub const HEAP_SIZE: usize = 0x0100_0000;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut HEAP: Mutex<Heap> = Mutex::new(Heap {
+    head: FreeRegion {
+        size: 0,
+        next: ptr::null_mut(),
+    },
+    used: 0,
+});
+
+//header written at the start of every free region
+struct FreeRegion {
+    size: usize,
+    next: *mut FreeRegion,
+}
+
+impl FreeRegion {
+    fn start(&self) -> usize {
+        self as *const FreeRegion as usize
+    }
+
+    fn end(&self) -> usize {
+        self.start() + self.size
+    }
+}
+
+//every allocation must be able to hold a free region header once freed
+const MIN_SIZE: usize = mem::size_of::<FreeRegion>();
+const MIN_ALIGN: usize = mem::align_of::<FreeRegion>();
+
+pub struct Heap {
+    //dummy region pointing to the first free one
+    head: FreeRegion,
+
+    //allocated bytes
+    pub used: usize,
+}
+
+impl Heap {
+    //make whole heap area free
+    pub fn init(&mut self, start: usize, size: usize) {
+        self.head.next = ptr::null_mut();
+        self.used = 0;
+
+        unsafe {
+            self.add_region(start, size);
+        }
+    }
+
+    //find a free region large enough and take memory from it
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Heap::adjust(layout);
+
+        unsafe {
+            let mut previous = &mut self.head as *mut FreeRegion;
+
+            while !(*previous).next.is_null() {
+                let region = (*previous).next;
+
+                let mut start = align_up((*region).start(), align);
+
+                //space left before allocation must fit a free region header
+                if start != (*region).start() && start - (*region).start() < MIN_SIZE {
+                    start = align_up((*region).start() + MIN_SIZE, align);
+                }
+
+                let end = start + size;
+                let region_start = (*region).start();
+                let region_end = (*region).end();
+
+                //space left after allocation must fit a free region header too
+                if end > region_end || (end != region_end && region_end - end < MIN_SIZE) {
+                    previous = region;
+                    continue;
+                }
+
+                //remove region from list and give back unused parts
+                (*previous).next = (*region).next;
+
+                if start > region_start {
+                    self.add_region(region_start, start - region_start);
+                }
+
+                if region_end > end {
+                    self.add_region(end, region_end - end);
+                }
+
+                self.used += size;
+
+                return start as *mut u8;
+            }
+        }
+
+        ptr::null_mut()
+    }
+
+    //give memory back to the free list
+    pub fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Heap::adjust(layout);
+
+        unsafe {
+            self.add_region(ptr as usize, size);
+        }
+
+        self.used -= size;
+    }
+
+    //insert region in the list keeping it sorted, merging it with its neighbours
+    unsafe fn add_region(&mut self, start: usize, size: usize) {
+        let mut previous = &mut self.head as *mut FreeRegion;
+
+        while !(*previous).next.is_null() && (*(*previous).next).start() < start {
+            previous = (*previous).next;
+        }
+
+        let next = (*previous).next;
+
+        let region = start as *mut FreeRegion;
+        region.write(FreeRegion { size, next });
+
+        //merge with next region
+        if !next.is_null() && (*region).end() == (*next).start() {
+            (*region).size += (*next).size;
+            (*region).next = (*next).next;
+        }
+
+        //merge with previous region, head is not a real region
+        if previous != &mut self.head as *mut FreeRegion && (*previous).end() == start {
+            (*previous).size += (*region).size;
+            (*previous).next = (*region).next;
+        } else {
+            (*previous).next = region;
+        }
+    }
+
+    //round size and alignment so that freed memory can hold a region header
+    fn adjust(layout: Layout) -> (usize, usize) {
+        let align = layout.align().max(MIN_ALIGN);
+        let size = align_up(layout.size().max(MIN_SIZE), MIN_ALIGN);
+
+        (size, align)
+    }
+}
+
+fn align_up(address: usize, align: usize) -> usize {
+    (address + align - 1) & !(align - 1)
+}
+
+//page aligned and zeroed memory taken from heap, given back when dropped
+//used for memory that is mapped or protected page by page, like task stacks and app memory
+pub struct PageBlock {
+    address: usize,
+    size: usize,
+}
+
+impl PageBlock {
+    //size is rounded up to whole pages, returns none if heap is full
+    pub fn new(size: usize) -> Option<PageBlock> {
+        let size = align_up(size.max(1), PAGE_SIZE as usize);
+        let layout = Layout::from_size_align(size, PAGE_SIZE as usize).ok()?;
+
+        let address = unsafe { alloc::alloc::alloc_zeroed(layout) } as usize;
+        if address == 0 {
+            return None;
+        }
+
+        Some(PageBlock { address, size })
+    }
+
+    pub fn address(&self) -> u32 {
+        self.address as u32
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size as u32
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.address as *const u8, self.size) }
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.address as *mut u8, self.size) }
+    }
+}
+
+impl Drop for PageBlock {
+    fn drop(&mut self) {
+        unsafe {
+            let layout = Layout::from_size_align_unchecked(self.size, PAGE_SIZE as usize);
+            alloc::alloc::dealloc(self.address as *mut u8, layout);
+        }
+    }
+}
 
 pub struct Allocator;
 
+//kernel threads allocate with interrupts enabled, an interrupt handler allocating while
+//the heap is locked would spin forever, so interrupts are disabled while holding it
 unsafe impl GlobalAlloc for Allocator {
-    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
-        0 as *mut u8
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let enabled = idt::disable_interrupts();
+        let ptr = HEAP.acquire_mut().allocate(layout);
+        HEAP.free();
+        idt::restore_interrupts(enabled);
+
+        ptr
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        panic!("PANIC")
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let enabled = idt::disable_interrupts();
+        HEAP.acquire_mut().deallocate(ptr, layout);
+        HEAP.free();
+        idt::restore_interrupts(enabled);
     }
 }
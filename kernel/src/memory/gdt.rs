@@ -0,0 +1,244 @@
+//GLOBAL DESCRIPTOR TABLE
+//Replaces the one loaded by the bootloader, adding user segments and a task state segment
+//Memory model is still flat, user segments differ from kernel ones only by their privilege level
+//Only the thread local storage segment, loaded in gs, has a base, changed for each user task
+//The task state segment tells the cpu which stack to use when an interrupt comes from user mode
+
+use crate::interrupts::exceptions;
+use core::arch::asm;
+use core::mem::size_of;
+
+const GDT_ENTRIES: usize = 8;
+
+//segment selectors, user ones have requested privilege level 3
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+pub const USER_CODE_SELECTOR: u16 = 0x18 | 3;
+pub const USER_DATA_SELECTOR: u16 = 0x20 | 3;
+const TSS_SELECTOR: u16 = 0x28;
+pub const DOUBLE_FAULT_TSS_SELECTOR: u16 = 0x30;
+pub const TLS_SELECTOR: u16 = 0x38 | 3;
+
+const TLS_ENTRY: usize = 7;
+
+//access bytes
+const KERNEL_CODE: u8 = 0x9a; //present, ring 0, code, readable
+const KERNEL_DATA: u8 = 0x92; //present, ring 0, data, writable
+const USER_CODE: u8 = 0xfa; //present, ring 3, code, readable
+const USER_DATA: u8 = 0xf2; //present, ring 3, data, writable
+const TSS_AVAILABLE: u8 = 0x89; //present, ring 0, 32bit available tss
+
+//4KiB granularity and 32bit segment
+const FLAT_FLAGS: u8 = 0b1100;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut GDT: GlobalDescriptorTable = GlobalDescriptorTable {
+    entries: [GdtEntry { entry: 0 }; GDT_ENTRIES],
+};
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+//double faults are handled by a separate hardware task with its own stack
+//so they can be handled even when the stack in use is broken, like after a kernel stack overflow
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+pub static mut DOUBLE_FAULT_TSS: TaskStateSegment = TaskStateSegment::new();
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+static mut DOUBLE_FAULT_STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct GdtEntry {
+    entry: u64,
+}
+
+impl GdtEntry {
+    //pack base, limit, access byte and flags in the weird layout the cpu wants
+    const fn new(base: u32, limit: u32, access: u8, flags: u8) -> Self {
+        let limit_low = (limit & 0xffff) as u64;
+        let limit_high = ((limit >> 16) & 0xf) as u64;
+        let base_low = (base & 0xff_ffff) as u64;
+        let base_high = (base >> 24) as u64;
+
+        GdtEntry {
+            entry: limit_low
+                | base_low << 16
+                | (access as u64) << 40
+                | limit_high << 48
+                | ((flags & 0xf) as u64) << 52
+                | base_high << 56,
+        }
+    }
+}
+
+#[repr(C, packed)]
+pub struct GlobalDescriptorTable {
+    entries: [GdtEntry; GDT_ENTRIES],
+}
+
+#[repr(C, packed)]
+pub struct GdtDescriptor {
+    size: u16,                            //gdt size
+    offset: *const GlobalDescriptorTable, //pointer to gdt
+}
+
+//only esp0 and ss0 are used by the main tss, hardware task switching is used only for double faults
+#[repr(C, packed)]
+pub struct TaskStateSegment {
+    link: u32,
+    pub esp0: u32,
+    ss0: u32,
+    esp1: u32,
+    ss1: u32,
+    esp2: u32,
+    ss2: u32,
+    cr3: u32,
+    eip: u32,
+    eflags: u32,
+    eax: u32,
+    ecx: u32,
+    edx: u32,
+    ebx: u32,
+    esp: u32,
+    ebp: u32,
+    esi: u32,
+    edi: u32,
+    es: u32,
+    cs: u32,
+    ss: u32,
+    ds: u32,
+    fs: u32,
+    gs: u32,
+    ldt: u32,
+    trap: u16,
+    iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    const fn new() -> Self {
+        TaskStateSegment {
+            link: 0,
+            esp0: 0,
+            ss0: KERNEL_DATA_SELECTOR as u32,
+            esp1: 0,
+            ss1: 0,
+            esp2: 0,
+            ss2: 0,
+            cr3: 0,
+            eip: 0,
+            eflags: 0,
+            eax: 0,
+            ecx: 0,
+            edx: 0,
+            ebx: 0,
+            esp: 0,
+            ebp: 0,
+            esi: 0,
+            edi: 0,
+            es: 0,
+            cs: 0,
+            ss: 0,
+            ds: 0,
+            fs: 0,
+            gs: 0,
+            ldt: 0,
+            trap: 0,
+            //io bitmap offset past the segment limit means no io port is allowed in user mode
+            iomap_base: size_of::<TaskStateSegment>() as u16,
+        }
+    }
+
+    //set state loaded when cpu switches to this task, it runs kernel code with interrupts disabled
+    pub fn set_state(&mut self, eip: u32, esp: u32) {
+        self.eip = eip;
+        self.esp = esp;
+        self.eflags = 0x2;
+        self.cs = KERNEL_CODE_SELECTOR as u32;
+        self.ss = KERNEL_DATA_SELECTOR as u32;
+        self.ds = USER_DATA_SELECTOR as u32;
+        self.es = USER_DATA_SELECTOR as u32;
+        self.fs = USER_DATA_SELECTOR as u32;
+        self.gs = TLS_SELECTOR as u32;
+    }
+}
+
+impl GlobalDescriptorTable {
+    //fill entries, tss entries need tss addresses so they can't be built at compile time
+    pub fn init(&mut self) {
+        let tss_base = unsafe { &TSS as *const TaskStateSegment as u32 };
+        let double_fault_base = unsafe { &DOUBLE_FAULT_TSS as *const TaskStateSegment as u32 };
+        let tss_limit = size_of::<TaskStateSegment>() as u32 - 1;
+
+        //double fault task uses same page directory as the kernel
+        unsafe {
+            let stack_top =
+                &DOUBLE_FAULT_STACK as *const u8 as u32 + DOUBLE_FAULT_STACK_SIZE as u32;
+            DOUBLE_FAULT_TSS.set_state(exceptions::double_fault as u32, stack_top);
+            asm!("mov {0:e}, cr3", out(reg) DOUBLE_FAULT_TSS.cr3);
+        }
+
+        self.entries = [
+            GdtEntry { entry: 0 },
+            GdtEntry::new(0, 0xfffff, KERNEL_CODE, FLAT_FLAGS),
+            GdtEntry::new(0, 0xfffff, KERNEL_DATA, FLAT_FLAGS),
+            GdtEntry::new(0, 0xfffff, USER_CODE, FLAT_FLAGS),
+            GdtEntry::new(0, 0xfffff, USER_DATA, FLAT_FLAGS),
+            GdtEntry::new(tss_base, tss_limit, TSS_AVAILABLE, 0),
+            GdtEntry::new(double_fault_base, tss_limit, TSS_AVAILABLE, 0),
+            GdtEntry::new(0, 0xfffff, USER_DATA, FLAT_FLAGS),
+        ];
+    }
+
+    //move thread local storage segment to given base and reload gs, so the cpu sees the new base
+    pub fn set_tls(&mut self, base: u32) {
+        self.entries[TLS_ENTRY] = GdtEntry::new(base, 0xfffff, USER_DATA, FLAT_FLAGS);
+
+        unsafe {
+            asm!("mov gs, {0:x}", in(reg) TLS_SELECTOR);
+        }
+    }
+
+    //load gdt using lgdt instruction, then reload segment registers and task register
+    pub fn load(&self) {
+        let descriptor = GdtDescriptor {
+            size: (GDT_ENTRIES * size_of::<GdtEntry>() - 1) as u16, //calculate size of gdt
+            offset: self,                                           //pointer to gdt
+        };
+
+        unsafe {
+            asm!("lgdt [{0:e}]", in(reg) &descriptor);
+
+            //code segment can be reloaded only with a far jump or return
+            asm!(
+                "push {0:e}",
+                "lea {1:e}, [3f]",
+                "push {1:e}",
+                "retf",
+                "3:",
+                in(reg) KERNEL_CODE_SELECTOR as u32,
+                out(reg) _,
+            );
+
+            //data segment registers hold the user selector, so they stay valid when returning to user mode
+            //stack segment is switched by the cpu using tss
+            asm!(
+                "mov ds, {0:x}",
+                "mov es, {0:x}",
+                "mov fs, {0:x}",
+                "mov gs, {0:x}",
+                "mov ss, {1:x}",
+                in(reg) USER_DATA_SELECTOR,
+                in(reg) KERNEL_DATA_SELECTOR,
+            );
+
+            asm!("ltr {0:x}", in(reg) TSS_SELECTOR);
+        }
+    }
+}
@@ -1,4 +1,8 @@
+use crate::memory::allocator::PageBlock;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
 use core::arch::asm;
+use core::slice;
 
 pub static mut PAGING: PageDirectory = PageDirectory {
     //0b010 (supervisor, write, not present)
@@ -9,6 +13,26 @@ pub static mut TABLES: [PageTable; 16] = [NULL_TABLE; 16];
 
 pub static NULL_TABLE: PageTable = PageTable { entries: [0; 1024] };
 
+//table 8 maps virtual address 0x02000000, where apps are linked
+pub const APP_TABLE: usize = 8;
+pub const APP_VIRTUAL: u32 = 0x0200_0000;
+
+pub const PAGE_SIZE: u32 = 0x1000;
+
+//apps are mapped using a single table
+pub const MAX_APP_SIZE: u32 = 1024 * PAGE_SIZE;
+
+//page table entry bits
+const PRESENT: u32 = 1 << 0;
+const WRITABLE: u32 = 1 << 1;
+const USER: u32 = 1 << 2;
+
+//bit left to the os, marks read-only pages shared until one of their owners writes to them
+const COPY_ON_WRITE: u32 = 1 << 9;
+
+//directory entry of a table that is not there, supervisor and writable like the ones not used yet
+const NO_TABLE: u32 = 0x00000002;
+
 #[repr(align(4096))]
 pub struct PageDirectory {
     pub entries: [u32; 1024],
@@ -19,13 +43,52 @@ impl PageDirectory {
         self.entries[index] = (table as *const PageTable) as u32 | 0b011;
     }
 
+    //map app memory at 0x02000000, it's the only memory accessible from user mode
+    pub fn map_app(&mut self, memory: &AddressSpace) {
+        //0b111 (user, write, present), table entries decide which pages are really accessible
+        self.entries[APP_TABLE] = memory.table.address() | 0b111;
+
+        self.flush();
+    }
+
+    //remove app memory mapping, used when a kernel task runs
+    pub fn unmap_app(&mut self) {
+        self.entries[APP_TABLE] = NO_TABLE;
+
+        self.flush();
+    }
+
+    //make an identity mapped page not present or present again, used for stack guard pages
+    pub fn set_present(&mut self, address: u32, present: bool) {
+        let table = (address >> 22) as usize;
+        let page = ((address >> 12) & 0x3ff) as usize;
+
+        unsafe {
+            if present {
+                TABLES[table].entries[page] |= 1;
+            } else {
+                TABLES[table].entries[page] &= !1;
+            }
+
+            asm!("invlpg [{0:e}]", in(reg) address);
+        }
+    }
+
+    //reload cr3 so that cpu forgets cached translations
+    pub fn flush(&self) {
+        unsafe {
+            asm!("mov eax, cr3", "mov cr3, eax", out("eax") _);
+        }
+    }
+
+    //write protection is enabled for the kernel too, so its writes to user memory trigger copy on write
     pub fn enable(&self) {
         unsafe {
             let address = (self as *const PageDirectory) as u32;
 
             asm!("mov cr3, eax",
                 "mov eax, cr0",
-                "or eax, 0x80000001",
+                "or eax, 0x80010001",
                 "mov cr0, eax",
                 in("eax") address);
         }
@@ -56,3 +119,185 @@ impl PageTable {
         }
     }
 }
+
+//user memory of a process, mapped at 0x02000000 through a page table of its own
+//every page is a frame allocated on kernel heap, frames shared after a fork are counted by reference
+//and freed when the last address space using them goes away
+pub struct AddressSpace {
+    table: PageBlock,
+    frames: Vec<Rc<PageBlock>>,
+}
+
+impl AddressSpace {
+    //allocate zeroed writable pages for given size, returns none if there is not enough memory
+    pub fn new(size: u32) -> Option<AddressSpace> {
+        if size > MAX_APP_SIZE {
+            return None;
+        }
+
+        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let mut memory = AddressSpace {
+            table: PageBlock::new(PAGE_SIZE as usize)?,
+            frames: Vec::with_capacity(pages as usize),
+        };
+
+        for i in 0..pages as usize {
+            let frame = PageBlock::new(PAGE_SIZE as usize)?;
+            memory.entries_mut()[i] = frame.address() | USER | WRITABLE | PRESENT;
+            memory.frames.push(Rc::new(frame));
+        }
+
+        Some(memory)
+    }
+
+    pub fn size(&self) -> u32 {
+        self.frames.len() as u32 * PAGE_SIZE
+    }
+
+    fn entries(&self) -> &[u32] {
+        unsafe { slice::from_raw_parts(self.table.address() as *const u32, 1024) }
+    }
+
+    fn entries_mut(&mut self) -> &mut [u32] {
+        unsafe { slice::from_raw_parts_mut(self.table.address() as *mut u32, 1024) }
+    }
+
+    //page with given index, used while loading a program before the address space is shared
+    pub fn page_mut(&mut self, index: usize) -> Option<&mut [u8]> {
+        let frame = Rc::get_mut(self.frames.get_mut(index)?)?;
+
+        Some(frame.as_slice_mut())
+    }
+
+    //let the app write to page with given index or make it read-only, used for code and constants
+    pub fn set_writable(&mut self, index: usize, writable: bool) {
+        if index >= self.frames.len() {
+            return;
+        }
+
+        let entry = &mut self.entries_mut()[index];
+        match writable {
+            true => *entry |= WRITABLE,
+            false => *entry &= !WRITABLE,
+        }
+    }
+
+    //copy data to given offset, stops at the first page shared with other address spaces
+    pub fn write(&mut self, offset: u32, data: &[u8]) {
+        let mut position = offset as usize;
+        let mut written = 0;
+
+        while written < data.len() {
+            let page = position / PAGE_SIZE as usize;
+            let from = position % PAGE_SIZE as usize;
+
+            let frame = match self.frames.get_mut(page).and_then(Rc::get_mut) {
+                Some(frame) => frame,
+                None => return,
+            };
+
+            let len = (PAGE_SIZE as usize - from).min(data.len() - written);
+            frame.as_slice_mut()[from..from + len].copy_from_slice(&data[written..written + len]);
+
+            written += len;
+            position += len;
+        }
+    }
+
+    //make a copy sharing every page, writable pages become read-only and copy on write in both
+    pub fn fork(&mut self) -> Option<AddressSpace> {
+        let mut copy = AddressSpace {
+            table: PageBlock::new(PAGE_SIZE as usize)?,
+            frames: self.frames.clone(),
+        };
+
+        for i in 0..self.frames.len() {
+            let entry = &mut self.entries_mut()[i];
+            if *entry & WRITABLE != 0 {
+                *entry = (*entry & !WRITABLE) | COPY_ON_WRITE;
+            }
+
+            copy.entries_mut()[i] = *entry;
+        }
+
+        //this address space may be the one in use, the cpu could still allow writes to it
+        unsafe {
+            PAGING.flush();
+        }
+
+        Some(copy)
+    }
+
+    //entry of page containing given address, none if address is outside app memory
+    fn entry(&self, address: u32) -> Option<u32> {
+        if address < APP_VIRTUAL || address >= APP_VIRTUAL + self.size() {
+            return None;
+        }
+
+        Some(self.entries()[((address - APP_VIRTUAL) / PAGE_SIZE) as usize])
+    }
+
+    //check if the app can write at given address right now
+    pub fn is_writable(&self, address: u32) -> bool {
+        matches!(self.entry(address), Some(entry) if entry & (PRESENT | WRITABLE) == PRESENT | WRITABLE)
+    }
+
+    //check if given address is in a page that becomes writable once copied
+    pub fn is_copy_on_write(&self, address: u32) -> bool {
+        matches!(self.entry(address), Some(entry) if entry & COPY_ON_WRITE != 0)
+    }
+
+    //check that every page of range at given address is present, and writable if asked
+    //copy on write pages count as writable, since they are copied on first write
+    pub fn check_range(&self, address: u32, len: u32, write: bool) -> bool {
+        if len == 0 {
+            return true;
+        }
+
+        let last = match address.checked_add(len - 1) {
+            Some(last) => last,
+            None => return false,
+        };
+
+        (address / PAGE_SIZE..=last / PAGE_SIZE).all(|page| match self.entry(page * PAGE_SIZE) {
+            Some(entry) if entry & PRESENT != 0 => {
+                !write || entry & (WRITABLE | COPY_ON_WRITE) != 0
+            }
+            _ => false,
+        })
+    }
+
+    //handle a write to a read-only page, returns false if the page is not copy on write
+    //the page gets a copy of the frame, or the frame itself if nobody else is using it anymore
+    pub fn copy_on_write(&mut self, address: u32) -> bool {
+        if address < APP_VIRTUAL || address >= APP_VIRTUAL + self.size() {
+            return false;
+        }
+
+        let page = ((address - APP_VIRTUAL) / PAGE_SIZE) as usize;
+        let entry = self.entries_mut()[page];
+        if entry & COPY_ON_WRITE == 0 {
+            return false;
+        }
+
+        if Rc::strong_count(&self.frames[page]) > 1 {
+            let mut frame = match PageBlock::new(PAGE_SIZE as usize) {
+                Some(frame) => frame,
+                None => return false,
+            };
+
+            frame
+                .as_slice_mut()
+                .copy_from_slice(self.frames[page].as_slice());
+            self.frames[page] = Rc::new(frame);
+        }
+
+        self.entries_mut()[page] = self.frames[page].address() | USER | WRITABLE | PRESENT;
+
+        unsafe {
+            asm!("invlpg [{0:e}]", in(reg) address);
+        }
+
+        true
+    }
+}
@@ -0,0 +1,141 @@
+//KERNEL THREADS
+//Run a closure as a kernel task, for background jobs like flushing the disk cache
+//The closure is boxed on the heap and its pointer is passed in eax to a trampoline that calls it
+//Threads must be joined or detached, dropping the join handle detaches the thread
+
+use crate::interrupts::idt;
+use crate::interrupts::timer::TICKS;
+use crate::multitasking::task;
+use crate::multitasking::task::{TaskState, EXITED, TASK_MANAGER};
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::mem;
+use libfelix::signal;
+
+//exit code returned by join if the thread has been removed before exiting
+pub const REMOVED_EXIT_CODE: i32 = -1;
+
+type Closure = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct JoinHandle {
+    pid: u32,
+}
+
+impl JoinHandle {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    //wait until thread ends and free it, returns its exit code
+    //must be called by a task, since it blocks
+    pub fn join(self) -> i32 {
+        let pid = self.pid;
+        mem::forget(self);
+
+        //interrupts are disabled so the thread can't exit between the check and the sleep
+        let enabled = idt::disable_interrupts();
+
+        let exit_code = loop {
+            unsafe {
+                let slot = match TASK_MANAGER.find_task(pid) {
+                    Some(slot) => slot,
+                    None => break REMOVED_EXIT_CODE,
+                };
+
+                if let Some(task) = TASK_MANAGER.get_task(slot) {
+                    if task.state == TaskState::Zombie {
+                        let exit_code = task.exit_code;
+                        TASK_MANAGER.remove_task(slot);
+                        break exit_code;
+                    }
+                }
+
+                EXITED.sleep();
+            }
+        };
+
+        idt::restore_interrupts(enabled);
+
+        exit_code
+    }
+}
+
+//thread keeps running on its own and is freed as soon as it exits
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        unsafe {
+            TASK_MANAGER.detach(self.pid);
+        }
+    }
+}
+
+//run closure in a new kernel task, returns none if there is not enough memory
+pub fn spawn<F>(name: &str, f: F) -> Option<JoinHandle>
+where
+    F: FnOnce() + Send + 'static,
+{
+    //closure is a fat pointer, so it's boxed again to pass a single pointer
+    let closure: Box<Closure> = Box::new(Box::new(f));
+    let closure = Box::into_raw(closure);
+
+    let enabled = idt::disable_interrupts();
+    let pid = unsafe { TASK_MANAGER.spawn_thread(thread_entry as u32, closure as u32, name) };
+    idt::restore_interrupts(enabled);
+
+    match pid {
+        Some(pid) => Some(JoinHandle { pid }),
+        None => {
+            //task has not been created, closure is dropped here
+            unsafe {
+                drop(Box::from_raw(closure));
+            }
+            None
+        }
+    }
+}
+
+//signals asking a kernel thread to stop, it checks them itself since it has no handlers
+const STOP_SIGNALS: u32 = 1 << signal::TERM | 1 << signal::INT | 1 << signal::KILL;
+
+//check if current thread has been asked to stop, long running threads should call it now and then
+pub fn should_stop() -> bool {
+    unsafe {
+        TASK_MANAGER
+            .get_current_task()
+            .map_or(false, |task| task.pending & STOP_SIGNALS != 0)
+    }
+}
+
+//give cpu to next task
+pub fn yield_now() {
+    task::yield_now();
+}
+
+//block current task for given number of timer ticks, about 55ms each
+pub fn sleep(ticks: u32) {
+    let enabled = idt::disable_interrupts();
+
+    unsafe {
+        if TASK_MANAGER.sleep_current_task(TICKS.wrapping_add(ticks)) {
+            task::yield_now();
+        }
+    }
+
+    idt::restore_interrupts(enabled);
+}
+
+//thread starts here with closure pointer in eax
+#[naked]
+extern "C" fn thread_entry() {
+    unsafe {
+        asm!("push eax", "call thread_main", options(noreturn));
+    }
+}
+
+#[no_mangle]
+extern "C" fn thread_main(closure: u32) -> ! {
+    let closure = unsafe { Box::from_raw(closure as *mut Closure) };
+    closure();
+
+    task::exit(0)
+}
@@ -0,0 +1,373 @@
+//PROGRAM LOADER
+//Loads ELF executables from the filesystem into new app memory, used by the shell and by the exec system call
+//Each loadable segment is copied at its address, pages of segments that are not writable are read-only
+//App memory holds the segments followed by the stack, arguments and environment are copied at the top of the stack
+//They are laid out as System V does, argument count followed by argument, environment and auxiliary vector arrays
+//Entry point is called as a cdecl function getting argument count and array, it returns to a trampoline calling exit
+
+use crate::filesystem::vfs::{VfsError, Vnode, VFS};
+use crate::memory::paging::{AddressSpace, APP_VIRTUAL, MAX_APP_SIZE, PAGE_SIZE};
+use alloc::vec;
+use alloc::vec::Vec;
+use libfelix::syscall;
+
+//identification of 32 bit little endian executables for x86
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_CLASS_32: u8 = 1;
+const ELF_DATA_LSB: u8 = 1;
+const ELF_VERSION: u8 = 1;
+const ELF_TYPE_EXEC: u16 = 2;
+const ELF_MACHINE_386: u16 = 3;
+
+const ELF_HEADER_SIZE: usize = 52;
+const PROGRAM_HEADER_SIZE: usize = 32;
+
+//executables with more program headers are rejected
+const MAX_PROGRAM_HEADERS: usize = 16;
+
+//program header types and flags
+const PT_LOAD: u32 = 1;
+const PT_GNU_STACK: u32 = 0x6474e551;
+const PF_W: u32 = 2;
+
+//stack size of executables that don't set it in their stack program header
+const DEFAULT_APP_STACK_SIZE: u32 = 0x4000;
+
+//arguments and environment may take at most this part of the stack
+const MAX_ARGS_SIZE: usize = 0x1000;
+
+//auxiliary vector entry types
+const AT_NULL: u32 = 0;
+const AT_PAGESZ: u32 = 6;
+const AT_ENTRY: u32 = 9;
+
+//code put at the end of user stacks, app entry point returns to it and it calls exit with the returned value
+//mov ebx, eax; mov eax, EXIT; int 0x80
+const EXIT_TRAMPOLINE: [u8; 9] = [
+    0x89,
+    0xc3,
+    0xb8,
+    syscall::EXIT as u8,
+    (syscall::EXIT >> 8) as u8,
+    (syscall::EXIT >> 16) as u8,
+    (syscall::EXIT >> 24) as u8,
+    0xcd,
+    0x80,
+];
+
+//space taken by trampoline at the end of stack, keeping stack aligned
+const TRAMPOLINE_SIZE: usize = 12;
+
+//space taken on top of stack besides strings and pointers to them
+//trampoline, argument count, null pointers, auxiliary vector, entry point arguments and alignment
+const STACK_SETUP_SIZE: usize = 128;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoadError {
+    NotFound,
+    InvalidExecutable,
+    UnsupportedExecutable,
+    MalformedExecutable,
+    TooBig,
+    ArgumentsTooLong,
+    NoMemory,
+    Vfs(VfsError),
+}
+
+impl LoadError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            LoadError::NotFound => "Program not found",
+            LoadError::InvalidExecutable => "File is not an ELF executable",
+            LoadError::UnsupportedExecutable => "Executable is not a 32 bit x86 program",
+            LoadError::MalformedExecutable => "Executable is malformed",
+            LoadError::TooBig => "Program too big",
+            LoadError::ArgumentsTooLong => "Arguments too long",
+            LoadError::NoMemory => "Not enough memory",
+            LoadError::Vfs(e) => e.message(),
+        }
+    }
+}
+
+impl From<VfsError> for LoadError {
+    fn from(error: VfsError) -> Self {
+        LoadError::Vfs(error)
+    }
+}
+
+//program ready to run, memory is mapped at 0x02000000 when it runs
+pub struct Image {
+    pub memory: AddressSpace,
+    pub entry_point: u32,
+    pub stack: u32,
+}
+
+//loadable segment, the part of it past the end of file data is bss and stays zero
+struct Segment {
+    offset: u32,
+    address: u32,
+    file_size: u32,
+    memory_size: u32,
+    writable: bool,
+}
+
+impl Segment {
+    fn contains(&self, address: u32) -> bool {
+        address >= self.address && address - self.address < self.memory_size
+    }
+
+    //offset in app memory of first byte after the segment
+    fn end(&self) -> u32 {
+        self.address - APP_VIRTUAL + self.memory_size
+    }
+
+    //indexes of pages the segment is in
+    fn pages(&self) -> core::ops::Range<usize> {
+        let first = (self.address - APP_VIRTUAL) / PAGE_SIZE;
+        let last = (self.end() + PAGE_SIZE - 1) / PAGE_SIZE;
+
+        first as usize..last as usize
+    }
+}
+
+//load executable at path, giving it arguments and environment variables
+pub unsafe fn load(path: &str, args: &[&str], env: &[&str]) -> Result<Image, LoadError> {
+    let file = match VFS.lookup(path) {
+        Ok(file) if !file.is_directory() => file,
+        _ => return Err(LoadError::NotFound),
+    };
+
+    let mut header = [0u8; ELF_HEADER_SIZE];
+    if VFS.read(&file, 0, &mut header)? != ELF_HEADER_SIZE || header[..4] != ELF_MAGIC {
+        return Err(LoadError::InvalidExecutable);
+    }
+
+    if header[4] != ELF_CLASS_32
+        || header[5] != ELF_DATA_LSB
+        || header[6] != ELF_VERSION
+        || read_u16(&header, 16) != ELF_TYPE_EXEC
+        || read_u16(&header, 18) != ELF_MACHINE_386
+    {
+        return Err(LoadError::UnsupportedExecutable);
+    }
+
+    let entry_point = read_u32(&header, 24);
+    let (segments, stack_size) = read_program_headers(&file, &header)?;
+
+    //entry point must be in the program
+    if !segments.iter().any(|segment| segment.contains(entry_point)) {
+        return Err(LoadError::MalformedExecutable);
+    }
+
+    //strings with their terminator and pointers to them must fit in their part of the stack
+    let args_size: usize = args.iter().chain(env).map(|arg| arg.len() + 5).sum();
+    if args_size > MAX_ARGS_SIZE.min(stack_size as usize / 2) {
+        return Err(LoadError::ArgumentsTooLong);
+    }
+
+    //stack starts on the page after the last segment and takes whole pages
+    let end = segments.iter().map(Segment::end).max().unwrap_or(0);
+    let size = align_page(end as u64) + align_page(stack_size as u64);
+    if size > MAX_APP_SIZE as u64 {
+        return Err(LoadError::TooBig);
+    }
+
+    let mut memory = AddressSpace::new(size as u32).ok_or(LoadError::NoMemory)?;
+
+    for segment in segments.iter() {
+        load_segment(&file, &mut memory, segment)?;
+    }
+
+    //read-only segments are protected first, so a page shared with a writable segment stays writable
+    for segment in segments.iter().filter(|segment| !segment.writable) {
+        for page in segment.pages() {
+            memory.set_writable(page, false);
+        }
+    }
+
+    for segment in segments.iter().filter(|segment| segment.writable) {
+        for page in segment.pages() {
+            memory.set_writable(page, true);
+        }
+    }
+
+    //top of stack is prepared apart and then copied at the end of memory
+    let mut top = vec![0u8; (args_size + STACK_SETUP_SIZE) & !15];
+    let offset = memory.size() - top.len() as u32;
+    let stack = init_user_stack(&mut top, APP_VIRTUAL + offset, args, env, entry_point);
+    memory.write(offset, &top);
+
+    Ok(Image {
+        memory,
+        entry_point,
+        stack,
+    })
+}
+
+//read loadable segments and stack size from program headers, checking they fit in file and app memory
+unsafe fn read_program_headers(
+    file: &Vnode,
+    header: &[u8],
+) -> Result<(Vec<Segment>, u32), LoadError> {
+    let table_offset = read_u32(header, 28);
+    let entry_size = read_u16(header, 42) as usize;
+    let count = read_u16(header, 44) as usize;
+
+    if entry_size != PROGRAM_HEADER_SIZE || count == 0 || count > MAX_PROGRAM_HEADERS {
+        return Err(LoadError::MalformedExecutable);
+    }
+
+    let mut table = [0u8; PROGRAM_HEADER_SIZE * MAX_PROGRAM_HEADERS];
+    let table = &mut table[..count * PROGRAM_HEADER_SIZE];
+    if VFS.read(file, table_offset, table)? != table.len() {
+        return Err(LoadError::MalformedExecutable);
+    }
+
+    let file_size = file.inode.size as u64;
+    let app_end = APP_VIRTUAL as u64 + MAX_APP_SIZE as u64;
+
+    let mut segments = Vec::with_capacity(count);
+    let mut stack_size = DEFAULT_APP_STACK_SIZE;
+
+    for entry in table.chunks_exact(PROGRAM_HEADER_SIZE) {
+        match read_u32(entry, 0) {
+            PT_LOAD => {
+                let segment = Segment {
+                    offset: read_u32(entry, 4),
+                    address: read_u32(entry, 8),
+                    file_size: read_u32(entry, 16),
+                    memory_size: read_u32(entry, 20),
+                    writable: read_u32(entry, 24) & PF_W != 0,
+                };
+
+                if segment.file_size > segment.memory_size
+                    || segment.offset as u64 + segment.file_size as u64 > file_size
+                    || segment.address < APP_VIRTUAL
+                    || segment.address as u64 + segment.memory_size as u64 > app_end
+                {
+                    return Err(LoadError::MalformedExecutable);
+                }
+
+                if segment.memory_size > 0 {
+                    segments.push(segment);
+                }
+            }
+            PT_GNU_STACK if read_u32(entry, 20) > 0 => stack_size = read_u32(entry, 20),
+            _ => {}
+        }
+    }
+
+    if segments.is_empty() {
+        return Err(LoadError::MalformedExecutable);
+    }
+
+    Ok((segments, stack_size))
+}
+
+//copy file data of segment to its pages, using their physical address
+unsafe fn load_segment(
+    file: &Vnode,
+    memory: &mut AddressSpace,
+    segment: &Segment,
+) -> Result<(), LoadError> {
+    let mut loaded = 0;
+
+    while loaded < segment.file_size {
+        let offset = segment.address - APP_VIRTUAL + loaded;
+        let page = memory
+            .page_mut((offset / PAGE_SIZE) as usize)
+            .ok_or(LoadError::NoMemory)?;
+
+        let from = (offset % PAGE_SIZE) as usize;
+        let len = (PAGE_SIZE - from as u32).min(segment.file_size - loaded);
+
+        if VFS.read(
+            file,
+            segment.offset + loaded,
+            &mut page[from..from + len as usize],
+        )? != len as usize
+        {
+            return Err(LoadError::MalformedExecutable);
+        }
+
+        loaded += len;
+    }
+
+    Ok(())
+}
+
+fn align_page(size: u64) -> u64 {
+    (size + PAGE_SIZE as u64 - 1) & !(PAGE_SIZE as u64 - 1)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+//tasks are named after the file they have been loaded from
+pub fn program_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+//write exit trampoline, arguments, environment and auxiliary vector at the end of given memory
+//memory is the top of app stack as seen by the kernel, base is the address where it's mapped for the app
+//returns initial stack pointer, pointing at trampoline address followed by argument count and array
+fn init_user_stack(
+    memory: &mut [u8],
+    base: u32,
+    args: &[&str],
+    env: &[&str],
+    entry_point: u32,
+) -> u32 {
+    let trampoline = memory.len() - TRAMPOLINE_SIZE;
+    memory[trampoline..trampoline + EXIT_TRAMPOLINE.len()].copy_from_slice(&EXIT_TRAMPOLINE);
+
+    //null terminated strings, below trampoline
+    let mut top = trampoline;
+    let mut pointers = Vec::with_capacity(args.len() + env.len());
+
+    for string in args.iter().chain(env).rev() {
+        top -= string.len() + 1;
+        memory[top..top + string.len()].copy_from_slice(string.as_bytes());
+        memory[top + string.len()] = 0;
+        pointers.push(base + top as u32);
+    }
+
+    pointers.reverse();
+    let (arg_pointers, env_pointers) = pointers.split_at(args.len());
+
+    //argument count, null terminated argument and environment arrays, auxiliary vector
+    let mut words = Vec::with_capacity(pointers.len() + 9);
+    words.push(args.len() as u32);
+    words.extend_from_slice(arg_pointers);
+    words.push(0);
+    words.extend_from_slice(env_pointers);
+    words.push(0);
+    words.extend_from_slice(&[AT_PAGESZ, PAGE_SIZE, AT_ENTRY, entry_point, AT_NULL, 0]);
+
+    top = (top - words.len() * 4) & !15;
+    let argv = base + top as u32 + 4;
+    write_words(memory, top, &words);
+
+    //entry point finds trampoline as return address, followed by argument count and array
+    let words = [base + trampoline as u32, args.len() as u32, argv];
+    top -= words.len() * 4;
+    write_words(memory, top, &words);
+
+    base + top as u32
+}
+
+fn write_words(memory: &mut [u8], offset: usize, words: &[u32]) {
+    for (i, word) in words.iter().enumerate() {
+        memory[offset + i * 4..offset + i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+}
@@ -1 +1,6 @@
+pub mod kthread;
+pub mod loader;
+pub mod scheduler;
+pub mod signal;
 pub mod task;
+pub mod wait_queue;
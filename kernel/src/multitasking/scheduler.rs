@@ -0,0 +1,112 @@
+//SCHEDULING POLICIES
+//Decide which ready task runs next and for how long, the policy is chosen at build time with a cargo feature
+//round-robin: ready tasks take turns, each one running for the same time slice
+//priority: ready task with the lowest nice value runs, tasks with the same value take turns
+//mlfq: multilevel feedback queue, tasks using their whole time slice move to a lower level with a longer slice
+//      while tasks that block early, like the ones waiting for the keyboard, stay on top and run first
+
+use crate::multitasking::task::Task;
+
+#[cfg(not(any(feature = "round-robin", feature = "priority", feature = "mlfq")))]
+compile_error!(
+    "No scheduling policy selected! Enable one of round-robin, priority or mlfq features"
+);
+
+#[cfg(any(
+    all(feature = "round-robin", feature = "priority"),
+    all(feature = "round-robin", feature = "mlfq"),
+    all(feature = "priority", feature = "mlfq")
+))]
+compile_error!("More than one scheduling policy selected! Build with --no-default-features");
+
+//nice values like unix ones, lower values mean higher priority
+pub const MIN_NICE: i8 = -20;
+pub const MAX_NICE: i8 = 19;
+pub const DEFAULT_NICE: i8 = 0;
+
+//time slices in timer ticks, mlfq has one for each level, lower levels run less often but for longer
+#[cfg(not(feature = "mlfq"))]
+pub const TIME_SLICES: &[u32] = &[1];
+#[cfg(feature = "mlfq")]
+pub const TIME_SLICES: &[u32] = &[1, 2, 4, 8];
+
+//every task goes back to top level periodically, so cpu hogs can't starve (about 5 seconds)
+pub const BOOST_INTERVAL: u32 = 91;
+
+pub use policy::*;
+
+#[cfg(feature = "round-robin")]
+mod policy {
+    use super::*;
+
+    pub const POLICY: &str = "round-robin";
+
+    //ready task with the lowest key runs first
+    pub fn key(_task: &Task) -> i32 {
+        0
+    }
+
+    //ticks given to a task each time it's scheduled
+    pub fn time_slice(_task: &Task) -> u32 {
+        TIME_SLICES[0]
+    }
+
+    //task has used its whole time slice
+    pub fn expired(_task: &mut Task) {}
+
+    //periodic boost
+    pub fn boost(_task: &mut Task) {}
+}
+
+#[cfg(feature = "priority")]
+mod policy {
+    use super::*;
+
+    pub const POLICY: &str = "priority";
+
+    //ready task with the lowest key runs first
+    pub fn key(task: &Task) -> i32 {
+        task.nice as i32
+    }
+
+    //ticks given to a task each time it's scheduled
+    pub fn time_slice(_task: &Task) -> u32 {
+        TIME_SLICES[0]
+    }
+
+    //task has used its whole time slice
+    pub fn expired(_task: &mut Task) {}
+
+    //periodic boost
+    pub fn boost(_task: &mut Task) {}
+}
+
+#[cfg(feature = "mlfq")]
+mod policy {
+    use super::*;
+
+    pub const POLICY: &str = "mlfq";
+
+    //ready task with the lowest key runs first, level comes first and nice value breaks ties
+    pub fn key(task: &Task) -> i32 {
+        let nice_range = (MAX_NICE - MIN_NICE) as i32 + 1;
+        task.level as i32 * nice_range + (task.nice - MIN_NICE) as i32
+    }
+
+    //ticks given to a task each time it's scheduled
+    pub fn time_slice(task: &Task) -> u32 {
+        TIME_SLICES[task.level]
+    }
+
+    //task has used its whole time slice, it's cpu bound so it goes down a level
+    pub fn expired(task: &mut Task) {
+        if task.level < TIME_SLICES.len() - 1 {
+            task.level += 1;
+        }
+    }
+
+    //periodic boost, every task goes back to top level
+    pub fn boost(task: &mut Task) {
+        task.level = 0;
+    }
+}
@@ -0,0 +1,170 @@
+//SIGNALS
+//Signals interrupt a user task to run the handler it has set, or to end it when it has none
+//They are delivered only when a task is about to go back to user mode, so it's never ended while holding kernel locks
+//A handler runs on the user stack, below a frame saving the interrupted state, and returns to code calling sigreturn
+
+use crate::multitasking::task;
+use crate::multitasking::task::{CPUState, Task, TASK_MANAGER};
+use core::arch::asm;
+use core::mem::size_of;
+use core::ptr;
+use libfelix::signal::{CHLD, DEFAULT_HANDLER, IGNORE_HANDLER, KILL, MAX_SIGNAL, SEGV};
+use libfelix::syscall;
+
+//signal numbers start from 1, so tables have a slot more
+pub const SIGNALS: usize = MAX_SIGNAL as usize + 1;
+
+//code put in the frame, handler returns to it
+//mov eax, SIGRETURN; int 0x80; nop
+const SIGRETURN_TRAMPOLINE: [u8; 8] = [
+    0xb8,
+    syscall::SIGRETURN as u8,
+    (syscall::SIGRETURN >> 8) as u8,
+    (syscall::SIGRETURN >> 16) as u8,
+    (syscall::SIGRETURN >> 24) as u8,
+    0xcd,
+    0x80,
+    0x90,
+];
+
+//frame pushed on user stack: return address, signal number, saved state and trampoline
+const FRAME_SIZE: u32 = 8 + size_of::<CPUState>() as u32 + SIGRETURN_TRAMPOLINE.len() as u32;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    Ignore,
+    Terminate,
+    Handle(u32),
+}
+
+//what a user task does when it gets given signal, KILL can't be handled nor ignored
+pub fn action(task: &Task, signal: u32) -> Action {
+    match task.handlers[signal as usize] {
+        _ if signal == KILL => Action::Terminate,
+        DEFAULT_HANDLER if signal == CHLD => Action::Ignore,
+        DEFAULT_HANDLER => Action::Terminate,
+        IGNORE_HANDLER => Action::Ignore,
+        handler => Action::Handle(handler),
+    }
+}
+
+//handle pending signals of current task before it goes back to user mode with given state
+//the frame can be written to copy on write pages only when copying them is allowed, from system calls
+//otherwise the signal stays pending until the task can take it
+pub unsafe fn deliver(state: &mut CPUState, may_copy: bool) {
+    if !state.is_user() {
+        return;
+    }
+
+    let task = match TASK_MANAGER.get_current_task() {
+        Some(task) if task.user => task,
+        _ => return,
+    };
+
+    while task.pending != 0 {
+        let signal = task.pending.trailing_zeros();
+
+        let handler = match action(task, signal) {
+            Action::Ignore => {
+                task.pending &= !(1 << signal);
+                continue;
+            }
+            Action::Terminate => {
+                task.pending &= !(1 << signal);
+                terminate(state, signal);
+                return;
+            }
+            Action::Handle(handler) => handler,
+        };
+
+        let memory = match &task.memory {
+            Some(memory) => memory,
+            None => return,
+        };
+
+        //handler is entered with its argument aligned to 16 bytes, as if it had been called
+        let frame = (state.user_stack().wrapping_sub(FRAME_SIZE) & !15).wrapping_sub(4);
+        let last = frame.wrapping_add(FRAME_SIZE - 1);
+
+        let mut memory = memory.borrow_mut();
+        for address in [frame, last] {
+            if may_copy {
+                memory.copy_on_write(address);
+            } else if memory.is_copy_on_write(address) {
+                return;
+            }
+        }
+
+        task.pending &= !(1 << signal);
+
+        //there is no room for the frame, the stack is broken
+        if last < frame || !memory.is_writable(frame) || !memory.is_writable(last) {
+            terminate(state, SEGV);
+            return;
+        }
+
+        let trampoline = frame + FRAME_SIZE - SIGRETURN_TRAMPOLINE.len() as u32;
+        let words = frame as *mut u32;
+        words.write(trampoline);
+        words.add(1).write(signal);
+        ((frame + 8) as *mut CPUState).write(*state);
+        ptr::copy_nonoverlapping(
+            SIGRETURN_TRAMPOLINE.as_ptr(),
+            trampoline as *mut u8,
+            SIGRETURN_TRAMPOLINE.len(),
+        );
+
+        state.call_user(handler, frame);
+        return;
+    }
+}
+
+//make task end with the exit code of given signal as soon as it resumes
+//it leaves user mode to call exit by itself, so it never runs its own code again
+fn terminate(state: &mut CPUState, signal: u32) {
+    state.enter_kernel(
+        terminate_entry as u32,
+        libfelix::signal::exit_code(signal) as u32,
+    );
+}
+
+//SYSCALL SIGRETURN, end handler going back to the state saved in its frame
+//handler has already popped its return address, so the frame follows the signal number on top of stack
+//returns saved eax, so the interrupted code finds it unchanged
+pub unsafe fn sigreturn(state: &mut CPUState) -> u32 {
+    let saved = state.user_stack().wrapping_add(4);
+    let last = saved.wrapping_add(size_of::<CPUState>() as u32 - 1);
+
+    let valid = match TASK_MANAGER
+        .get_current_task()
+        .and_then(|task| task.memory.as_ref())
+    {
+        Some(memory) => {
+            let memory = memory.borrow();
+            last > saved && memory.is_writable(saved) && memory.is_writable(last)
+        }
+        None => false,
+    };
+
+    if !valid {
+        task::exit(libfelix::signal::exit_code(SEGV));
+    }
+
+    let saved = (saved as *const CPUState).read();
+    state.restore_user(&saved);
+
+    state.result()
+}
+
+//task starts here in kernel mode with exit code in eax
+#[naked]
+extern "C" fn terminate_entry() {
+    unsafe {
+        asm!("push eax", "call terminate_main", options(noreturn));
+    }
+}
+
+#[no_mangle]
+extern "C" fn terminate_main(exit_code: i32) -> ! {
+    task::exit(exit_code)
+}
@@ -1,17 +1,122 @@
 //TASK MANAGER
+//Tasks are allocated on kernel heap, so their number is limited only by available memory
+use crate::filesystem::file::{FileTable, EMPTY_FILE_TABLE};
+use crate::interrupts::timer::TICKS;
+use crate::memory::allocator::PageBlock;
+use crate::memory::gdt::{KERNEL_CODE_SELECTOR, USER_CODE_SELECTOR, USER_DATA_SELECTOR};
+use crate::memory::paging::{AddressSpace, PAGE_SIZE, PAGING};
+use crate::multitasking::loader::Image;
+use crate::multitasking::scheduler;
+use crate::multitasking::signal;
+use crate::multitasking::signal::{Action, SIGNALS};
+use crate::multitasking::wait_queue::WaitQueue;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
 use core::arch::asm;
+use core::cell::RefCell;
+use core::fmt;
+use core::fmt::Write;
 
-const STACK_SIZE: usize = 4096;
-const MAX_TASKS: i8 = 32;
+//kernel stack size of tasks when none is given, formatting with println needs quite some stack
+pub const DEFAULT_STACK_SIZE: usize = 0x4000;
 
-//each task has a 4KiB stack containg the cpu state in the bottom part of it
-#[derive(Copy, Debug, Clone)]
+const TASK_NAME_SIZE: usize = 16;
+
+//eflags bits a user task can set for itself, status flags, trap and direction
+const USER_FLAGS: u32 = 0xdd5;
+
+//tasks started by the kernel or by the shell have the kernel as parent, the idle task has its pid
+pub const KERNEL_PID: u32 = 0;
+
+//idle task is always in first slot, it runs only when no other task is ready
+const IDLE_SLOT: usize = 0;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+//tasks waiting for a child to exit
+pub static mut EXITED: WaitQueue = WaitQueue::new();
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TaskState {
+    //removed while running, freed once the cpu has switched to another task
+    Free,
+    Ready,
+    Running,
+    //waiting on a wait queue, not scheduled until woken up
+    Blocked,
+    //exited, kept until its exit code is collected
+    Zombie,
+}
+
+impl TaskState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskState::Free => "free",
+            TaskState::Ready => "ready",
+            TaskState::Running => "running",
+            TaskState::Blocked => "blocked",
+            TaskState::Zombie => "zombie",
+        }
+    }
+}
+
+//process control block
+//each task has its own kernel stack containg the cpu state in the bottom part of it
+//user threads are tasks sharing memory and files of the task that started the process, that task is the process leader
+//the page below the stack is a guard page, not present, so an overflow faults instead of corrupting other memory
+//user tasks have their own stack in user memory, this one is used only by the kernel while handling their interrupts
 pub struct Task {
-    pub stack: [u8; STACK_SIZE],
+    stack: PageBlock,       //guard page followed by stack
     pub cpu_state_ptr: u32, //pub cpu_state: *mut CPUState,
-    pub running: bool,
+    pub state: TaskState,
+    pub user: bool,
+    pub files: FileTable,
+
+    //memory mapped at 0x02000000 while a user task runs, shared by all threads of a process
+    pub memory: Option<Rc<RefCell<AddressSpace>>>,
+
+    //pid of process leader, the task itself unless it's a user thread
+    pub process: u32,
+
+    //base of the gs segment of a user task, where its thread local storage is
+    pub tls: u32,
+
+    //kernel threads are collected by their join handle, never by wait or the shell
+    pub thread: bool,
+
+    //detached tasks are freed as soon as they exit, nobody collects their exit code
+    pub detached: bool,
+
+    //tick when a sleeping task wakes up
+    wake_at: Option<u32>,
+
+    //signals sent to the task and not handled yet, a bit for each signal number
+    pub pending: u32,
+
+    //handler of each signal for user tasks, a function address or one of the default and ignore values
+    pub handlers: [u32; SIGNALS],
+
+    //pids are never reused, unlike slots
+    pub pid: u32,
+    pub parent: u32,
+    name: [u8; TASK_NAME_SIZE],
+    name_len: usize,
+
+    //timer tick when task was created and ticks it has been running for
+    pub created: u32,
+    pub ticks: u32,
+
+    //scheduling, level is used only by mlfq policy
+    pub nice: i8,
+    pub level: usize,
+    slice: u32, //ticks left of current time slice
+
+    //valid only for zombie tasks
+    pub exit_code: i32,
 }
 
+#[derive(Copy, Clone)]
 #[repr(C, packed)]
 pub struct CPUState {
     //manually pushed
@@ -23,7 +128,7 @@ pub struct CPUState {
     edi: u32,
     ebp: u32,
 
-    //automatically pushed by cpu
+    //automatically pushed by cpu, esp and ss only when coming from user mode
     eip: u32,
     cs: u32,
     eflags: u32,
@@ -31,27 +136,112 @@ pub struct CPUState {
     ss: u32,
 }
 
-static NULL_TASK: Task = Task {
-    stack: [0; STACK_SIZE],
-    cpu_state_ptr: 0 as u32, //cpu_state: 0 as *mut CPUState,
-    running: false,
-};
+impl CPUState {
+    //start user program from scratch at entry point with given stack
+    pub fn start_user(&mut self, entry_point: u32, stack: u32) {
+        self.eax = 0;
+        self.ebx = 0;
+        self.ecx = 0;
+        self.edx = 0;
+        self.esi = 0;
+        self.edi = 0;
+        self.ebp = 0;
+        self.eip = entry_point;
+        self.esp = stack;
+        self.eflags = 0x202;
+    }
 
-impl Task {
-    //setup task stack, zeroing its cpu state and setting entry point
-    pub fn init(&mut self, entry_point: u32) {
-        //mark task as running
-        self.running = true;
+    //check if the state was saved while running in user mode
+    pub fn is_user(&self) -> bool {
+        self.cs & 0b11 == 3
+    }
+
+    pub fn user_stack(&self) -> u32 {
+        self.esp
+    }
+
+    //value left in eax, the result of a system call
+    pub fn result(&self) -> u32 {
+        self.eax
+    }
+
+    pub fn set_result(&mut self, value: u32) {
+        self.eax = value;
+    }
+
+    //make a user task call function at given address when it goes back to user mode, using given stack
+    pub fn call_user(&mut self, function: u32, stack: u32) {
+        self.eip = function;
+        self.esp = stack;
 
-        //set cpu state pointer to the bottom part of its stack
-        let mut state = &self.stack as *const u8;
+        //direction flag is expected clear when a function is called
+        self.eflags &= !0x400;
+    }
+
+    //make a user task run given kernel function on its kernel stack, finding argument in eax
+    //the cpu does not pop user stack when returning to ring 0, so the function starts right below the saved state
+    pub fn enter_kernel(&mut self, entry_point: u32, argument: u32) {
+        self.eax = argument;
+        self.eip = entry_point;
+        self.cs = KERNEL_CODE_SELECTOR as u32;
+        self.eflags = 0x202;
+    }
+
+    //restore registers saved in user memory, segments are kept and only harmless flags are taken
+    pub fn restore_user(&mut self, saved: &CPUState) {
+        let (cs, ss) = (self.cs, self.ss);
+
+        *self = *saved;
+        self.cs = cs;
+        self.ss = ss;
+        self.eflags = (saved.eflags & USER_FLAGS) | 0x202;
+    }
+}
+
+impl Task {
+    //allocate task stack and setup its cpu state, returns none if there is not enough memory
+    //user tasks run in ring 3 using given stack, kernel tasks run in ring 0 on their own stack
+    fn new(entry_point: u32, user_stack: Option<u32>, stack_size: usize) -> Option<Task> {
+        let stack = PageBlock::new(PAGE_SIZE as usize + stack_size)?;
         unsafe {
-            state = state.byte_add(STACK_SIZE);
-            state = state.byte_sub(core::mem::size_of::<CPUState>());
+            PAGING.set_present(stack.address(), false);
         }
 
+        let mut task = Task {
+            stack,
+            cpu_state_ptr: 0,
+            state: TaskState::Ready,
+            user: user_stack.is_some(),
+            files: EMPTY_FILE_TABLE,
+            memory: None,
+            process: KERNEL_PID,
+            tls: 0,
+            thread: false,
+            detached: false,
+            wake_at: None,
+            pending: 0,
+            handlers: [libfelix::signal::DEFAULT_HANDLER; SIGNALS],
+            pid: 0,
+            parent: KERNEL_PID,
+            name: [0; TASK_NAME_SIZE],
+            name_len: 0,
+            created: unsafe { TICKS },
+            ticks: 0,
+            nice: scheduler::DEFAULT_NICE,
+            level: 0,
+            slice: 0,
+            exit_code: 0,
+        };
+
+        task.init(entry_point, user_stack);
+
+        Some(task)
+    }
+
+    //zero cpu state at the bottom part of stack and set entry point
+    fn init(&mut self, entry_point: u32, user_stack: Option<u32>) {
         //update cpu state pointer
-        self.cpu_state_ptr = state as u32;
+        self.cpu_state_ptr = self.stack_top() - core::mem::size_of::<CPUState>() as u32;
 
         let cpu_state = self.cpu_state_ptr as *mut CPUState;
 
@@ -68,191 +258,782 @@ impl Task {
             //set instruction pointer to entry point of task
             (*cpu_state).eip = entry_point;
 
-            //set code segment
-            (*cpu_state).cs = 0x8;
+            //set code segment, stack is set only for user tasks since cpu pops it only when changing ring
+            match user_stack {
+                Some(stack) => {
+                    (*cpu_state).cs = USER_CODE_SELECTOR as u32;
+                    (*cpu_state).esp = stack;
+                    (*cpu_state).ss = USER_DATA_SELECTOR as u32;
+                }
+                None => {
+                    (*cpu_state).cs = KERNEL_CODE_SELECTOR as u32;
 
-            //set eflags
+                    //kernel tasks keep running on this stack after iret, so the esp slot becomes their return address
+                    (*cpu_state).esp = kernel_task_return as u32;
+                }
+            }
+
+            //set eflags, interrupts enabled and io privilege level 0, so user tasks can't use io ports
             (*cpu_state).eflags = 0x202;
         }
     }
+
+    //set eax of a task that has not run yet, used to pass an argument to its entry point
+    fn set_argument(&mut self, value: u32) {
+        let cpu_state = self.cpu_state_ptr as *mut CPUState;
+
+        unsafe {
+            (*cpu_state).eax = value;
+        }
+    }
+
+    //address right after the end of task stack
+    pub fn stack_top(&self) -> u32 {
+        self.stack.address() + self.stack.size()
+    }
+
+    //check if address is in the guard page below task stack
+    pub fn is_stack_guard(&self, address: u32) -> bool {
+        address >= self.stack.address() && address < self.stack.address() + PAGE_SIZE
+    }
+
+    //names longer than the limit are truncated
+    fn set_name(&mut self, name: &str) {
+        let mut len = name.len().min(TASK_NAME_SIZE);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        self.name_len = len;
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+
+    //task can still run, now or in the future
+    pub fn is_alive(&self) -> bool {
+        matches!(
+            self.state,
+            TaskState::Ready | TaskState::Running | TaskState::Blocked
+        )
+    }
+}
+
+//guard page must be present again before its memory goes back to heap
+impl Drop for Task {
+    fn drop(&mut self) {
+        unsafe {
+            PAGING.set_present(self.stack.address(), true);
+        }
+    }
 }
 
 pub struct TaskManager {
-    tasks: [Task; MAX_TASKS as usize], //arry of tasks
-    task_count: i8,                    //how many tasks are in the queue
-    current_task: i8,                  //current running task
+    tasks: Vec<Option<Box<Task>>>, //task slots, none if free
+    task_count: usize,             //how many tasks are in the queue
+    current_task: Option<usize>,   //current running task
+    next_pid: u32,                 //pid given to next created task
 }
 
 //init null task manager
 pub static mut TASK_MANAGER: TaskManager = TaskManager {
-    tasks: [NULL_TASK; MAX_TASKS as usize],
+    tasks: Vec::new(),
     task_count: 0,
-    current_task: -1,
+    current_task: None,
+    next_pid: KERNEL_PID,
 };
 
 impl TaskManager {
     pub fn init(&mut self) {
-        self.add_task(idle as u32);
+        self.add_task(idle as u32, "idle");
     }
 
-    //add given task to next slot
-    pub fn add_task(&mut self, entry_point: u32) {
-        let free_slot = self.get_free_slot();
+    //put task in first free slot, giving it a pid, returns its pid
+    fn insert(&mut self, mut task: Task, name: &str) -> u32 {
+        //tasks removed while running can be freed now that they are not running anymore
+        self.free_removed();
+
+        let pid = self.next_pid;
+        self.next_pid += 1;
 
-        self.tasks[free_slot as usize].init(entry_point);
+        task.pid = pid;
+        task.set_name(name);
+
+        //a task is its own process, unless it's a thread of another one
+        if task.process == KERNEL_PID {
+            task.process = pid;
+        }
+
+        let task = Some(Box::new(task));
+        match self.tasks.iter().position(|slot| slot.is_none()) {
+            Some(slot) => self.tasks[slot] = task,
+            None => self.tasks.push(task),
+        }
 
         self.task_count += 1;
+
+        pid
     }
 
-    //remove task
-    pub fn remove_task(&mut self, id: usize) {
-        if id != 0 {
-            self.tasks[id] = NULL_TASK;
-            self.task_count -= 1;
+    //add kernel task with given stack size and argument in eax, returns its pid or none if there is not enough memory
+    pub fn spawn(
+        &mut self,
+        entry_point: u32,
+        argument: u32,
+        name: &str,
+        stack_size: usize,
+    ) -> Option<u32> {
+        match Task::new(entry_point, None, stack_size) {
+            Some(mut task) => {
+                task.set_argument(argument);
+                Some(self.insert(task, name))
+            }
+            None => {
+                libfelix::println!("[ERROR] Cannot start {}! Not enough memory", name);
+                None
+            }
         }
     }
 
-    pub fn remove_current_task(&mut self) {
-        self.remove_task(self.current_task as usize);
+    //add kernel thread running entry point with given argument, returns its pid
+    pub fn spawn_thread(&mut self, entry_point: u32, argument: u32, name: &str) -> Option<u32> {
+        let pid = self.spawn(entry_point, argument, name, DEFAULT_STACK_SIZE)?;
+
+        if let Some(task) = self.iter_mut().find(|task| task.pid == pid) {
+            task.thread = true;
+        }
+
+        Some(pid)
     }
 
-    //CPU SCHEDULER LOGIC
-    //triggers scheduler with round robin scheduling algorithm, returns new cpu state
-    pub fn schedule(&mut self, cpu_state: *mut CPUState) -> *mut CPUState {
-        //if no tasks return current state
-        if self.task_count <= 0 {
-            return cpu_state;
+    //add given kernel task
+    pub fn add_task(&mut self, entry_point: u32, name: &str) -> Option<u32> {
+        self.spawn(entry_point, 0, name, DEFAULT_STACK_SIZE)
+    }
+
+    //duplicate current user task giving it a copy of its memory and open files
+    //memory pages are shared copy on write, so they are really copied only when written
+    //the copy resumes from given cpu state, getting zero as result, returns its pid
+    pub fn fork_current_task(&mut self, state: &CPUState) -> Option<u32> {
+        let files = *self.current_files()?;
+        let current = self.get_current_task()?;
+        let copy = current.memory.as_ref()?.borrow_mut().fork()?;
+
+        let mut task = Task::new(0, Some(0), DEFAULT_STACK_SIZE)?;
+        unsafe {
+            let cpu_state = task.cpu_state_ptr as *mut CPUState;
+            *cpu_state = *state;
+            (*cpu_state).eax = 0;
         }
 
-        //save current state of current task
-        if self.current_task >= 0 {
-            self.tasks[self.current_task as usize].cpu_state_ptr = cpu_state as u32;
+        task.memory = Some(Rc::new(RefCell::new(copy)));
+        task.files = files;
+        task.parent = current.pid;
+        task.tls = current.tls;
+        task.nice = current.nice;
+        task.handlers = current.handlers;
+
+        let (name, name_len) = (current.name, current.name_len);
+        let name = core::str::from_utf8(&name[..name_len]).unwrap_or("?");
+
+        Some(self.insert(task, name))
+    }
+
+    //replace program of current task with given image, returns false if it's not the leader of a user process
+    //other threads of the process are freed, open files and pending signals are kept, handlers are reset
+    //new memory is mapped right away, since the task goes on running the new program
+    pub fn exec_current_task(&mut self, image: Image, name: &str) -> bool {
+        let process = match self.get_current_task() {
+            Some(task) if task.user && task.pid == task.process => task.process,
+            _ => return false,
+        };
+
+        self.remove_threads(process);
+
+        if let Some(task) = self.get_current_task() {
+            unsafe {
+                PAGING.map_app(&image.memory);
+            }
+
+            //old memory is freed only now that it's not mapped anymore
+            task.memory = Some(Rc::new(RefCell::new(image.memory)));
+            task.tls = 0;
+            task.handlers = [libfelix::signal::DEFAULT_HANDLER; SIGNALS];
+            task.set_name(name);
         }
 
-        self.current_task = self.get_next_task();
+        true
+    }
 
-        self.tasks[self.current_task as usize].cpu_state_ptr as *mut CPUState
+    //let current task write to a copy on write page at given address, returns false if it can't
+    pub fn copy_on_write(&mut self, address: u32) -> bool {
+        match self
+            .get_current_task()
+            .and_then(|task| task.memory.as_ref())
+        {
+            Some(memory) => memory.borrow_mut().copy_on_write(address),
+            None => false,
+        }
     }
 
-    pub fn get_next_task(&self) -> i8 {
-        let mut i = self.current_task + 1;
-        while i < MAX_TASKS {
-            let running = self.tasks[i as usize].running;
+    //add given user task running in given memory, its stack must be in that memory too
+    pub fn add_user_task(
+        &mut self,
+        entry_point: u32,
+        stack: u32,
+        memory: AddressSpace,
+        name: &str,
+    ) -> Option<u32> {
+        match Task::new(entry_point, Some(stack), DEFAULT_STACK_SIZE) {
+            Some(mut task) => {
+                task.memory = Some(Rc::new(RefCell::new(memory)));
+                Some(self.insert(task, name))
+            }
+            None => {
+                libfelix::println!("[ERROR] Cannot start {}! Not enough memory", name);
+                None
+            }
+        }
+    }
+
+    //add thread to the process of current user task, sharing its memory and files
+    //it starts at entry point with given user stack and thread local storage, finding its pid in eax
+    pub fn add_user_thread(&mut self, entry_point: u32, stack: u32, tls: u32) -> Option<u32> {
+        let current = self.get_current_task()?;
+        if !current.user {
+            return None;
+        }
+
+        let mut task = Task::new(entry_point, Some(stack), DEFAULT_STACK_SIZE)?;
+        task.memory = current.memory.clone();
+        task.process = current.process;
+        task.parent = current.process;
+        task.thread = true;
+        task.tls = tls;
+        task.nice = current.nice;
+        task.handlers = current.handlers;
+
+        let (name, name_len) = (current.name, current.name_len);
+        let name = core::str::from_utf8(&name[..name_len]).unwrap_or("?");
+
+        //insert gives it the next pid
+        task.set_argument(self.next_pid);
+
+        Some(self.insert(task, name))
+    }
+
+    //free task in given slot, a task removed while running is freed only after the cpu has left its stack
+    pub fn remove_task(&mut self, id: usize) {
+        if id == IDLE_SLOT {
+            return;
+        }
+
+        let task = match self.tasks.get_mut(id) {
+            Some(Some(task)) if task.state != TaskState::Free => task,
+            _ => return,
+        };
+
+        let (pid, process) = (task.pid, task.process);
 
-            if running {
-                return i;
+        if self.current_task == Some(id) {
+            task.state = TaskState::Free;
+            task.files = EMPTY_FILE_TABLE;
+        } else {
+            self.tasks[id] = None;
+        }
+
+        self.task_count -= 1;
+
+        //threads never outlive their process
+        if pid == process {
+            self.remove_threads(process);
+        }
+    }
+
+    //free all threads of a process, except its leader
+    fn remove_threads(&mut self, process: u32) {
+        for id in 0..self.tasks.len() {
+            let thread = match self.get_task(id) {
+                Some(task) => task.process == process && task.pid != process,
+                None => false,
+            };
+
+            if thread {
+                self.remove_task(id);
+            }
+        }
+    }
+
+    //free tasks removed while they were running
+    fn free_removed(&mut self) {
+        for (id, slot) in self.tasks.iter_mut().enumerate() {
+            let removed = matches!(slot, Some(task) if task.state == TaskState::Free);
+
+            if removed && self.current_task != Some(id) {
+                *slot = None;
             }
+        }
+    }
+
+    //iterate over tasks with their slot, skipping free ones
+    fn iter(&self) -> impl Iterator<Item = (usize, &Task)> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| match slot {
+                Some(task) if task.state != TaskState::Free => Some((id, task.as_ref())),
+                _ => None,
+            })
+    }
 
-            i = (i + 1) % MAX_TASKS;
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Task> {
+        self.tasks
+            .iter_mut()
+            .flatten()
+            .map(|task| task.as_mut())
+            .filter(|task| task.state != TaskState::Free)
+    }
+
+    //number of slots, some of them may be free
+    pub fn slots(&self) -> usize {
+        self.tasks.len()
+    }
+
+    //get slot of an exited child of parent, any child if pid is none
+    pub fn find_zombie(&self, parent: u32, pid: Option<u32>) -> Option<usize> {
+        self.iter()
+            .find(|(_, task)| {
+                task.state == TaskState::Zombie
+                    && !task.thread
+                    && task.parent == parent
+                    && pid.map_or(true, |pid| task.pid == pid)
+            })
+            .map(|(id, _)| id)
+    }
+
+    //check if parent has a child with given pid, any child if pid is none
+    pub fn has_child(&self, parent: u32, pid: Option<u32>) -> bool {
+        self.iter().any(|(_, task)| {
+            task.parent == parent
+                && task.pid != parent
+                && !task.thread
+                && pid.map_or(true, |pid| task.pid == pid)
+        })
+    }
+
+    //block current task, returns its pid or none if there is no task that can block
+    //idle task never blocks, so there is always something to schedule
+    pub fn block_current_task(&mut self) -> Option<u32> {
+        if self.current_task == Some(IDLE_SLOT) {
+            return None;
         }
 
-        -1
+        let task = self.get_current_task()?;
+        if task.state != TaskState::Running {
+            return None;
+        }
+
+        task.state = TaskState::Blocked;
+        Some(task.pid)
+    }
+
+    //block current task until given tick, returns false if it can't block
+    pub fn sleep_current_task(&mut self, wake_at: u32) -> bool {
+        if self.block_current_task().is_none() {
+            return false;
+        }
+
+        if let Some(task) = self.get_current_task() {
+            task.wake_at = Some(wake_at);
+        }
+
+        true
     }
 
-    pub fn get_free_slot(&self) -> i8 {
-        let mut slot: i8 = -1;
+    //free task with given pid as soon as it exits, or right now if it has already exited
+    pub fn detach(&mut self, pid: u32) {
+        if let Some(slot) = self.find_task(pid) {
+            if self.tasks[slot].as_ref().map(|task| task.state) == Some(TaskState::Zombie) {
+                self.remove_task(slot);
+            } else if let Some(Some(task)) = self.tasks.get_mut(slot) {
+                task.detached = true;
+            }
+        }
+    }
 
-        for i in 0..MAX_TASKS {
-            let running = self.tasks[i as usize].running;
-            if running == false {
-                slot = i as i8;
-                return slot;
+    //make blocked task with given pid ready again, returns false if there is no such task
+    //sleeping tasks are woken only by the timer
+    pub fn wake_task(&mut self, pid: u32) -> bool {
+        match self.iter_mut().find(|task| task.pid == pid) {
+            Some(task) if task.state == TaskState::Blocked && task.wake_at.is_none() => {
+                task.state = TaskState::Ready;
+                true
             }
+            _ => false,
+        }
+    }
+
+    //end the process of current task, its leader becomes a zombie keeping the exit code
+    //other threads of the process are freed, children are given to the kernel
+    pub fn exit_current_task(&mut self, exit_code: i32) {
+        if self.current_task == Some(IDLE_SLOT) {
+            return;
         }
 
-        slot
+        let process = match self.get_current_task() {
+            Some(task) if task.is_alive() => task.process,
+            _ => return,
+        };
+
+        self.remove_threads(process);
+
+        if let Some(leader) = self.find_task(process) {
+            self.end_task(leader, exit_code);
+        }
     }
 
-    pub fn get_current_slot(&self) -> i8 {
-        self.current_task
+    //end current thread only, ending the whole process if it's the leader
+    pub fn exit_current_thread(&mut self, exit_code: i32) {
+        let current = match self.get_current_task() {
+            Some(task) if task.is_alive() => task,
+            _ => return,
+        };
+
+        if current.pid == current.process {
+            self.exit_current_task(exit_code);
+        } else if let Some(id) = self.current_task {
+            self.end_task(id, exit_code);
+        }
     }
 
-    pub fn list_tasks(&self) {
-        libfelix::println!("Running tasks:");
+    //turn task into a zombie keeping its exit code, or free it if detached
+    //parent of a process is told with a CHLD signal, unless it's the kernel
+    fn end_task(&mut self, id: usize, exit_code: i32) {
+        let (pid, parent, thread) = match self.get_task(id) {
+            Some(task) => (task.pid, task.parent, task.thread),
+            None => return,
+        };
+
+        if parent != KERNEL_PID && !thread {
+            self.send_signal(parent, libfelix::signal::CHLD);
+        }
 
-        for i in 0..MAX_TASKS {
-            let running = self.tasks[i as usize].running;
-            if running {
-                libfelix::println!("ID: {}", i);
+        for task in self.iter_mut() {
+            if task.parent == pid {
+                task.parent = KERNEL_PID;
             }
         }
+
+        if let Some(Some(task)) = self.tasks.get_mut(id) {
+            if task.detached {
+                self.remove_task(id);
+                return;
+            }
+
+            task.state = TaskState::Zombie;
+            task.exit_code = exit_code;
+            task.files = EMPTY_FILE_TABLE;
+            task.wake_at = None;
+        }
     }
 
-    pub fn add_dummy_task_a(&mut self) {
-        self.add_task(task_a as u32);
+    //make signal pending for task with given pid, returns false if there is no such running task
+    //signals the task ignores are dropped right away, the others wake it up if it's blocked
+    //kernel tasks only get the pending bit, they check it themselves when they can stop
+    pub fn send_signal(&mut self, pid: u32, signal: u32) -> bool {
+        if signal == 0 || signal as usize >= SIGNALS {
+            return false;
+        }
+
+        let task = match self
+            .iter_mut()
+            .find(|task| task.pid == pid && task.is_alive())
+        {
+            Some(task) => task,
+            None => return false,
+        };
+
+        if task.user && signal::action(task, signal) == Action::Ignore {
+            return true;
+        }
+
+        task.pending |= 1 << signal;
+
+        if task.state == TaskState::Blocked {
+            task.wake_at = None;
+            task.state = TaskState::Ready;
+        }
+
+        true
     }
 
-    pub fn add_dummy_task_b(&mut self) {
-        self.add_task(task_b as u32);
+    //set handler of signal for every thread of the process of current task
+    pub fn set_signal_handler(&mut self, signal: u32, handler: u32) -> bool {
+        let process = match self.get_current_task() {
+            Some(task) if task.user => task.process,
+            _ => return false,
+        };
+
+        for task in self.iter_mut().filter(|task| task.process == process) {
+            task.handlers[signal as usize] = handler;
+        }
+
+        true
     }
 
-    pub fn add_dummy_task_c(&mut self) {
-        self.add_task(task_c as u32);
+    //check if current task has signals to handle, blocking system calls give up when it has
+    pub fn signal_pending(&mut self) -> bool {
+        self.get_current_task()
+            .map_or(false, |task| task.pending != 0)
     }
-}
 
-fn idle() {
-    loop {
-        unsafe {
-            asm!("hlt");
+    //get file table of current task, threads use the one of their process leader
+    pub fn current_files(&mut self) -> Option<&mut FileTable> {
+        let process = self.get_current_task()?.process;
+        let leader = self.find_task(process)?;
+
+        self.tasks
+            .get_mut(leader)?
+            .as_deref_mut()
+            .map(|task| &mut task.files)
+    }
+
+    //set nice value of task, clamped to valid range, returns the value set
+    pub fn set_nice(&mut self, id: usize, nice: i32) -> i8 {
+        let nice = nice.clamp(scheduler::MIN_NICE as i32, scheduler::MAX_NICE as i32) as i8;
+
+        if let Some(Some(task)) = self.tasks.get_mut(id) {
+            task.nice = nice;
         }
+
+        nice
     }
-}
 
-//EXAMPLE TASKS
-fn task_a() {
-    let mut a: u32 = 0;
-    let mut b: u8 = 0;
-    loop {
-        if a == 100_000_000 {
-            libfelix::println!("Process A running. {}% complete.", b);
-            a = 0;
-            b += 1;
-
-            if b == 100 {
-                libfelix::println!("Process A complete.");
-                break;
+    //get slot of task with given pid
+    pub fn find_task(&self, pid: u32) -> Option<usize> {
+        self.iter()
+            .find(|(_, task)| task.pid == pid)
+            .map(|(id, _)| id)
+    }
+
+    //CPU SCHEDULER LOGIC
+    //account a timer tick to current task, returns true if it's time to switch task
+    pub fn tick(&mut self) -> bool {
+        let ticks = unsafe { TICKS };
+
+        if ticks % scheduler::BOOST_INTERVAL == 0 {
+            for task in self.iter_mut().filter(|task| task.is_alive()) {
+                scheduler::boost(task);
+            }
+        }
+
+        //wake sleeping tasks whose time has come, comparison works even when ticks wrap around
+        for task in self.iter_mut() {
+            if let Some(wake_at) = task.wake_at {
+                if ticks.wrapping_sub(wake_at) as i32 >= 0 {
+                    task.wake_at = None;
+                    task.state = TaskState::Ready;
+                }
             }
         }
-        a += 1;
+
+        let current = match self.current_task {
+            Some(current) => current,
+            None => return true,
+        };
+
+        let next = self.get_next_task();
+
+        let task = match self.get_current_task() {
+            Some(task) if task.state == TaskState::Running => task,
+            _ => return true,
+        };
+
+        task.ticks += 1;
+
+        //idle task leaves the cpu as soon as some other task is ready
+        if current == IDLE_SLOT {
+            return next != IDLE_SLOT;
+        }
+
+        task.slice = task.slice.saturating_sub(1);
+        if task.slice == 0 {
+            scheduler::expired(task);
+            return true;
+        }
+
+        //a task that comes before current one may have become ready, woken up or boosted
+        let current_key = scheduler::key(task);
+        next != IDLE_SLOT
+            && self
+                .get_task(next)
+                .map_or(false, |next| scheduler::key(next) < current_key)
     }
-    loop {}
-}
 
-fn task_b() {
-    let mut a: u32 = 0;
-    let mut b: u8 = 0;
-    loop {
-        if a == 100_000_000 {
-            libfelix::println!("Process B running. {}% complete.", b);
-            a = 0;
-            b += 1;
-
-            if b == 100 {
-                libfelix::println!("Process B complete.");
-                break;
+    //triggers scheduler using the policy selected at build time, returns new cpu state
+    pub fn schedule(&mut self, cpu_state: *mut CPUState) -> *mut CPUState {
+        //if no tasks return current state
+        if self.task_count == 0 {
+            return cpu_state;
+        }
+
+        //save current state of current task, unless it has exited or has been removed
+        //a task that has just blocked keeps its state until woken up
+        if let Some(task) = self.get_current_task() {
+            match task.state {
+                TaskState::Running => {
+                    task.cpu_state_ptr = cpu_state as u32;
+                    task.state = TaskState::Ready;
+                }
+                TaskState::Blocked => task.cpu_state_ptr = cpu_state as u32,
+                _ => {}
+            }
+        }
+
+        self.current_task = Some(self.get_next_task());
+
+        let task = match self.get_current_task() {
+            Some(task) => task,
+            None => return cpu_state,
+        };
+
+        task.state = TaskState::Running;
+
+        //a task preempted before the end of its time slice keeps what is left of it
+        if task.slice == 0 {
+            task.slice = scheduler::time_slice(task);
+        }
+
+        task.cpu_state_ptr as *mut CPUState
+    }
+
+    //ready task with the lowest scheduling key, idle task if every other task is blocked or there are none
+    //search starts after current task, so tasks with the same key take turns
+    pub fn get_next_task(&self) -> usize {
+        let mut next = IDLE_SLOT;
+        let mut next_key = i32::MAX;
+
+        let count = self.tasks.len();
+        let start = self.current_task.unwrap_or(IDLE_SLOT);
+
+        for offset in 1..=count {
+            let i = (start + offset) % count;
+
+            if let Some(Some(task)) = self.tasks.get(i) {
+                if i != IDLE_SLOT && task.state == TaskState::Ready {
+                    let key = scheduler::key(task);
+                    if key < next_key {
+                        next = i;
+                        next_key = key;
+                    }
+                }
             }
         }
-        a += 1;
+
+        next
+    }
+
+    //get task currently running, none if multitasking has not started yet
+    pub fn get_current_task(&mut self) -> Option<&mut Task> {
+        let current = self.current_task?;
+
+        self.tasks.get_mut(current)?.as_deref_mut()
     }
+
+    //get task in given slot, none if slot is free
+    pub fn get_task(&self, id: usize) -> Option<&Task> {
+        match self.tasks.get(id) {
+            Some(Some(task)) if task.state != TaskState::Free => Some(task),
+            _ => None,
+        }
+    }
+
+    //write a table with a line for each task
+    pub fn write_tasks(&self, out: &mut dyn Write) -> fmt::Result {
+        writeln!(
+            out,
+            "PID   PPID  STATE    NICE  START     TICKS     EXIT  NAME"
+        )?;
+
+        for (_, task) in self.iter() {
+            write!(
+                out,
+                "{:<6}{:<6}{:<9}{:<6}{:<10}{:<10}",
+                task.pid,
+                task.parent,
+                task.state.name(),
+                task.nice,
+                task.created,
+                task.ticks
+            )?;
+
+            if task.state == TaskState::Zombie {
+                write!(out, "{:<6}", task.exit_code)?;
+            } else {
+                write!(out, "{:<6}", "-")?;
+            }
+
+            writeln!(out, "{}", task.name())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn list_tasks(&self) {
+        unsafe {
+            let _ = self.write_tasks(&mut libfelix::print::PRINTER);
+        }
+    }
+}
+
+//give cpu to next task right away, returns when current task is scheduled again
+pub fn yield_now() {
+    unsafe {
+        //yield interrupt
+        asm!("int 0x81");
+    }
+}
+
+//switch to next task after current one has exited, it's never scheduled again
+fn exit_now() -> ! {
+    yield_now();
+
     loop {}
 }
 
-fn task_c() {
-    let mut a: u32 = 0;
-    let mut b: u8 = 0;
+//end current task with given exit code, waking tasks waiting for their children
+pub fn exit(exit_code: i32) -> ! {
+    unsafe {
+        asm!("cli");
+        TASK_MANAGER.exit_current_task(exit_code);
+        EXITED.wake_all();
+    }
+
+    exit_now()
+}
+
+//end current thread with given exit code, the whole process if it's the process leader
+pub fn exit_thread(exit_code: i32) -> ! {
+    unsafe {
+        asm!("cli");
+        TASK_MANAGER.exit_current_thread(exit_code);
+        EXITED.wake_all();
+    }
+
+    exit_now()
+}
+
+//kernel tasks return here when their entry point ends
+fn kernel_task_return() -> ! {
+    exit(0)
+}
+
+fn idle() {
     loop {
-        if a == 100_000_000 {
-            libfelix::println!("Process C running. {}% complete.", b);
-            a = 0;
-            b += 1;
-
-            if b == 100 {
-                libfelix::println!("Process C complete.");
-                break;
-            }
+        unsafe {
+            asm!("hlt");
         }
-        a += 1;
     }
-    loop {}
 }
@@ -0,0 +1,60 @@
+//WAIT QUEUES
+//Tasks waiting for something (typed chars, a child exiting...) block on a queue and are not scheduled
+//Whoever makes the awaited thing happen, usually an interrupt handler, wakes the tasks in the queue
+//Woken tasks must check again what they were waiting for, since it may have been taken by another task
+
+use crate::interrupts::idt;
+use crate::multitasking::task;
+use crate::multitasking::task::TASK_MANAGER;
+use alloc::collections::VecDeque;
+
+pub struct WaitQueue {
+    //pids of waiting tasks, so a removed task whose slot is reused is never woken by mistake
+    tasks: VecDeque<u32>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue {
+            tasks: VecDeque::new(),
+        }
+    }
+
+    //block current task and add it to queue, it leaves the cpu at the next task switch
+    //used when the caller still holds something it must release before switching
+    //must run with interrupts disabled, otherwise the wake up may come before the task is in the queue
+    pub fn enqueue_current(&mut self) {
+        unsafe {
+            if let Some(pid) = TASK_MANAGER.block_current_task() {
+                self.tasks.push_back(pid);
+            }
+        }
+    }
+
+    //block current task until it's woken up
+    pub fn sleep(&mut self) {
+        let enabled = idt::disable_interrupts();
+
+        self.enqueue_current();
+        task::yield_now();
+
+        //restore interrupt flag as it was before sleeping
+        idt::restore_interrupts(enabled);
+    }
+
+    //wake oldest waiting task, returns false if no task was waiting
+    pub fn wake_one(&mut self) -> bool {
+        while let Some(pid) = self.tasks.pop_front() {
+            if unsafe { TASK_MANAGER.wake_task(pid) } {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    //wake every waiting task
+    pub fn wake_all(&mut self) {
+        while self.wake_one() {}
+    }
+}
@@ -1,40 +1,74 @@
 //SHELL
 
+use crate::drivers::disk::SECOND_DISK;
+use crate::drivers::disk::{Partition, DISK, FAT16_PARTITION, MAX_PARTITIONS, NO_PARTITION};
+use crate::filesystem::cache;
+use crate::filesystem::cache::CACHE;
+use crate::filesystem::fat::mkfs;
 use crate::filesystem::fat::FAT;
-use crate::multitasking::task::TASK_MANAGER;
+use crate::filesystem::reader::FileReader;
+use crate::filesystem::vfs::InodeKind;
+use crate::filesystem::vfs::VFS;
+use crate::multitasking::kthread;
+use crate::multitasking::loader;
+use crate::multitasking::task::{KERNEL_PID, TASK_MANAGER};
+use crate::syscalls::print::Redirect;
 use crate::syscalls::print::PRINTER;
 
-use crate::memory::paging::PAGING;
-use crate::memory::paging::TABLES;
-
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::arch::asm;
-
-const APP_TARGET: u32 = 0x00a0_0000;
-const APP_SIZE: u32 = 0x0001_0000;
-const APP_SIGNATURE: u32 = 0xB16B00B5;
+use libfelix::signal;
 
 const HELP: &'static str = "Available commands:
-ls - lists root directory entries
+ls <dir> - lists directory entries, root if no dir is given
 cat <file> - displays content of a file
+hexdump <file> - displays content of a file in hex format
+head <file> <n> - displays first n lines of a file, 10 if n is not given
+tail <file> <n> - displays last n lines of a file, 10 if n is not given
+mkdir <dir> - creates a directory
+rm <path> - removes a file or an empty directory
+echo <text> - displays text, use > or >> to write it to a file
+mount - lists mounted filesystems
+sync - writes cached data to disk
+fsck <-r> - checks FAT volume, repairs it if -r is given
+fdisk <n> <start> <sectors> <type> - sets partition n of second disk, lists partitions if n is not given
+mkfs.fat <n> <label> - formats partition n of second disk as FAT16
 test <a,b,c> - runs a dummy task
-run <file> - loads file as task and adds it to the task list
-ps - lists running tasks
-rt <id> - removes specified task";
+run <file> <args> - loads file as task with given arguments and adds it to the task list
+ps - lists tasks with their pid, parent, state, start tick, cpu ticks and exit code
+rt <pid> - kills specified task
+kill <pid> <signal> - sends a signal to a task, by name or number, TERM if not given
+nice <pid> <n> - sets nice value of a task, from -20 (highest priority) to 19 (lowest)
+Output of commands can be written to a file with > <file>, or appended with >> <file>
+Ctrl+C sends INT to the last task started with run";
 
 //Warning! Mutable static here
 //TODO: Implement a mutex to get safe access to this
 pub static mut SHELL: Shell = Shell {
     buffer: [0 as char; 256],
-    arg: [0 as char; 11],
+    arg: [0; 256],
     cursor: 0,
+    file_buffer: [0; FILE_BUFFER_SIZE],
+    foreground: None,
 };
 
 const PROMPT: &str = "felix> ";
 
+//environment variables of programs started by the shell
+const ENVIRONMENT: [&str; 2] = ["HOME=/", "PATH=/"];
+
+const FILE_BUFFER_SIZE: usize = 512;
+const DEFAULT_LINES: usize = 10;
+
 pub struct Shell {
     buffer: [char; 256],
-    arg: [char; 11],
+    arg: [u8; 256],
     cursor: usize,
+    file_buffer: [u8; FILE_BUFFER_SIZE],
+
+    //pid of the last task started with run, interrupted by ctrl+c
+    foreground: Option<u32>,
 }
 
 impl Shell {
@@ -71,6 +105,19 @@ impl Shell {
         }
     }
 
+    //ctrl+c, interrupt foreground task and discard command line
+    pub fn interrupt(&mut self) {
+        libfelix::println!("^C");
+
+        if let Some(pid) = self.foreground {
+            unsafe {
+                TASK_MANAGER.send_signal(pid, signal::INT);
+            }
+        }
+
+        self.init();
+    }
+
     //shell enter
     pub fn enter(&mut self) {
         //e9 port hack, new line
@@ -82,10 +129,38 @@ impl Shell {
             PRINTER.new_line();
         }
 
-        self.interpret();
+        //redirection is removed before the command is interpreted
+        if unsafe { self.start_redirect() } {
+            self.interpret();
+
+            unsafe {
+                PRINTER.redirect = None;
+            }
+        }
+
+        self.report_exited();
+
         self.init();
     }
 
+    //print exit code of tasks started by the shell that have exited, freeing their slots
+    fn report_exited(&mut self) {
+        unsafe {
+            while let Some(slot) = TASK_MANAGER.find_zombie(KERNEL_PID, None) {
+                if let Some(task) = TASK_MANAGER.get_task(slot) {
+                    libfelix::println!(
+                        "Task {} ({}) exited with code {}",
+                        task.pid,
+                        task.name(),
+                        task.exit_code
+                    );
+                }
+
+                TASK_MANAGER.remove_task(slot);
+            }
+        }
+    }
+
     //command interpreter
     #[allow(unused_unsafe)]
     fn interpret(&mut self) {
@@ -95,10 +170,70 @@ impl Shell {
                 libfelix::println!("PONG!");
             }
 
-            //list root directory
+            //list directory
             _b if self.is_command("ls") => unsafe {
-                FAT.acquire().list_entries();
-                FAT.free();
+                self.ls();
+            },
+
+            //create directory
+            _b if self.is_command("mkdir") => unsafe {
+                let path = self.get_arg(1);
+
+                if let Err(e) = VFS.create(path, InodeKind::Directory) {
+                    libfelix::println!("{}!", e.message());
+                }
+            },
+
+            //remove file or empty directory
+            _b if self.is_command("rm") => unsafe {
+                let path = self.get_arg(1);
+
+                if let Err(e) = VFS.remove(path) {
+                    libfelix::println!("{}!", e.message());
+                }
+            },
+
+            //print text
+            b if self.is_command("echo") => {
+                for c in b.iter().take(self.cursor).skip(5) {
+                    libfelix::print!("{}", c);
+                }
+                libfelix::println!();
+            }
+
+            //list mounted filesystems
+            _b if self.is_command("mount") => unsafe {
+                VFS.list_mounts();
+            },
+
+            //check fat volume
+            _b if self.is_command("fsck") => unsafe {
+                self.fsck();
+            },
+
+            //partition second disk
+            _b if self.is_command("fdisk") => unsafe {
+                self.fdisk();
+            },
+
+            //format partition of second disk
+            _b if self.is_command("mkfs.fat") => unsafe {
+                self.mkfs();
+            },
+
+            //write dirty cached blocks to disk
+            _b if self.is_command("sync") => unsafe {
+                let count = cache::flush();
+
+                let cache = CACHE.acquire_mut();
+                libfelix::println!(
+                    "{} blocks written. Cache: {} blocks used, {} hits, {} misses",
+                    count,
+                    cache.used(),
+                    cache.hits,
+                    cache.misses
+                );
+                CACHE.free();
             },
 
             //list running tasks
@@ -106,49 +241,83 @@ impl Shell {
                 TASK_MANAGER.list_tasks();
             },
 
-            //remove runing task
-            b if self.is_command("rt") => unsafe {
-                if (b[3] as u8) < 0x30 {
-                    libfelix::println!("No task id provided!");
-                    return;
+            //kill running task, it ends as soon as it goes back to user mode
+            _b if self.is_command("rt") => unsafe {
+                let pid = match self.get_arg(1).parse::<u32>() {
+                    Ok(pid) => pid,
+                    Err(_) => {
+                        libfelix::println!("No task id provided!");
+                        return;
+                    }
+                };
+
+                if pid == KERNEL_PID || !TASK_MANAGER.send_signal(pid, signal::KILL) {
+                    libfelix::println!("Task not found!");
                 }
+            },
 
-                //convert first char of arg to id
-                let id = ((b[3] as u8) - 0x30) as usize;
+            //send signal to task
+            _b if self.is_command("kill") => unsafe {
+                self.kill();
+            },
 
-                TASK_MANAGER.remove_task(id);
-                //TASK_MANAGER.remove_current_task();
+            //set nice value of a task
+            _b if self.is_command("nice") => unsafe {
+                self.nice();
             },
 
             //display content of file
-            b if self.is_command("cat") => unsafe {
-                self.cat(&b);
+            _b if self.is_command("cat") => unsafe {
+                self.cat();
+            },
+
+            //display content of file in hex format
+            _b if self.is_command("hexdump") => unsafe {
+                self.hexdump();
+            },
+
+            //display first lines of file
+            _b if self.is_command("head") => unsafe {
+                self.head();
+            },
+
+            //display last lines of file
+            _b if self.is_command("tail") => unsafe {
+                self.tail();
             },
 
             //jump to specified program
-            b if self.is_command("run") => unsafe {
-                self.run(&b);
+            _b if self.is_command("run") => unsafe {
+                self.run();
             },
 
             //run test task
-            b if self.is_command("test") => unsafe {
+            b if self.is_command("test") => {
                 let a = b[5];
 
-                match a {
-                    'a' => {
-                        TASK_MANAGER.add_dummy_task_a();
-                    }
-                    'b' => {
-                        TASK_MANAGER.add_dummy_task_b();
-                    }
-                    'c' => {
-                        TASK_MANAGER.add_dummy_task_c();
-                    }
+                let name = match a {
+                    'a' => "task_a",
+                    'b' => "task_b",
+                    'c' => "task_c",
                     _ => {
                         libfelix::println!("Specify test a, b, or c!");
+                        return;
                     }
-                }
-            },
+                };
+
+                let letter = a.to_ascii_uppercase();
+                let handle = match kthread::spawn(name, move || dummy_task(letter)) {
+                    Some(handle) => handle,
+                    None => return,
+                };
+
+                //another thread waits for the test task and reports its exit code
+                let pid = handle.pid();
+                let _ = kthread::spawn("test_join", move || {
+                    let code = handle.join();
+                    libfelix::println!("Task {} joined with code {}", pid, code);
+                });
+            }
 
             //help command
             _b if self.is_command("help") => {
@@ -165,61 +334,575 @@ impl Shell {
         }
     }
 
-    //shows content of a file in ascii format
-    pub unsafe fn cat(&mut self, b: &[char]) {
-        for i in 4..15 {
-            self.arg[i - 4] = b[i];
-        }
-        let fat = FAT.acquire();
+    //lists entries of given directory
+    pub unsafe fn ls(&mut self) {
+        let path = self.get_arg(1);
 
-        let entry = fat.search_file(&self.arg);
+        let dir = match VFS.lookup(path) {
+            Ok(dir) => dir,
+            Err(e) => {
+                libfelix::println!("{}!", e.message());
+                return;
+            }
+        };
+
+        libfelix::println!("Listing directory entries:");
+        libfelix::println!("Name          Size");
 
-        if entry.name[0] != 0 {
-            fat.read_file_to_buffer(entry);
+        let mut index = 0;
+        loop {
+            match VFS.read_dir(&dir, index) {
+                Ok(Some(entry)) => {
+                    libfelix::print!("{:14}", entry.name());
 
-            for c in fat.buffer {
-                if c != 0 {
-                    libfelix::print!("{}", c as char);
+                    if entry.inode.kind == InodeKind::Directory {
+                        libfelix::println!("<DIR>");
+                    } else {
+                        libfelix::println!("{} bytes", entry.inode.size);
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    libfelix::println!("{}!", e.message());
+                    break;
                 }
             }
-            libfelix::println!();
-        } else {
-            libfelix::println!("File not found!");
+
+            index += 1;
         }
+    }
+
+    //check fat volume consistency, repairing it if -r is given
+    pub unsafe fn fsck(&mut self) {
+        if !DISK.enabled {
+            libfelix::println!("No FAT volume!");
+            return;
+        }
+
+        let repair = self.get_arg(1) == "-r";
+
+        //report is printed after releasing the volume, since output may be redirected to it
+        let report = FAT.acquire_mut().check(repair);
         FAT.free();
+
+        for message in report.messages.iter() {
+            libfelix::println!("{}", message);
+        }
+
+        libfelix::println!(
+            "{} files, {} directories, {} errors {}",
+            report.files,
+            report.directories,
+            report.errors,
+            if repair && report.errors > 0 {
+                "repaired"
+            } else {
+                "found"
+            }
+        );
+
+        if repair {
+            cache::flush();
+        }
     }
 
-    //loads an executable as a task
-    pub unsafe fn run(&mut self, b: &[char]) {
-        for i in 4..15 {
-            self.arg[i - 4] = b[i];
+    //show partition table of second disk, or set one of its partitions
+    pub unsafe fn fdisk(&mut self) {
+        if !SECOND_DISK.enabled {
+            libfelix::println!("No second disk!");
+            return;
         }
-        let fat = FAT.acquire();
 
-        let entry = fat.search_file(&self.arg);
-        if entry.name[0] != 0 {
-            let slot = TASK_MANAGER.get_free_slot();
-            let target = APP_TARGET + (slot as u32 * APP_SIZE);
+        //a blank disk starts with an empty table
+        let mut partitions = SECOND_DISK
+            .read_partitions()
+            .unwrap_or([NO_PARTITION; MAX_PARTITIONS]);
+
+        if self.get_arg(1).is_empty() {
+            libfelix::println!(
+                "Disk {}: {}, {} sectors",
+                SECOND_DISK.name,
+                SECOND_DISK.model(),
+                SECOND_DISK.sectors
+            );
+            libfelix::println!("Name  Start       Sectors     Type");
+
+            for (i, partition) in partitions.iter().enumerate() {
+                if partition.sectors > 0 {
+                    libfelix::println!(
+                        "{}{}  {:<12}{:<12}{:02X}",
+                        SECOND_DISK.name,
+                        i + 1,
+                        partition.start,
+                        partition.sectors,
+                        partition.kind
+                    );
+                }
+            }
+            return;
+        }
 
-            //map table 8 (0x02000000) to the address where the executable is loaded
-            TABLES[8].set(target);
-            PAGING.set_table(8, &TABLES[8]);
+        let n = self.get_arg(1).parse::<usize>().unwrap_or(0);
+        let start = self.get_arg(2).parse::<u32>();
+        let sectors = self.get_arg(3).parse::<u32>();
 
-            fat.read_file_to_target(&entry, target as *mut u32);
+        let kind = match self.get_arg(4) {
+            "" => Ok(FAT16_PARTITION),
+            kind => u8::from_str_radix(kind, 16),
+        };
 
-            unsafe {
-                let signature = *(target as *mut u32);
+        let (start, sectors, kind) = match (start, sectors, kind) {
+            (Ok(start), Ok(sectors), Ok(kind)) if n >= 1 && n <= MAX_PARTITIONS => {
+                (start, sectors, kind)
+            }
+            _ => {
+                libfelix::println!("Usage: fdisk <n> <start> <sectors> <type>");
+                return;
+            }
+        };
+
+        //zero sectors removes partition
+        let partition = Partition {
+            start: if sectors > 0 { start } else { 0 },
+            sectors,
+            kind: if sectors > 0 { kind } else { 0 },
+        };
+
+        if sectors > 0 {
+            //first sector holds partition table
+            if start == 0 || start as u64 + sectors as u64 > SECOND_DISK.sectors as u64 {
+                libfelix::println!("Partition outside disk!");
+                return;
+            }
+
+            let overlapping = partitions.iter().enumerate().any(|(i, p)| {
+                i != n - 1
+                    && p.sectors > 0
+                    && start < p.start.saturating_add(p.sectors)
+                    && p.start < start + sectors
+            });
+
+            if overlapping {
+                libfelix::println!("Partition overlaps another one!");
+                return;
+            }
+        }
+
+        partitions[n - 1] = partition;
+        SECOND_DISK.write_partitions(&partitions);
+
+        libfelix::println!("Partition table of {} written", SECOND_DISK.name);
+    }
+
+    //format partition of second disk as FAT16
+    pub unsafe fn mkfs(&mut self) {
+        if !SECOND_DISK.enabled {
+            libfelix::println!("No second disk!");
+            return;
+        }
+
+        let n = self.get_arg(1).parse::<usize>().unwrap_or(0);
+        if n < 1 || n > MAX_PARTITIONS {
+            libfelix::println!("Usage: mkfs.fat <n> <label>");
+            return;
+        }
+
+        let partition = match SECOND_DISK.read_partitions() {
+            Some(partitions) if partitions[n - 1].sectors > 0 => partitions[n - 1],
+            _ => {
+                libfelix::println!("Partition not found!");
+                return;
+            }
+        };
+
+        let label = self.get_arg(2);
+
+        match mkfs::format(&SECOND_DISK, partition.start, partition.sectors, label) {
+            Ok(format) => {
+                libfelix::println!(
+                    "{}{} formatted as FAT16: {} clusters of {} bytes, {} sectors per fat",
+                    SECOND_DISK.name,
+                    n,
+                    format.clusters,
+                    format.sectors_per_cluster * 512,
+                    format.sectors_per_fat
+                );
+            }
+            Err(message) => {
+                libfelix::println!("{}!", message);
+            }
+        }
+    }
+
+    //shows content of a file in ascii format
+    pub unsafe fn cat(&mut self) {
+        let mut reader = match self.open_reader() {
+            Some(reader) => reader,
+            None => return,
+        };
+
+        loop {
+            match reader.chunk() {
+                Ok(Some(chunk)) => {
+                    for byte in chunk {
+                        print_byte(*byte);
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    libfelix::println!("{}!", e.message());
+                    break;
+                }
+            }
+        }
+        libfelix::println!();
+    }
+
+    //shows content of a file in hex and ascii format, 16 bytes per line
+    pub unsafe fn hexdump(&mut self) {
+        let mut reader = match self.open_reader() {
+            Some(reader) => reader,
+            None => return,
+        };
+
+        let mut line = [0u8; 16];
+
+        loop {
+            let offset = reader.offset();
+
+            //fill a line of bytes
+            let mut count = 0;
+            while count < line.len() {
+                match reader.next_byte() {
+                    Ok(Some(byte)) => {
+                        line[count] = byte;
+                        count += 1;
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        libfelix::println!("{}!", e.message());
+                        return;
+                    }
+                }
+            }
 
-                if signature == APP_SIGNATURE {
-                    TASK_MANAGER.add_task((target + 4) as u32);
+            if count == 0 {
+                break;
+            }
+
+            libfelix::print!("{:08x}  ", offset);
+
+            for i in 0..line.len() {
+                if i < count {
+                    libfelix::print!("{:02x} ", line[i]);
                 } else {
-                    libfelix::println!("File is not a valid executable!");
+                    libfelix::print!("   ");
+                }
+            }
+
+            libfelix::print!("|");
+            for byte in &line[..count] {
+                match byte {
+                    0x20..=0x7e => libfelix::print!("{}", *byte as char),
+                    _ => libfelix::print!("."),
+                }
+            }
+            libfelix::println!("|");
+        }
+    }
+
+    //shows first lines of a file
+    pub unsafe fn head(&mut self) {
+        let lines = self.get_lines_arg();
+        let mut reader = match self.open_reader() {
+            Some(reader) => reader,
+            None => return,
+        };
+
+        let mut printed = 0;
+        while printed < lines {
+            match reader.next_byte() {
+                Ok(Some(byte)) => {
+                    print_byte(byte);
+
+                    if byte == b'\n' {
+                        printed += 1;
+                    }
+                }
+                Ok(None) => {
+                    libfelix::println!();
+                    break;
+                }
+                Err(e) => {
+                    libfelix::println!("{}!", e.message());
+                    break;
+                }
+            }
+        }
+    }
+
+    //shows last lines of a file, reading it twice to avoid keeping it in memory
+    pub unsafe fn tail(&mut self) {
+        let lines = self.get_lines_arg();
+        let mut reader = match self.open_reader() {
+            Some(reader) => reader,
+            None => return,
+        };
+
+        //count lines, a last line without new line counts too
+        let mut total = 0;
+        let mut last = b'\n';
+        loop {
+            match reader.next_byte() {
+                Ok(Some(byte)) => {
+                    if byte == b'\n' {
+                        total += 1;
+                    }
+                    last = byte;
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    libfelix::println!("{}!", e.message());
+                    return;
                 }
             }
+        }
+        if last != b'\n' {
+            total += 1;
+        }
+
+        //skip lines before the last ones
+        reader.rewind();
+        let mut skipped = 0;
+        let skip = total - lines.min(total);
+
+        loop {
+            match reader.next_byte() {
+                Ok(Some(byte)) => {
+                    if skipped >= skip {
+                        print_byte(byte);
+                    } else if byte == b'\n' {
+                        skipped += 1;
+                    }
+                }
+                Ok(None) => {
+                    libfelix::println!();
+                    break;
+                }
+                Err(e) => {
+                    libfelix::println!("{}!", e.message());
+                    break;
+                }
+            }
+        }
+    }
+
+    //line count for head and tail, default is 10
+    fn get_lines_arg(&mut self) -> usize {
+        self.get_arg(2).parse().unwrap_or(DEFAULT_LINES)
+    }
+
+    //set nice value of task, values out of range are clamped
+    unsafe fn nice(&mut self) {
+        let pid = match self.get_arg(1).parse::<u32>() {
+            Ok(pid) => pid,
+            Err(_) => {
+                libfelix::println!("No task id provided!");
+                return;
+            }
+        };
+
+        let nice = match self.get_arg(2).parse::<i32>() {
+            Ok(nice) => nice,
+            Err(_) => {
+                libfelix::println!("No nice value provided!");
+                return;
+            }
+        };
+
+        match TASK_MANAGER.find_task(pid) {
+            Some(slot) => {
+                let nice = TASK_MANAGER.set_nice(slot, nice);
+                libfelix::println!("Task {} nice value set to {}", pid, nice);
+            }
+            None => {
+                libfelix::println!("Task not found!");
+            }
+        }
+    }
+
+    //send signal given by name or number to task, TERM if none is given
+    unsafe fn kill(&mut self) {
+        let pid = match self.get_arg(1).parse::<u32>() {
+            Ok(pid) => pid,
+            Err(_) => {
+                libfelix::println!("No task id provided!");
+                return;
+            }
+        };
+
+        let signal = match self.get_arg(2) {
+            "" => signal::TERM,
+            name => match signal::from_name(name) {
+                Some(signal) => signal,
+                None => {
+                    libfelix::println!("Unknown signal!");
+                    return;
+                }
+            },
+        };
+
+        if pid == KERNEL_PID || !TASK_MANAGER.send_signal(pid, signal) {
+            libfelix::println!("Task not found!");
+        }
+    }
+
+    //loads an executable as a task, the rest of the command line is passed to it as arguments
+    pub unsafe fn run(&mut self) {
+        let line: String = self.buffer[..self.cursor].iter().collect();
+        let args: Vec<&str> = line
+            .split(' ')
+            .filter(|arg| !arg.is_empty())
+            .skip(1)
+            .collect();
+
+        let path = match args.first() {
+            Some(&path) => path,
+            None => {
+                libfelix::println!("No program provided!");
+                return;
+            }
+        };
+
+        //app runs in user mode, returning from entry point jumps to the exit trampoline on top of stack
+        match loader::load(path, &args, &ENVIRONMENT) {
+            Ok(image) => {
+                let name = loader::program_name(path);
+                self.foreground =
+                    TASK_MANAGER.add_user_task(image.entry_point, image.stack, image.memory, name);
+            }
+            Err(e) => {
+                libfelix::println!("{}!", e.message());
+            }
+        }
+    }
+
+    //if command line contains > or >>, redirect output to the file after it
+    //returns false if the file can't be opened
+    unsafe fn start_redirect(&mut self) -> bool {
+        let position = match self.buffer[..self.cursor].iter().position(|&c| c == '>') {
+            Some(position) => position,
+            None => return true,
+        };
+
+        let append = self.buffer.get(position + 1) == Some(&'>');
+        let start = if append { position + 2 } else { position + 1 };
+
+        //copy first word after > to arg buffer
+        let mut len = 0;
+        for &c in self.buffer[start..self.cursor].iter() {
+            if c == ' ' {
+                if len > 0 {
+                    break;
+                }
+                continue;
+            }
+
+            if len < self.arg.len() {
+                self.arg[len] = c as u8;
+                len += 1;
+            }
+        }
+
+        //hide redirection from command
+        for c in self.buffer[position..self.cursor].iter_mut() {
+            *c = 0 as char;
+        }
+        self.cursor = position;
+
+        let path = core::str::from_utf8(&self.arg[..len]).unwrap_or("");
+
+        let file = match VFS.lookup(path) {
+            Ok(file) => file,
+            Err(_) => match VFS.create(path, InodeKind::File) {
+                Ok(file) => file,
+                Err(e) => {
+                    libfelix::println!("{}!", e.message());
+                    return false;
+                }
+            },
+        };
+
+        if file.is_directory() {
+            libfelix::println!("Cannot redirect to a directory!");
+            return false;
+        }
+
+        let offset = if append {
+            match VFS.stat(&file) {
+                Ok(file) => file.inode.size,
+                Err(_) => 0,
+            }
         } else {
-            libfelix::println!("Program not found!");
+            if let Err(e) = VFS.truncate(&file) {
+                libfelix::println!("{}!", e.message());
+                return false;
+            }
+            0
+        };
+
+        PRINTER.redirect = Some(Redirect { file, offset });
+
+        true
+    }
+
+    //open file given as first argument for streaming it, printing errors
+    fn open_reader(&mut self) -> Option<FileReader<'_>> {
+        let len = self.copy_arg(1);
+        let path = core::str::from_utf8(&self.arg[..len]).unwrap_or("");
+
+        match FileReader::open(path, &mut self.file_buffer) {
+            Ok(reader) => Some(reader),
+            Err(e) => {
+                libfelix::println!("{}!", e.message());
+                None
+            }
         }
-        FAT.free();
+    }
+
+    //copy nth word of command line to arg buffer and return it
+    fn get_arg(&mut self, n: usize) -> &str {
+        let len = self.copy_arg(n);
+
+        core::str::from_utf8(&self.arg[..len]).unwrap_or("")
+    }
+
+    //copy nth word of command line to arg buffer, returns its lenght
+    fn copy_arg(&mut self, n: usize) -> usize {
+        let mut word = 0;
+        let mut in_word = false;
+        let mut len = 0;
+
+        for i in 0..self.cursor {
+            let c = self.buffer[i];
+
+            if c == ' ' {
+                if in_word {
+                    word += 1;
+                    in_word = false;
+                }
+                continue;
+            }
+
+            in_word = true;
+
+            if word == n && len < self.arg.len() {
+                self.arg[len] = c as u8;
+                len += 1;
+            }
+        }
+
+        len
     }
 
     pub fn is_command(&self, command: &str) -> bool {
@@ -233,3 +916,42 @@ impl Shell {
         true
     }
 }
+
+//print byte as char, replacing those that can't be displayed
+fn print_byte(byte: u8) {
+    match byte {
+        b'\n' => libfelix::println!(),
+        b'\r' => {}
+        b'\t' => libfelix::print!("    "),
+        0x20..=0x7e => libfelix::print!("{}", byte as char),
+        _ => libfelix::print!("."),
+    }
+}
+
+//EXAMPLE TASK
+//counts up to 100%, printing progress and yielding after each step, used to test multitasking
+//stops early when it gets a TERM, INT or KILL signal
+fn dummy_task(name: char) {
+    let mut a: u32 = 0;
+    let mut b: u8 = 0;
+    loop {
+        if a == 100_000_000 {
+            libfelix::println!("Process {} running. {}% complete.", name, b);
+            a = 0;
+            b += 1;
+
+            if b == 100 {
+                libfelix::println!("Process {} complete.", name);
+                break;
+            }
+
+            if kthread::should_stop() {
+                libfelix::println!("Process {} stopped.", name);
+                break;
+            }
+
+            kthread::yield_now();
+        }
+        a += 1;
+    }
+}
@@ -0,0 +1,225 @@
+//FILE SYSTEM CALLS
+//Work on the file table of the current process, every file access goes through the vfs
+//Paths and buffers are checked against the memory of the caller before use
+
+use crate::filesystem::file::OpenFile;
+use crate::filesystem::vfs::{InodeKind, VfsError, VFS};
+use crate::multitasking::task;
+use crate::multitasking::task::TASK_MANAGER;
+use crate::syscalls::user::Caller;
+
+use libfelix::fs;
+use libfelix::fs::Stat;
+use libfelix::io::Error;
+
+use core::str;
+
+impl From<VfsError> for Error {
+    fn from(error: VfsError) -> Self {
+        match error {
+            VfsError::NotFound => Error::NotFound,
+            VfsError::NotDirectory => Error::NotDirectory,
+            VfsError::IsDirectory => Error::IsDirectory,
+            VfsError::InvalidPath => Error::InvalidPath,
+            VfsError::NotMounted => Error::NotFound,
+            VfsError::MountTableFull => Error::NoSpace,
+            VfsError::AlreadyExists => Error::AlreadyExists,
+            VfsError::ReadOnly => Error::ReadOnly,
+            VfsError::NoSpace => Error::NoSpace,
+            VfsError::NotEmpty => Error::NotEmpty,
+            VfsError::Busy => Error::Busy,
+        }
+    }
+}
+
+//convert result to value returned in eax
+pub fn result(result: Result<u32, Error>) -> u32 {
+    match result {
+        Ok(value) => value,
+        Err(error) => error.code(),
+    }
+}
+
+//get path passed by pointer and lenght
+unsafe fn get_str<'a>(caller: Caller, ptr: u32, len: u32) -> Result<&'a str, Error> {
+    let slice = caller.slice(ptr, len)?;
+    str::from_utf8(slice).map_err(|_| Error::InvalidPath)
+}
+
+//get open file of current task from descriptor
+unsafe fn get_file(fd: u32) -> Result<&'static mut OpenFile, Error> {
+    let files = TASK_MANAGER.current_files().ok_or(Error::BadDescriptor)?;
+    files.get_mut(fd as usize).ok_or(Error::BadDescriptor)
+}
+
+//SYSCALL OPEN, open file at path with given flags and return its descriptor
+pub unsafe fn open(caller: Caller, path_ptr: u32, path_len: u32, flags: u32) -> Result<u32, Error> {
+    let path = get_str(caller, path_ptr, path_len)?;
+
+    let vnode = match VFS.lookup(path) {
+        Ok(vnode) => vnode,
+        Err(VfsError::NotFound) if flags & fs::OPEN_CREATE != 0 => {
+            VFS.create(path, InodeKind::File)?
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let writable = flags & fs::OPEN_WRITE != 0;
+
+    if vnode.is_directory() && writable {
+        return Err(Error::IsDirectory);
+    }
+
+    if writable && flags & fs::OPEN_TRUNCATE != 0 {
+        VFS.truncate(&vnode)?;
+    }
+
+    let file = OpenFile {
+        vnode,
+        offset: 0,
+        flags,
+    };
+
+    let files = TASK_MANAGER.current_files().ok_or(Error::BadDescriptor)?;
+    let fd = files.insert(file).ok_or(Error::TooManyFiles)?;
+
+    Ok(fd as u32)
+}
+
+//SYSCALL READ, read from file to buffer, advancing offset
+pub unsafe fn read(caller: Caller, fd: u32, buffer_ptr: u32, len: u32) -> Result<u32, Error> {
+    let file = get_file(fd)?;
+
+    if file.flags & fs::OPEN_READ == 0 {
+        return Err(Error::NotPermitted);
+    }
+
+    let buffer = caller.slice_mut(buffer_ptr, len)?;
+
+    //files that may have data later, like the keyboard, block the task until they have some
+    let read = loop {
+        let read = VFS.read(&file.vnode, file.offset, buffer)?;
+
+        if read > 0 || buffer.is_empty() {
+            break read;
+        }
+
+        //a signal interrupts the read, so it can be handled
+        if TASK_MANAGER.signal_pending() {
+            return Err(Error::Interrupted);
+        }
+
+        if !VFS.wait(&file.vnode)? {
+            break read;
+        }
+
+        task::yield_now();
+    };
+
+    file.offset += read as u32;
+
+    Ok(read as u32)
+}
+
+//SYSCALL WRITE, write buffer to file, advancing offset
+pub unsafe fn write(caller: Caller, fd: u32, buffer_ptr: u32, len: u32) -> Result<u32, Error> {
+    let file = get_file(fd)?;
+
+    if file.flags & fs::OPEN_WRITE == 0 {
+        return Err(Error::NotPermitted);
+    }
+
+    let buffer = caller.slice(buffer_ptr, len)?;
+
+    //append always writes at the end of the file
+    if file.flags & fs::OPEN_APPEND != 0 {
+        file.offset = VFS.stat(&file.vnode)?.inode.size;
+    }
+
+    let written = VFS.write(&file.vnode, file.offset, buffer)?;
+
+    file.offset += written as u32;
+
+    Ok(written as u32)
+}
+
+//SYSCALL SEEK, move file offset and return it
+pub unsafe fn seek(fd: u32, offset: u32, origin: u32) -> Result<u32, Error> {
+    let file = get_file(fd)?;
+
+    let base = match origin {
+        fs::SEEK_START => 0,
+        fs::SEEK_CURRENT => file.offset as i64,
+        fs::SEEK_END => VFS.stat(&file.vnode)?.inode.size as i64,
+        _ => return Err(Error::InvalidArgument),
+    };
+
+    //offset is relative to the origin, so it can be negative
+    let relative = if origin == fs::SEEK_START {
+        offset as i64
+    } else {
+        offset as i32 as i64
+    };
+
+    let position = base + relative;
+    if position < 0 || position > i32::MAX as i64 {
+        return Err(Error::InvalidArgument);
+    }
+
+    file.offset = position as u32;
+
+    Ok(file.offset)
+}
+
+//SYSCALL CLOSE, free file descriptor
+pub unsafe fn close(fd: u32) -> Result<u32, Error> {
+    let files = TASK_MANAGER.current_files().ok_or(Error::BadDescriptor)?;
+
+    match files.remove(fd as usize) {
+        Some(_) => Ok(0),
+        None => Err(Error::BadDescriptor),
+    }
+}
+
+//SYSCALL STAT, fill stat struct with info about file at path
+pub unsafe fn stat(
+    caller: Caller,
+    path_ptr: u32,
+    path_len: u32,
+    stat_ptr: u32,
+) -> Result<u32, Error> {
+    let path = get_str(caller, path_ptr, path_len)?;
+
+    let vnode = VFS.lookup(path)?;
+    let vnode = VFS.stat(&vnode)?;
+
+    let stat = Stat {
+        size: vnode.inode.size,
+        kind: match vnode.inode.kind {
+            InodeKind::File => fs::KIND_FILE,
+            InodeKind::Directory => fs::KIND_DIRECTORY,
+        },
+    };
+
+    caller.write(stat_ptr, stat)?;
+
+    Ok(0)
+}
+
+//SYSCALL MKDIR, create directory at path
+pub unsafe fn mkdir(caller: Caller, path_ptr: u32, path_len: u32) -> Result<u32, Error> {
+    let path = get_str(caller, path_ptr, path_len)?;
+
+    VFS.create(path, InodeKind::Directory)?;
+
+    Ok(0)
+}
+
+//SYSCALL REMOVE, delete file or empty directory at path
+pub unsafe fn remove(caller: Caller, path_ptr: u32, path_len: u32) -> Result<u32, Error> {
+    let path = get_str(caller, path_ptr, path_len)?;
+
+    VFS.remove(path)?;
+
+    Ok(0)
+}
@@ -1,54 +1,124 @@
 //SYSTEM CALLS HANDLER
 
 use crate::drivers::pic::PICS;
-use crate::multitasking::task::TASK_MANAGER;
+use crate::interrupts::idt;
+use crate::multitasking::signal;
+use crate::multitasking::task;
+use crate::multitasking::task::CPUState;
+use crate::syscalls::file;
 use crate::syscalls::print;
+use crate::syscalls::process;
+use crate::syscalls::user::Caller;
 use core::arch::asm;
 
-use core::slice;
-use core::str;
+use libfelix::io::Error;
+use libfelix::syscall;
 
 pub const SYSCALL_INT: u8 = 0x80;
 
 //SYSCALL IRQ, calls local function using cdecl calling convention
+//registers are saved as the cpu state of the caller, so fork can copy it and exec can replace it
+//the value returned by the function is left in eax and returned to the caller
+//pending signals are handled on the way back, possibly changing the saved state
 #[naked]
 pub extern "C" fn syscall() {
     unsafe {
         asm!(
-            "push eax",
+            "push ebp",
+            "push edi",
+            "push esi",
+            "push edx",
+            "push ecx",
             "push ebx",
+            "push eax",
+            "push esp",
+            "push edx",
             "push ecx",
+            "push ebx",
+            "push eax",
             "call syscall_handler",
-            "add esp, 12",
+            "add esp, 24",
+            "pop ebx",
+            "pop ecx",
+            "pop edx",
+            "pop esi",
+            "pop edi",
+            "pop ebp",
             "iretd",
             options(noreturn)
         );
     }
 }
 
-//handle syscalls, get syscall number from eax register
+//handle syscalls, get syscall number from eax register and arguments from ebx, ecx and edx
 #[no_mangle]
-pub extern "C" fn syscall_handler(ecx: u32, ebx: u32, eax: u32) {
-    unsafe {
+pub extern "C" fn syscall_handler(
+    eax: u32,
+    ebx: u32,
+    ecx: u32,
+    edx: u32,
+    state: *mut CPUState,
+) -> u32 {
+    idt::count_interrupt(SYSCALL_INT);
+
+    let result = unsafe {
+        //pointers passed from user mode must point to memory of the app
+        let caller = Caller::from_state(&*state);
+
         match eax {
             //SYSCALL 0, print string pointed by ebx with lenght specified in ecx
-            0 => {
-                let s = {
-                    let slice = slice::from_raw_parts(ebx as *const u8, ecx as usize);
-                    str::from_utf8(slice)
-                };
+            syscall::PRINT => match caller.str(ebx, ecx) {
+                Ok(s) => {
+                    print::PRINTER.prints(s);
+                    0
+                }
+                Err(error) => error.code(),
+            },
 
-                print::PRINTER.prints(s.unwrap());
-            }
+            //SYSCALL 1, end current active task with exit code in ebx, never returns to it
+            syscall::EXIT => task::exit(ebx as i32),
 
-            //SYSCALL 1, remove current active task
-            1 => {
-                TASK_MANAGER.remove_current_task();
-            }
+            //SYSCALLS 2-7, file access using file descriptors
+            syscall::OPEN => file::result(file::open(caller, ebx, ecx, edx)),
+            syscall::READ => file::result(file::read(caller, ebx, ecx, edx)),
+            syscall::WRITE => file::result(file::write(caller, ebx, ecx, edx)),
+            syscall::SEEK => file::result(file::seek(ebx, ecx, edx)),
+            syscall::CLOSE => file::result(file::close(ebx)),
+            syscall::STAT => file::result(file::stat(caller, ebx, ecx, edx)),
 
-            _ => {}
+            //SYSCALLS 8-9, directories and file deletion
+            syscall::MKDIR => file::result(file::mkdir(caller, ebx, ecx)),
+            syscall::REMOVE => file::result(file::remove(caller, ebx, ecx)),
+
+            //SYSCALL 10, wait for child with pid in ebx to exit, exit code is written where ecx points
+            syscall::WAIT => file::result(process::wait(caller, ebx, ecx)),
+
+            //SYSCALLS 11-15, threads of current process
+            syscall::THREAD_CREATE => file::result(process::thread_create(ebx, ecx, edx)),
+            syscall::THREAD_EXIT => process::thread_exit(caller, ebx as i32, ecx),
+            syscall::THREAD_JOIN => file::result(process::thread_join(caller, ebx, ecx)),
+            syscall::THREAD_DETACH => file::result(process::thread_detach(ebx)),
+            syscall::SET_TLS => file::result(process::set_tls(ebx)),
+
+            //SYSCALLS 16-17, start programs the unix way
+            syscall::FORK => file::result(process::fork(&*state)),
+            syscall::EXEC => file::result(process::exec(caller, &mut *state, ebx, ecx, edx)),
+
+            //SYSCALLS 18-20, signals
+            syscall::SIGNAL => file::result(process::signal(ebx, ecx)),
+            syscall::KILL => file::result(process::kill(ebx, ecx)),
+            syscall::SIGRETURN => signal::sigreturn(&mut *state),
+
+            _ => Error::InvalidArgument.code(),
         }
+    };
+
+    PICS.end_interrupt(SYSCALL_INT);
+
+    unsafe {
+        (*state).set_result(result);
+        signal::deliver(&mut *state, true);
 
-        PICS.end_interrupt(SYSCALL_INT);
+        (*state).result()
     }
 }
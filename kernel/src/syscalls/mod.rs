@@ -1,2 +1,5 @@
+pub mod file;
 pub mod handler;
 pub mod print;
+pub mod process;
+pub mod user;
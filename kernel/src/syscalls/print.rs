@@ -1,6 +1,7 @@
 //PRINTER
 //Manages text output by directly writing to VGA video memory
 
+use crate::filesystem::vfs::{Vnode, VFS};
 use core::arch::asm;
 
 //Warning! Mutable static here
@@ -10,6 +11,7 @@ pub static mut PRINTER: Printer = Printer {
     y: 0,
     foreground: 0x7,
     background: 0,
+    redirect: None,
 };
 
 const WIDTH: u16 = 80;
@@ -22,6 +24,15 @@ pub struct Printer {
     y: u16,
     foreground: u8,
     background: u8,
+
+    //when set, text is written to a file instead of the screen
+    pub redirect: Option<Redirect>,
+}
+
+//file receiving redirected output
+pub struct Redirect {
+    pub file: Vnode,
+    pub offset: u32,
 }
 
 impl Printer {
@@ -67,6 +78,21 @@ impl Printer {
 
     //print a string by printing one char at the time
     pub fn prints(&mut self, s: &str) {
+        if let Some(redirect) = self.redirect.as_mut() {
+            //errors can't be reported, since printing them would be redirected too
+            unsafe {
+                if let Ok(written) = VFS.write(&redirect.file, redirect.offset, s.as_bytes()) {
+                    redirect.offset += written as u32;
+                }
+            }
+            return;
+        }
+
+        self.prints_screen(s);
+    }
+
+    //print a string on screen, ignoring redirection
+    pub fn prints_screen(&mut self, s: &str) {
         //set coords to current cursor position
         let cursor = self.get_cursor_position();
         self.x = cursor.0;
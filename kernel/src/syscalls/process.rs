@@ -0,0 +1,247 @@
+//PROCESS SYSTEM CALLS
+//Let a task wait for its children, exited children stay zombies until their exit code is collected
+//Threads of a process are joined the same way by other threads of the same process
+//New programs are started by forking the current process and replacing the program of the copy
+//Waiting gives up when a signal arrives, so it can be handled
+
+use crate::memory::gdt::GDT;
+use crate::memory::paging::{APP_VIRTUAL, MAX_APP_SIZE};
+use crate::multitasking::loader;
+use crate::multitasking::loader::LoadError;
+use crate::multitasking::task;
+use crate::multitasking::task::{CPUState, TaskState, EXITED, TASK_MANAGER};
+use crate::syscalls::user::Caller;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use libfelix::io::Error;
+use libfelix::process::{MAX_ARGS, MAX_ENV};
+use libfelix::signal::{DEFAULT_HANDLER, IGNORE_HANDLER, KILL, MAX_SIGNAL};
+
+impl From<LoadError> for Error {
+    fn from(error: LoadError) -> Self {
+        match error {
+            LoadError::NotFound => Error::NotFound,
+            LoadError::InvalidExecutable => Error::InvalidArgument,
+            LoadError::UnsupportedExecutable => Error::InvalidArgument,
+            LoadError::MalformedExecutable => Error::InvalidArgument,
+            LoadError::TooBig => Error::NoMemory,
+            LoadError::ArgumentsTooLong => Error::InvalidArgument,
+            LoadError::NoMemory => Error::NoMemory,
+            LoadError::Vfs(e) => e.into(),
+        }
+    }
+}
+
+//pid zero means any child, since the kernel is never a child
+const ANY_CHILD: u32 = 0;
+
+//wait until child with given pid exits, write its exit code to code pointer if not null and free its slot
+//returns pid of the collected child, the child is not freed if the code can't be written
+pub unsafe fn wait(caller: Caller, pid: u32, code: u32) -> Result<u32, Error> {
+    let parent = TASK_MANAGER.get_current_task().ok_or(Error::NoChild)?.pid;
+    let pid = match pid {
+        ANY_CHILD => None,
+        pid => Some(pid),
+    };
+
+    loop {
+        if let Some(slot) = TASK_MANAGER.find_zombie(parent, pid) {
+            let (child, exit_code) = match TASK_MANAGER.get_task(slot) {
+                Some(task) => (task.pid, task.exit_code),
+                None => return Err(Error::NoChild),
+            };
+
+            if code != 0 {
+                caller.write(code, exit_code)?;
+            }
+
+            TASK_MANAGER.remove_task(slot);
+            return Ok(child);
+        }
+
+        if !TASK_MANAGER.has_child(parent, pid) {
+            return Err(Error::NoChild);
+        }
+
+        if TASK_MANAGER.signal_pending() {
+            return Err(Error::Interrupted);
+        }
+
+        //children are still running, try again when one of them exits
+        EXITED.sleep();
+    }
+}
+
+//SYSCALL THREAD CREATE, start thread in current process at entry point with given stack and thread local storage
+//returns pid of the new thread, the thread finds it in eax
+pub unsafe fn thread_create(entry_point: u32, stack: u32, tls: u32) -> Result<u32, Error> {
+    TASK_MANAGER
+        .add_user_thread(entry_point, stack, tls)
+        .ok_or(Error::NoMemory)
+}
+
+//SYSCALL THREAD EXIT, end current thread with given exit code, never returns to it
+//the counter pointed by the second argument, if not null, is decremented once the thread stopped using its stack
+//a counter outside app memory is ignored
+pub unsafe fn thread_exit(caller: Caller, exit_code: i32, counter: u32) -> ! {
+    if counter != 0 {
+        if let Ok(counter) = caller.slice_mut(counter, 4) {
+            let counter = counter.as_mut_ptr() as *mut u32;
+            counter.write_unaligned(counter.read_unaligned().wrapping_sub(1));
+        }
+    }
+
+    task::exit_thread(exit_code)
+}
+
+//get slot of thread with given pid, it must belong to the process of current task
+unsafe fn find_thread(pid: u32) -> Result<usize, Error> {
+    let current = TASK_MANAGER.get_current_task().ok_or(Error::NoChild)?;
+    let (process, current) = (current.process, current.pid);
+
+    if pid == current {
+        return Err(Error::InvalidArgument);
+    }
+
+    let slot = TASK_MANAGER.find_task(pid).ok_or(Error::NoChild)?;
+    match TASK_MANAGER.get_task(slot) {
+        Some(task) if task.process == process && task.thread && !task.detached => Ok(slot),
+        _ => Err(Error::NoChild),
+    }
+}
+
+//SYSCALL THREAD JOIN, wait until thread with given pid exits, write its exit code to code pointer if not null and free it
+//thread is not freed if the code can't be written, so it can still be joined
+pub unsafe fn thread_join(caller: Caller, pid: u32, code: u32) -> Result<u32, Error> {
+    loop {
+        let slot = find_thread(pid)?;
+
+        let exit_code = match TASK_MANAGER.get_task(slot) {
+            Some(task) if task.state == TaskState::Zombie => Some(task.exit_code),
+            _ => None,
+        };
+
+        if let Some(exit_code) = exit_code {
+            if code != 0 {
+                caller.write(code, exit_code)?;
+            }
+
+            TASK_MANAGER.remove_task(slot);
+            return Ok(pid);
+        }
+
+        if TASK_MANAGER.signal_pending() {
+            return Err(Error::Interrupted);
+        }
+
+        //thread is still running, try again when some task exits
+        EXITED.sleep();
+    }
+}
+
+//SYSCALL THREAD DETACH, let thread with given pid be freed as soon as it exits
+pub unsafe fn thread_detach(pid: u32) -> Result<u32, Error> {
+    find_thread(pid)?;
+    TASK_MANAGER.detach(pid);
+
+    Ok(0)
+}
+
+//SYSCALL SET TLS, move thread local storage of current task, returns its pid
+pub unsafe fn set_tls(base: u32) -> Result<u32, Error> {
+    let task = TASK_MANAGER
+        .get_current_task()
+        .ok_or(Error::InvalidArgument)?;
+    task.tls = base;
+
+    GDT.set_tls(base);
+
+    Ok(task.pid)
+}
+
+//SYSCALL FORK, duplicate current process, returns pid of the copy, zero to the copy itself
+pub unsafe fn fork(state: &CPUState) -> Result<u32, Error> {
+    TASK_MANAGER.fork_current_task(state).ok_or(Error::NoMemory)
+}
+
+//SYSCALL EXEC, replace program of current process, returns to the new program only if it has been loaded
+//arguments are an array of pointer and length pairs, the first one is the program path
+//environment variables follow arguments in the same array
+pub unsafe fn exec(
+    caller: Caller,
+    state: &mut CPUState,
+    args_ptr: u32,
+    count: u32,
+    env_count: u32,
+) -> Result<u32, Error> {
+    if count == 0 || count as usize > MAX_ARGS || env_count as usize > MAX_ENV {
+        return Err(Error::InvalidArgument);
+    }
+
+    //strings are copied, since they are in the memory being replaced
+    let pairs = caller.slice(args_ptr, (count + env_count) * 8)?;
+    let mut strings = Vec::with_capacity(pairs.len() / 8);
+    for pair in pairs.chunks_exact(8) {
+        let ptr = u32::from_le_bytes([pair[0], pair[1], pair[2], pair[3]]);
+        let len = u32::from_le_bytes([pair[4], pair[5], pair[6], pair[7]]);
+        strings.push(String::from(caller.str(ptr, len)?));
+    }
+
+    let strings: Vec<&str> = strings.iter().map(|string| string.as_str()).collect();
+    let (args, env) = strings.split_at(count as usize);
+    let path = args[0];
+
+    if env.iter().any(|var| !var.contains('=')) {
+        return Err(Error::InvalidArgument);
+    }
+
+    let image = loader::load(path, args, env)?;
+    let (entry_point, stack) = (image.entry_point, image.stack);
+
+    if !TASK_MANAGER.exec_current_task(image, loader::program_name(path)) {
+        return Err(Error::NotPermitted);
+    }
+
+    state.start_user(entry_point, stack);
+
+    Ok(0)
+}
+
+//SYSCALL SIGNAL, set handler of signal for current process, KILL can't be handled
+//handler is the address of a function getting the signal number, or the default or ignore value
+pub unsafe fn signal(signal: u32, handler: u32) -> Result<u32, Error> {
+    if signal == 0 || signal > MAX_SIGNAL || signal == KILL {
+        return Err(Error::InvalidArgument);
+    }
+
+    let function = (APP_VIRTUAL..APP_VIRTUAL + MAX_APP_SIZE).contains(&handler);
+    if handler != DEFAULT_HANDLER && handler != IGNORE_HANDLER && !function {
+        return Err(Error::InvalidArgument);
+    }
+
+    if !TASK_MANAGER.set_signal_handler(signal, handler) {
+        return Err(Error::NotPermitted);
+    }
+
+    Ok(0)
+}
+
+//SYSCALL KILL, send signal to task with given pid, only user tasks can be signaled
+pub unsafe fn kill(pid: u32, signal: u32) -> Result<u32, Error> {
+    if signal == 0 || signal > MAX_SIGNAL {
+        return Err(Error::InvalidArgument);
+    }
+
+    let slot = TASK_MANAGER.find_task(pid).ok_or(Error::NotFound)?;
+    match TASK_MANAGER.get_task(slot) {
+        Some(task) if task.user => {}
+        _ => return Err(Error::NotPermitted),
+    }
+
+    if !TASK_MANAGER.send_signal(pid, signal) {
+        return Err(Error::NotFound);
+    }
+
+    Ok(0)
+}
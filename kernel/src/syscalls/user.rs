@@ -0,0 +1,75 @@
+//USER POINTERS
+//Pointers passed to system calls from user mode are checked against the memory of the calling app before use
+//so an app can't make the kernel read or write kernel memory, or fault in ring 0
+//Calls made by the kernel itself, like printing, pass kernel pointers and are trusted
+
+use crate::multitasking::task::{CPUState, TASK_MANAGER};
+use core::mem::size_of;
+use core::slice;
+use core::str;
+
+use libfelix::io::Error;
+
+//who made a system call, found from the privilege level of the saved state
+#[derive(Copy, Clone)]
+pub struct Caller {
+    user: bool,
+}
+
+impl Caller {
+    pub fn from_state(state: &CPUState) -> Caller {
+        Caller {
+            user: state.is_user(),
+        }
+    }
+
+    //check that caller can read given range, or also write it
+    unsafe fn check(&self, ptr: u32, len: u32, write: bool) -> Result<(), Error> {
+        if !self.user {
+            return Ok(());
+        }
+
+        let valid = match TASK_MANAGER
+            .get_current_task()
+            .and_then(|task| task.memory.as_ref())
+        {
+            Some(memory) => memory.borrow().check_range(ptr, len, write),
+            None => false,
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err(Error::InvalidArgument),
+        }
+    }
+
+    pub unsafe fn slice<'a>(&self, ptr: u32, len: u32) -> Result<&'a [u8], Error> {
+        self.check(ptr, len, false)?;
+
+        match len {
+            0 => Ok(&[]),
+            _ => Ok(slice::from_raw_parts(ptr as *const u8, len as usize)),
+        }
+    }
+
+    pub unsafe fn slice_mut<'a>(&self, ptr: u32, len: u32) -> Result<&'a mut [u8], Error> {
+        self.check(ptr, len, true)?;
+
+        match len {
+            0 => Ok(&mut []),
+            _ => Ok(slice::from_raw_parts_mut(ptr as *mut u8, len as usize)),
+        }
+    }
+
+    pub unsafe fn str<'a>(&self, ptr: u32, len: u32) -> Result<&'a str, Error> {
+        str::from_utf8(self.slice(ptr, len)?).map_err(|_| Error::InvalidArgument)
+    }
+
+    //write value of given type, it may be unaligned
+    pub unsafe fn write<T>(&self, ptr: u32, value: T) -> Result<(), Error> {
+        self.check(ptr, size_of::<T>() as u32, true)?;
+
+        (ptr as *mut T).write_unaligned(value);
+        Ok(())
+    }
+}
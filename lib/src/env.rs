@@ -0,0 +1,103 @@
+//ENVIRONMENT
+//Arguments and environment variables of the program, found on its initial stack as System V lays them out
+//Argument array ends with a null pointer and is followed by environment array, ending the same way
+//Environment variables are KEY=value strings
+
+use core::ptr;
+use core::slice;
+use core::str;
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+static mut ARGV: *const *const u8 = ptr::null();
+static mut ENVP: *const *const u8 = ptr::null();
+
+//save where arguments and environment are, called at program start with entry point arguments
+pub unsafe fn init(argc: usize, argv: *const *const u8) {
+    if argv.is_null() {
+        return;
+    }
+
+    ARGV = argv;
+    ENVP = argv.add(argc + 1);
+}
+
+//iterator over a null terminated array of null terminated strings
+struct Strings {
+    next: *const *const u8,
+}
+
+impl Iterator for Strings {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            if self.next.is_null() || (*self.next).is_null() {
+                return None;
+            }
+
+            let string = *self.next;
+            self.next = self.next.add(1);
+
+            let mut len = 0;
+            while *string.add(len) != 0 {
+                len += 1;
+            }
+
+            Some(str::from_utf8(slice::from_raw_parts(string, len)).unwrap_or(""))
+        }
+    }
+}
+
+//arguments of the program, the first one is the path it has been started from
+pub struct Args(Strings);
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+//environment variables as key and value pairs
+pub struct Vars(Strings);
+
+impl Iterator for Vars {
+    type Item = (&'static str, &'static str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        //strings without an equal sign are not variables
+        loop {
+            if let Some(pair) = self.0.next()?.split_once('=') {
+                return Some(pair);
+            }
+        }
+    }
+}
+
+pub fn args() -> Args {
+    Args(Strings {
+        next: unsafe { ARGV },
+    })
+}
+
+pub fn vars() -> Vars {
+    Vars(Strings {
+        next: unsafe { ENVP },
+    })
+}
+
+//get value of environment variable
+pub fn var(key: &str) -> Option<&'static str> {
+    vars()
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+}
+
+//environment as KEY=value strings, passed as they are to programs started with exec
+pub(crate) fn raw_vars() -> impl Iterator<Item = &'static str> {
+    Strings {
+        next: unsafe { ENVP },
+    }
+}
@@ -0,0 +1,175 @@
+//FILESYSTEM
+//Files are accessed through file descriptors returned by the open syscall
+
+use crate::io::{Error, Read, Write};
+use crate::syscall;
+use core::fmt;
+
+//open flags
+pub const OPEN_READ: u32 = 1;
+pub const OPEN_WRITE: u32 = 2;
+pub const OPEN_CREATE: u32 = 4;
+pub const OPEN_TRUNCATE: u32 = 8;
+pub const OPEN_APPEND: u32 = 16;
+
+//seek origins
+pub const SEEK_START: u32 = 0;
+pub const SEEK_CURRENT: u32 = 1;
+pub const SEEK_END: u32 = 2;
+
+//file kinds
+pub const KIND_FILE: u32 = 0;
+pub const KIND_DIRECTORY: u32 = 1;
+
+//filled by the stat syscall
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct Stat {
+    pub size: u32,
+    pub kind: u32,
+}
+
+impl Stat {
+    pub fn is_directory(&self) -> bool {
+        self.kind == KIND_DIRECTORY
+    }
+}
+
+pub enum SeekFrom {
+    Start(u32),
+    Current(i32),
+    End(i32),
+}
+
+pub struct File {
+    fd: u32,
+}
+
+impl File {
+    //open existing file for reading
+    pub fn open(path: &str) -> Result<File, Error> {
+        File::open_with(path, OPEN_READ)
+    }
+
+    //create file for writing, truncating it if it exists
+    pub fn create(path: &str) -> Result<File, Error> {
+        File::open_with(path, OPEN_WRITE | OPEN_CREATE | OPEN_TRUNCATE)
+    }
+
+    //open file with given flags
+    pub fn open_with(path: &str, flags: u32) -> Result<File, Error> {
+        let result = unsafe {
+            syscall::syscall3(
+                syscall::OPEN,
+                path.as_ptr() as u32,
+                path.len() as u32,
+                flags,
+            )
+        };
+
+        Ok(File {
+            fd: Error::check(result)?,
+        })
+    }
+
+    //move file offset, returns the new offset
+    pub fn seek(&mut self, position: SeekFrom) -> Result<u32, Error> {
+        let (offset, origin) = match position {
+            SeekFrom::Start(offset) => (offset, SEEK_START),
+            SeekFrom::Current(offset) => (offset as u32, SEEK_CURRENT),
+            SeekFrom::End(offset) => (offset as u32, SEEK_END),
+        };
+
+        let result = unsafe { syscall::syscall3(syscall::SEEK, self.fd, offset, origin) };
+
+        Error::check(result)
+    }
+
+    pub fn fd(&self) -> u32 {
+        self.fd
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let result = unsafe {
+            syscall::syscall3(
+                syscall::READ,
+                self.fd,
+                buffer.as_mut_ptr() as u32,
+                buffer.len() as u32,
+            )
+        };
+
+        Error::check(result).map(|n| n as usize)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        let result = unsafe {
+            syscall::syscall3(
+                syscall::WRITE,
+                self.fd,
+                buffer.as_ptr() as u32,
+                buffer.len() as u32,
+            )
+        };
+
+        Error::check(result).map(|n| n as usize)
+    }
+}
+
+//allows using write! macro on files
+impl fmt::Write for File {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+//file is closed when it goes out of scope
+impl Drop for File {
+    fn drop(&mut self) {
+        unsafe {
+            syscall::syscall1(syscall::CLOSE, self.fd);
+        }
+    }
+}
+
+//get size and kind of file at path
+pub fn metadata(path: &str) -> Result<Stat, Error> {
+    let mut stat = Stat::default();
+
+    let result = unsafe {
+        syscall::syscall3(
+            syscall::STAT,
+            path.as_ptr() as u32,
+            path.len() as u32,
+            &mut stat as *mut Stat as u32,
+        )
+    };
+
+    Error::check(result)?;
+
+    Ok(stat)
+}
+
+//create a new directory
+pub fn create_dir(path: &str) -> Result<(), Error> {
+    let result =
+        unsafe { syscall::syscall2(syscall::MKDIR, path.as_ptr() as u32, path.len() as u32) };
+
+    Error::check(result)?;
+
+    Ok(())
+}
+
+//delete a file or an empty directory
+pub fn remove(path: &str) -> Result<(), Error> {
+    let result =
+        unsafe { syscall::syscall2(syscall::REMOVE, path.as_ptr() as u32, path.len() as u32) };
+
+    Error::check(result)?;
+
+    Ok(())
+}
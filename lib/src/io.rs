@@ -0,0 +1,110 @@
+//INPUT OUTPUT
+//Read and write traits implemented by files, and errors returned by syscalls
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(i32)]
+pub enum Error {
+    NotFound = 1,
+    NotDirectory = 2,
+    IsDirectory = 3,
+    InvalidPath = 4,
+    AlreadyExists = 5,
+    ReadOnly = 6,
+    NoSpace = 7,
+    BadDescriptor = 8,
+    TooManyFiles = 9,
+    InvalidArgument = 10,
+    NotPermitted = 11,
+    UnexpectedEof = 12,
+    NotEmpty = 13,
+    Busy = 14,
+    NoChild = 15,
+    NoMemory = 16,
+    Interrupted = 17,
+    Unknown = 18,
+}
+
+impl Error {
+    //syscalls return errors as negative values
+    pub fn code(&self) -> u32 {
+        (-(*self as i32)) as u32
+    }
+
+    //get error from a syscall result, none if it's not an error
+    pub fn from_code(code: u32) -> Option<Error> {
+        let code = code as i32;
+
+        if code >= 0 {
+            return None;
+        }
+
+        let error = match -code {
+            1 => Error::NotFound,
+            2 => Error::NotDirectory,
+            3 => Error::IsDirectory,
+            4 => Error::InvalidPath,
+            5 => Error::AlreadyExists,
+            6 => Error::ReadOnly,
+            7 => Error::NoSpace,
+            8 => Error::BadDescriptor,
+            9 => Error::TooManyFiles,
+            10 => Error::InvalidArgument,
+            11 => Error::NotPermitted,
+            12 => Error::UnexpectedEof,
+            13 => Error::NotEmpty,
+            14 => Error::Busy,
+            15 => Error::NoChild,
+            16 => Error::NoMemory,
+            17 => Error::Interrupted,
+            _ => Error::Unknown,
+        };
+
+        Some(error)
+    }
+
+    //convert syscall result to a rust result
+    pub fn check(code: u32) -> Result<u32, Error> {
+        match Error::from_code(code) {
+            Some(error) => Err(error),
+            None => Ok(code),
+        }
+    }
+}
+
+pub trait Read {
+    //read bytes to buffer, returns how many bytes have been read, zero at end of file
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error>;
+
+    //fill the whole buffer
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        let mut read = 0;
+
+        while read < buffer.len() {
+            match self.read(&mut buffer[read..])? {
+                0 => return Err(Error::UnexpectedEof),
+                n => read += n,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub trait Write {
+    //write bytes from buffer, returns how many bytes have been written
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Error>;
+
+    //write the whole buffer
+    fn write_all(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        let mut written = 0;
+
+        while written < buffer.len() {
+            match self.write(&buffer[written..])? {
+                0 => return Err(Error::NoSpace),
+                n => written += n,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,14 @@
 #![no_std]
+#![feature(naked_functions)]
 
+pub mod env;
+pub mod fs;
+pub mod io;
 pub mod mutex;
 pub mod print;
+pub mod process;
+#[cfg(feature = "rt")]
+pub mod rt;
+pub mod signal;
+pub mod syscall;
+pub mod thread;
@@ -6,19 +6,23 @@ use core::sync::atomic::{AtomicBool, Ordering};
 
     TODO: Improve it
 */
-pub struct Mutex<T> {
-    target: T,
+
+//target is the last field so that the mutex can also wrap unsized types like trait objects
+pub struct Mutex<T: ?Sized> {
     free: AtomicBool,
+    target: T,
 }
 
 impl<T> Mutex<T> {
     pub const fn new(value: T) -> Self {
         Self {
-            target: value,
             free: AtomicBool::new(true),
+            target: value,
         }
     }
+}
 
+impl<T: ?Sized> Mutex<T> {
     //WARNING: You MUST call free()  after using acquire() or acquire_mut() when the target is no longer needed. Not doing so can, and will, lead to problems.
     pub fn acquire_mut(&mut self) -> &mut T {
         while !self.free.load(Ordering::SeqCst) {} // Wait until free is true
@@ -33,12 +37,24 @@ impl<T> Mutex<T> {
         return &self.target;
     }
 
+    //acquire target only if it's free, without waiting
+    //useful in interrupt handlers, where waiting for the interrupted code would never end
+    pub fn try_acquire_mut(&mut self) -> Option<&mut T> {
+        match self
+            .free
+            .compare_exchange(true, false, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => Some(&mut self.target),
+            Err(_) => None,
+        }
+    }
+
     pub fn free(&self) {
         self.free.store(true, Ordering::SeqCst); // Set free to true
     }
 }
 
-impl<T> Drop for Mutex<T> {
+impl<T: ?Sized> Drop for Mutex<T> {
     fn drop(&mut self) {
         self.free = AtomicBool::from(true);
     }
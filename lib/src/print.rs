@@ -1,4 +1,4 @@
-use core::arch::asm;
+use crate::syscall;
 use core::fmt;
 
 pub struct Printer {}
@@ -19,7 +19,7 @@ impl Printer {
             let ptr = s.as_ptr();
             let len = s.len();
 
-            asm!("push eax", "push ebx","push ecx", "int 0x80", "pop ecx", "pop ebx", "pop eax", in("eax") 0, in("ebx") ptr as u32, in("ecx") len as u32);
+            syscall::syscall2(syscall::PRINT, ptr as u32, len as u32);
         }
     }
 }
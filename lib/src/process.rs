@@ -0,0 +1,70 @@
+//PROCESSES
+//Ending the current task, waiting for its children and starting programs with fork and exec
+
+use crate::env;
+use crate::io::Error;
+use crate::syscall;
+
+//arguments that can be passed to exec, including the program path
+pub const MAX_ARGS: usize = 16;
+
+//environment variables that can be passed to exec
+pub const MAX_ENV: usize = 16;
+
+//end current task with given exit code, never returns
+pub fn exit(code: i32) -> ! {
+    unsafe {
+        syscall::syscall1(syscall::EXIT, code as u32);
+    }
+
+    loop {}
+}
+
+//wait until child with given pid exits, zero waits for any child
+//returns pid and exit code of the child
+pub fn wait(pid: u32) -> Result<(u32, i32), Error> {
+    let mut code: i32 = 0;
+    let result = unsafe { syscall::syscall2(syscall::WAIT, pid, &mut code as *mut i32 as u32) };
+
+    Error::check(result).map(|pid| (pid, code))
+}
+
+//duplicate current process, returns pid of the copy to the caller and zero to the copy
+pub fn fork() -> Result<u32, Error> {
+    let result = unsafe { syscall::syscall0(syscall::FORK) };
+
+    Error::check(result)
+}
+
+//replace program of current process with the one at path, it gets path followed by args as arguments
+//the environment of current process is passed to the new program
+//returns only if the program can't be started
+pub fn exec(path: &str, args: &[&str]) -> Error {
+    let env_count = env::raw_vars().count();
+    if args.len() >= MAX_ARGS || env_count > MAX_ENV {
+        return Error::InvalidArgument;
+    }
+
+    //arguments are followed by environment variables
+    let mut pairs = [[0u32; 2]; MAX_ARGS + MAX_ENV];
+    pairs[0] = [path.as_ptr() as u32, path.len() as u32];
+    for (pair, arg) in pairs[1..].iter_mut().zip(args) {
+        *pair = [arg.as_ptr() as u32, arg.len() as u32];
+    }
+
+    for (pair, var) in pairs[args.len() + 1..].iter_mut().zip(env::raw_vars()) {
+        *pair = [var.as_ptr() as u32, var.len() as u32];
+    }
+
+    let count = args.len() as u32 + 1;
+    let result = unsafe {
+        syscall::syscall3(
+            syscall::EXEC,
+            pairs.as_ptr() as u32,
+            count,
+            env_count as u32,
+        )
+    };
+
+    Error::from_code(result).unwrap_or(Error::Unknown)
+}
@@ -0,0 +1,32 @@
+//RUNTIME
+//Entry point and panic handler of apps, enabled by the rt feature since the kernel has its own
+//The loader calls the entry point with argument count and array, arguments and environment are set up before main
+//An app only defines its main function, returning the exit code: #[no_mangle] fn main() -> i32
+
+use crate::env;
+use crate::process;
+use core::panic::PanicInfo;
+
+//exit code of apps ending with a panic, the same used by rust std
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+extern "Rust" {
+    fn main() -> i32;
+}
+
+#[no_mangle]
+pub extern "C" fn _start(argc: usize, argv: *const *const u8) -> ! {
+    unsafe {
+        env::init(argc, argv);
+
+        process::exit(main())
+    }
+}
+
+//a panic in any thread ends the whole process
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crate::println!("{}", info);
+
+    process::exit(PANIC_EXIT_CODE)
+}
@@ -0,0 +1,75 @@
+//SIGNALS
+//A signal interrupts a task to run the handler it has set for it, or the default action
+//Default action of CHLD is to ignore it, any other signal ends the task, KILL can't be handled nor ignored
+
+use crate::io::Error;
+use crate::syscall;
+
+pub const INT: u32 = 2;
+pub const KILL: u32 = 9;
+pub const SEGV: u32 = 11;
+pub const TERM: u32 = 15;
+pub const CHLD: u32 = 17;
+
+//signals are numbered from 1 to this
+pub const MAX_SIGNAL: u32 = 31;
+
+//handler values that are not addresses of functions
+pub const DEFAULT_HANDLER: u32 = 0;
+pub const IGNORE_HANDLER: u32 = 1;
+
+pub enum Handler {
+    Default,
+    Ignore,
+    Function(extern "C" fn(u32)),
+}
+
+//exit code of a task ended by a signal
+pub const fn exit_code(signal: u32) -> i32 {
+    128 + signal as i32
+}
+
+pub fn name(signal: u32) -> &'static str {
+    match signal {
+        INT => "INT",
+        KILL => "KILL",
+        SEGV => "SEGV",
+        TERM => "TERM",
+        CHLD => "CHLD",
+        _ => "?",
+    }
+}
+
+//get signal from its name or number
+pub fn from_name(name: &str) -> Option<u32> {
+    let signal = match name {
+        "INT" => INT,
+        "KILL" => KILL,
+        "SEGV" => SEGV,
+        "TERM" => TERM,
+        "CHLD" => CHLD,
+        _ => name.parse().ok()?,
+    };
+
+    (1..=MAX_SIGNAL).contains(&signal).then_some(signal)
+}
+
+//set what current process does when it gets given signal
+pub fn set_handler(signal: u32, handler: Handler) -> Result<(), Error> {
+    let handler = match handler {
+        Handler::Default => DEFAULT_HANDLER,
+        Handler::Ignore => IGNORE_HANDLER,
+        Handler::Function(function) => function as u32,
+    };
+
+    let result = unsafe { syscall::syscall2(syscall::SIGNAL, signal, handler) };
+
+    Error::check(result).map(|_| ())
+}
+
+//send signal to task with given pid
+pub fn kill(pid: u32, signal: u32) -> Result<(), Error> {
+    let result = unsafe { syscall::syscall2(syscall::KILL, pid, signal) };
+
+    Error::check(result).map(|_| ())
+}
@@ -0,0 +1,52 @@
+//SYSTEM CALLS
+//Syscall numbers shared by kernel and applications and raw wrappers around int 0x80
+//The syscall number goes in eax, arguments in ebx, ecx and edx, the result is returned in eax
+
+use core::arch::asm;
+
+pub const PRINT: u32 = 0;
+pub const EXIT: u32 = 1;
+pub const OPEN: u32 = 2;
+pub const READ: u32 = 3;
+pub const WRITE: u32 = 4;
+pub const SEEK: u32 = 5;
+pub const CLOSE: u32 = 6;
+pub const STAT: u32 = 7;
+pub const MKDIR: u32 = 8;
+pub const REMOVE: u32 = 9;
+pub const WAIT: u32 = 10;
+pub const THREAD_CREATE: u32 = 11;
+pub const THREAD_EXIT: u32 = 12;
+pub const THREAD_JOIN: u32 = 13;
+pub const THREAD_DETACH: u32 = 14;
+pub const SET_TLS: u32 = 15;
+pub const FORK: u32 = 16;
+pub const EXEC: u32 = 17;
+pub const SIGNAL: u32 = 18;
+pub const KILL: u32 = 19;
+pub const SIGRETURN: u32 = 20;
+
+//kernel may overwrite ecx and edx, so they are marked as clobbered
+pub unsafe fn syscall0(number: u32) -> u32 {
+    let result: u32;
+    asm!("int 0x80", inlateout("eax") number => result, out("ecx") _, out("edx") _);
+    result
+}
+
+pub unsafe fn syscall1(number: u32, a: u32) -> u32 {
+    let result: u32;
+    asm!("int 0x80", inlateout("eax") number => result, in("ebx") a, out("ecx") _, out("edx") _);
+    result
+}
+
+pub unsafe fn syscall2(number: u32, a: u32, b: u32) -> u32 {
+    let result: u32;
+    asm!("int 0x80", inlateout("eax") number => result, in("ebx") a, inlateout("ecx") b => _, out("edx") _);
+    result
+}
+
+pub unsafe fn syscall3(number: u32, a: u32, b: u32, c: u32) -> u32 {
+    let result: u32;
+    asm!("int 0x80", inlateout("eax") number => result, in("ebx") a, inlateout("ecx") b => _, inlateout("edx") c => _);
+    result
+}
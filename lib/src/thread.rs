@@ -0,0 +1,236 @@
+//THREADS
+//Threads of a process share its memory and open files, the api follows std::thread
+//There is no heap, so thread stacks come from a fixed pool and the closure is kept at the top of the new thread stack
+//Each thread has a control block pointed by its gs segment, holding its pid and a word of thread local storage
+
+use crate::io::Error;
+use crate::syscall;
+use core::arch::asm;
+use core::mem;
+use core::mem::{align_of, size_of};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+//threads that can exist at the same time, besides the main one
+pub const MAX_THREADS: usize = 4;
+
+pub const STACK_SIZE: usize = 0x2000;
+
+//stack top and packet address are kept aligned to this, as the compiler expects
+const STACK_ALIGN: usize = 16;
+
+//thread control block, found at offset 0 of the gs segment
+#[repr(C)]
+struct ControlBlock {
+    pid: u32,
+    local: u32,
+}
+
+#[repr(C, align(16))]
+struct Slot {
+    stack: [u8; STACK_SIZE],
+    control: ControlBlock,
+
+    //the thread and its join handle, slot is free when none of them is left
+    //the kernel decrements it when the thread exits, once the thread does not use its stack anymore
+    users: AtomicU32,
+}
+
+const EMPTY_SLOT: Slot = Slot {
+    stack: [0; STACK_SIZE],
+    control: ControlBlock { pid: 0, local: 0 },
+    users: AtomicU32::new(0),
+};
+
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+static mut SLOTS: [Slot; MAX_THREADS] = [EMPTY_SLOT; MAX_THREADS];
+
+//main thread gets its control block the first time it's needed
+//Warning! Mutable static here
+//TODO: Implement a mutex to get safe access to this
+static mut MAIN: ControlBlock = ControlBlock { pid: 0, local: 0 };
+static MAIN_READY: AtomicBool = AtomicBool::new(false);
+
+//closure to run and its result, at the top of the thread stack
+struct Packet<F, T> {
+    f: Option<F>,
+    result: Option<T>,
+    users: *const AtomicU32,
+}
+
+pub struct JoinHandle<T> {
+    pid: u32,
+    result: *mut Option<T>,
+    users: *const AtomicU32,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    //wait until thread ends and return what its closure returned
+    pub fn join(self) -> Result<T, Error> {
+        let (pid, result, users) = (self.pid, self.result, self.users);
+        mem::forget(self);
+
+        let code = unsafe { syscall::syscall2(syscall::THREAD_JOIN, pid, 0) };
+        Error::check(code)?;
+
+        //the thread has ended, so its stack can be read and given back
+        unsafe {
+            let value = (*result).take();
+            (*users).fetch_sub(1, Ordering::SeqCst);
+
+            value.ok_or(Error::Unknown)
+        }
+    }
+}
+
+//thread keeps running on its own, its slot is freed when it exits
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        unsafe {
+            syscall::syscall1(syscall::THREAD_DETACH, self.pid);
+            (*self.users).fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+//run closure in a new thread of current process
+//unlike std, fails instead of panicking when there is no free slot or the kernel can't create the thread
+pub fn spawn<F, T>(f: F) -> Result<JoinHandle<T>, Error>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    init_main();
+
+    let packet_size = size_of::<Packet<F, T>>();
+    if align_of::<Packet<F, T>>() > STACK_ALIGN || packet_size + STACK_ALIGN * 4 > STACK_SIZE {
+        return Err(Error::InvalidArgument);
+    }
+
+    unsafe {
+        let slot = SLOTS
+            .iter_mut()
+            .find(|slot| {
+                slot.users
+                    .compare_exchange(0, 2, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            })
+            .ok_or(Error::Busy)?;
+
+        let users = &slot.users as *const AtomicU32;
+        slot.control = ControlBlock { pid: 0, local: 0 };
+
+        //packet goes at the top of the stack
+        let top = slot.stack.as_mut_ptr() as usize + STACK_SIZE;
+        let packet = ((top - packet_size) & !(STACK_ALIGN - 1)) as *mut Packet<F, T>;
+        packet.write(Packet {
+            f: Some(f),
+            result: None,
+            users,
+        });
+
+        //entry pops the function to call, which then finds the packet as its argument
+        let argument = (packet as usize - STACK_ALIGN) as *mut u32;
+        argument.write(packet as u32);
+        let stack = argument.sub(1);
+        stack.write(thread_main::<F, T> as u32);
+
+        let tls = &slot.control as *const ControlBlock as u32;
+        let result = syscall::syscall3(
+            syscall::THREAD_CREATE,
+            thread_entry as u32,
+            stack as u32,
+            tls,
+        );
+
+        match Error::check(result) {
+            Ok(pid) => Ok(JoinHandle {
+                pid,
+                result: &mut (*packet).result,
+                users,
+            }),
+            Err(error) => {
+                //thread has not been created, closure is dropped here
+                packet.drop_in_place();
+                slot.users.store(0, Ordering::SeqCst);
+                Err(error)
+            }
+        }
+    }
+}
+
+//give main thread its control block, before any other thread exists
+fn init_main() {
+    if MAIN_READY.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    unsafe {
+        let tls = &MAIN as *const ControlBlock as u32;
+        MAIN.pid = syscall::syscall1(syscall::SET_TLS, tls);
+    }
+}
+
+//pid of current thread
+pub fn current_id() -> u32 {
+    init_main();
+
+    let pid: u32;
+    unsafe {
+        asm!("mov {0:e}, gs:[0]", out(reg) pid);
+    }
+    pid
+}
+
+//word of thread local storage, every thread has its own
+pub fn local() -> u32 {
+    init_main();
+
+    let value: u32;
+    unsafe {
+        asm!("mov {0:e}, gs:[4]", out(reg) value);
+    }
+    value
+}
+
+pub fn set_local(value: u32) {
+    init_main();
+
+    unsafe {
+        asm!("mov gs:[4], {0:e}", in(reg) value);
+    }
+}
+
+//thread starts here with its pid in eax and the function to call on top of stack
+#[naked]
+extern "C" fn thread_entry() {
+    unsafe {
+        asm!(
+            "mov gs:[0], eax",
+            "pop eax",
+            "call eax",
+            "ud2",
+            options(noreturn)
+        );
+    }
+}
+
+extern "C" fn thread_main<F, T>(packet: *mut Packet<F, T>) -> !
+where
+    F: FnOnce() -> T,
+{
+    unsafe {
+        if let Some(f) = (*packet).f.take() {
+            (*packet).result = Some(f());
+        }
+
+        //kernel releases the slot after leaving this stack
+        syscall::syscall2(syscall::THREAD_EXIT, 0, (*packet).users as u32);
+    }
+
+    loop {}
+}